@@ -0,0 +1,228 @@
+//! Views of the `class` and `data-*` attributes, like `classList` and `dataset` in the DOM.
+
+use crate::tree::ElementData;
+
+impl ElementData {
+    /// Return a view of the tokens of the `class` attribute.
+    #[inline]
+    pub fn class_list(&self) -> ClassList<'_> {
+        ClassList { element: self }
+    }
+
+    /// Return a view of the `data-*` attributes, keyed by camel-cased names.
+    #[inline]
+    pub fn dataset(&self) -> Dataset<'_> {
+        Dataset { element: self }
+    }
+}
+
+/// The tokens of a `class` attribute, separated by ASCII whitespace.
+///
+/// Changes are written back to the attribute immediately,
+/// without duplicate tokens and with single spaces between tokens.
+/// Methods that take a token fail if it is empty or contains ASCII whitespace.
+#[derive(Debug, Clone, Copy)]
+pub struct ClassList<'a> {
+    element: &'a ElementData,
+}
+
+fn check_token(token: &str) -> Result<(), ()> {
+    if token.is_empty() || token.contains(|c: char| c.is_ascii_whitespace()) {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Invalid tokens are `Err(())`. The DOM throws a SyntaxError or InvalidCharacterError,
+// neither of which carries more information.
+#[allow(clippy::result_unit_err)]
+impl<'a> ClassList<'a> {
+    /// Return the tokens, in order and without duplicates.
+    pub fn tokens(&self) -> Vec<String> {
+        let mut tokens: Vec<String> = Vec::new();
+        let attributes = self.element.attributes.borrow();
+        for token in attributes
+            .get(local_name!("class"))
+            .unwrap_or("")
+            .split_ascii_whitespace()
+        {
+            if !tokens.iter().any(|t| t == token) {
+                tokens.push(token.to_owned())
+            }
+        }
+        tokens
+    }
+
+    /// The number of distinct tokens.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tokens().len()
+    }
+
+    /// Whether there is no token.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tokens().is_empty()
+    }
+
+    /// Return whether the given class is present. This is case-sensitive.
+    pub fn contains(&self, token: &str) -> bool {
+        let attributes = self.element.attributes.borrow();
+        attributes
+            .get(local_name!("class"))
+            .is_some_and(|class| class.split_ascii_whitespace().any(|t| t == token))
+    }
+
+    /// Add a class, if not already present.
+    pub fn add(&self, token: &str) -> Result<(), ()> {
+        check_token(token)?;
+        let mut tokens = self.tokens();
+        if !tokens.iter().any(|t| t == token) {
+            tokens.push(token.to_owned());
+        }
+        self.write_back(tokens);
+        Ok(())
+    }
+
+    /// Remove a class, if present.
+    pub fn remove(&self, token: &str) -> Result<(), ()> {
+        check_token(token)?;
+        let mut tokens = self.tokens();
+        tokens.retain(|t| t != token);
+        self.write_back(tokens);
+        Ok(())
+    }
+
+    /// Remove a class if present, add it otherwise. Return whether it is now present.
+    pub fn toggle(&self, token: &str) -> Result<bool, ()> {
+        if self.contains(token) {
+            self.remove(token)?;
+            Ok(false)
+        } else {
+            self.add(token)?;
+            Ok(true)
+        }
+    }
+
+    /// Replace a class with another, in place. Return whether the old class was present.
+    pub fn replace(&self, old: &str, new: &str) -> Result<bool, ()> {
+        check_token(old)?;
+        check_token(new)?;
+        let mut tokens = self.tokens();
+        let position = match tokens.iter().position(|t| t == old) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+        if tokens.iter().any(|t| t == new) {
+            tokens.remove(position);
+        } else {
+            tokens[position] = new.to_owned();
+        }
+        self.write_back(tokens);
+        Ok(true)
+    }
+
+    fn write_back(&self, tokens: Vec<String>) {
+        let mut attributes = self.element.attributes.borrow_mut();
+        // Like the DOM, do not create an empty attribute.
+        if tokens.is_empty() && !attributes.contains(local_name!("class")) {
+            return;
+        }
+        attributes.insert(local_name!("class"), tokens.join(" "));
+    }
+}
+
+/// The `data-*` attributes of an element.
+///
+/// Keys are camel-cased: `data-foo-bar` has the key `fooBar`.
+/// Methods that take a key fail if it contains `-` followed by an ASCII lower case letter,
+/// as such a key has no corresponding attribute.
+#[derive(Debug, Clone, Copy)]
+pub struct Dataset<'a> {
+    element: &'a ElementData,
+}
+
+/// <https://html.spec.whatwg.org/multipage/#dom-domstringmap-setitem>
+fn key_to_attribute_name(key: &str) -> Result<String, ()> {
+    let mut name = String::from("data-");
+    let mut chars = key.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '-' && chars.peek().is_some_and(char::is_ascii_lowercase) {
+            return Err(());
+        }
+        if c.is_ascii_uppercase() {
+            name.push('-');
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    Ok(name)
+}
+
+/// <https://html.spec.whatwg.org/multipage/#concept-domstringmap-pairs>
+fn attribute_name_to_key(name: &str) -> Option<String> {
+    let rest = name.strip_prefix("data-")?;
+    if name.contains(|c: char| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let mut key = String::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '-' && next.is_ascii_lowercase() => {
+                key.push(next.to_ascii_uppercase());
+                chars.next();
+            }
+            _ => key.push(c),
+        }
+    }
+    Some(key)
+}
+
+// Invalid keys are `Err(())`, where the DOM throws a SyntaxError.
+#[allow(clippy::result_unit_err)]
+impl<'a> Dataset<'a> {
+    /// Return the keys and values, in attribute order.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let attributes = self.element.attributes.borrow();
+        attributes
+            .map
+            .iter()
+            .filter(|(name, _)| name.ns == ns!())
+            .filter_map(|(name, attr)| {
+                Some((attribute_name_to_key(&name.local)?, attr.value.clone()))
+            })
+            .collect()
+    }
+
+    /// Return the value for a key, if the attribute is present.
+    pub fn get(&self, key: &str) -> Result<Option<String>, ()> {
+        let name = key_to_attribute_name(key)?;
+        let attributes = self.element.attributes.borrow();
+        Ok(attributes.get(name).map(str::to_owned))
+    }
+
+    /// Return whether the attribute for a key is present.
+    pub fn contains(&self, key: &str) -> Result<bool, ()> {
+        let name = key_to_attribute_name(key)?;
+        Ok(self.element.attributes.borrow().contains(name))
+    }
+
+    /// Set the value for a key, returning the previous one.
+    pub fn set(&self, key: &str, value: &str) -> Result<Option<String>, ()> {
+        let name = key_to_attribute_name(key)?;
+        let mut attributes = self.element.attributes.borrow_mut();
+        Ok(attributes
+            .insert(name, value.to_owned())
+            .map(|attr| attr.value))
+    }
+
+    /// Remove the attribute for a key, returning its value.
+    pub fn remove(&self, key: &str) -> Result<Option<String>, ()> {
+        let name = key_to_attribute_name(key)?;
+        let mut attributes = self.element.attributes.borrow_mut();
+        Ok(attributes.remove(name).map(|attr| attr.value))
+    }
+}
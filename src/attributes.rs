@@ -1,4 +1,4 @@
-use html5ever::{LocalName, Namespace, Prefix};
+use html5ever::{LocalName, Namespace, Prefix, QualName};
 use indexmap::{map::Entry, IndexMap};
 
 /// Convenience wrapper around a indexmap that adds method for attributes in the null namespace.
@@ -57,7 +57,10 @@ impl Attributes {
     }
 
     /// Like IndexMap::entry
-    pub fn entry<A: Into<LocalName>>(&mut self, local_name: A) -> Entry<ExpandedName, Attribute> {
+    pub fn entry<A: Into<LocalName>>(
+        &mut self,
+        local_name: A,
+    ) -> Entry<'_, ExpandedName, Attribute> {
         self.map.entry(ExpandedName::new(ns!(), local_name))
     }
 
@@ -80,4 +83,57 @@ impl Attributes {
     pub fn remove<A: Into<LocalName>>(&mut self, local_name: A) -> Option<Attribute> {
         self.map.remove(&ExpandedName::new(ns!(), local_name))
     }
+
+    /// Like IndexMap::contains, for an attribute in the given namespace
+    pub fn contains_ns<N: Into<Namespace>, A: Into<LocalName>>(
+        &self,
+        ns: N,
+        local_name: A,
+    ) -> bool {
+        self.map.contains_key(&ExpandedName::new(ns, local_name))
+    }
+
+    /// Like IndexMap::get, for an attribute in the given namespace
+    pub fn get_ns<N: Into<Namespace>, A: Into<LocalName>>(
+        &self,
+        ns: N,
+        local_name: A,
+    ) -> Option<&str> {
+        self.map
+            .get(&ExpandedName::new(ns, local_name))
+            .map(|attr| &*attr.value)
+    }
+
+    /// Like IndexMap::insert, for an attribute in the given namespace
+    pub fn insert_ns<N: Into<Namespace>, A: Into<LocalName>>(
+        &mut self,
+        ns: N,
+        prefix: Option<Prefix>,
+        local_name: A,
+        value: String,
+    ) -> Option<Attribute> {
+        self.map.insert(
+            ExpandedName::new(ns, local_name),
+            Attribute { prefix, value },
+        )
+    }
+
+    /// Like IndexMap::remove, for an attribute in the given namespace
+    pub fn remove_ns<N: Into<Namespace>, A: Into<LocalName>>(
+        &mut self,
+        ns: N,
+        local_name: A,
+    ) -> Option<Attribute> {
+        self.map.remove(&ExpandedName::new(ns, local_name))
+    }
+
+    /// Iterate over the attributes in order, with their full qualified names.
+    pub fn iter_qualified(&self) -> impl Iterator<Item = (QualName, &str)> {
+        self.map.iter().map(|(name, attr)| {
+            (
+                QualName::new(attr.prefix.clone(), name.ns.clone(), name.local.clone()),
+                &*attr.value,
+            )
+        })
+    }
 }
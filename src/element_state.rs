@@ -0,0 +1,577 @@
+//! Element states from the HTML specification, as used by pseudo-classes.
+//!
+//! <https://html.spec.whatwg.org/multipage/#pseudo-classes>
+//!
+//! Kuchiki has no script or user interaction,
+//! so dynamic states (such as a checkbox’s checkedness) are their default values
+//! as determined by content attributes.
+
+use html5ever::LocalName;
+
+use crate::html_elements::html_name;
+use crate::iter::NodeIterator;
+use crate::node_data_ref::NodeDataRef;
+use crate::tree::{ElementData, NodeRef};
+
+fn has_attribute(element: &ElementData, name: LocalName) -> bool {
+    element.attributes.borrow().contains(name)
+}
+
+/// The state of the `type` attribute of an `input` element, lower-cased.
+fn input_type(element: &ElementData) -> String {
+    let attributes = element.attributes.borrow();
+    let type_ = attributes.get(local_name!("type")).unwrap_or("text");
+    let type_ = type_.to_ascii_lowercase();
+    match &*type_ {
+        "hidden" | "text" | "search" | "tel" | "url" | "email" | "password" | "date" | "month"
+        | "week" | "time" | "datetime-local" | "number" | "range" | "color" | "checkbox"
+        | "radio" | "file" | "submit" | "image" | "reset" | "button" => type_,
+        _ => "text".to_owned(),
+    }
+}
+
+fn is_input_of_type(element: &ElementData, types: &[&str]) -> bool {
+    html_name(element) == Some(&local_name!("input")) && types.contains(&&*input_type(element))
+}
+
+fn ancestor_elements(node: &NodeRef) -> impl Iterator<Item = NodeDataRef<ElementData>> {
+    node.ancestors().elements()
+}
+
+/// <https://html.spec.whatwg.org/multipage/#concept-fe-disabled>
+fn is_actually_disabled(element: &NodeDataRef<ElementData>) -> bool {
+    match html_name(element) {
+        Some(&local_name!("button"))
+        | Some(&local_name!("input"))
+        | Some(&local_name!("select"))
+        | Some(&local_name!("textarea"))
+        | Some(&local_name!("fieldset")) => {
+            has_attribute(element, local_name!("disabled"))
+                || is_in_disabled_fieldset(element.as_node())
+        }
+        Some(&local_name!("optgroup")) => has_attribute(element, local_name!("disabled")),
+        Some(&local_name!("option")) => {
+            has_attribute(element, local_name!("disabled"))
+                || element
+                    .as_node()
+                    .parent()
+                    .and_then(NodeRef::into_element_ref)
+                    .is_some_and(|parent| {
+                        html_name(&parent) == Some(&local_name!("optgroup"))
+                            && has_attribute(&parent, local_name!("disabled"))
+                    })
+        }
+        _ => false,
+    }
+}
+
+/// Whether the node is a descendant of a disabled `fieldset`,
+/// but not of that fieldset’s first `legend` child.
+fn is_in_disabled_fieldset(node: &NodeRef) -> bool {
+    let mut child = node.clone();
+    for ancestor in node.ancestors() {
+        if let Some(element) = ancestor.as_element() {
+            if html_name(element) == Some(&local_name!("fieldset"))
+                && has_attribute(element, local_name!("disabled"))
+            {
+                let first_legend = ancestor
+                    .children()
+                    .elements()
+                    .find(|e| html_name(e) == Some(&local_name!("legend")));
+                if first_legend.map_or(true, |legend| *legend.as_node() != child) {
+                    return true;
+                }
+            }
+        }
+        child = ancestor;
+    }
+    false
+}
+
+/// `:enabled` and `:disabled` only apply to these elements.
+fn can_be_disabled(element: &ElementData) -> bool {
+    matches!(
+        html_name(element),
+        Some(&local_name!("button"))
+            | Some(&local_name!("input"))
+            | Some(&local_name!("select"))
+            | Some(&local_name!("textarea"))
+            | Some(&local_name!("optgroup"))
+            | Some(&local_name!("option"))
+            | Some(&local_name!("fieldset"))
+    )
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-enabled>
+pub fn is_enabled(element: &NodeDataRef<ElementData>) -> bool {
+    can_be_disabled(element) && !is_actually_disabled(element)
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-disabled>
+pub fn is_disabled(element: &NodeDataRef<ElementData>) -> bool {
+    can_be_disabled(element) && is_actually_disabled(element)
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-checked>
+pub fn is_checked(element: &NodeDataRef<ElementData>) -> bool {
+    if is_input_of_type(element, &["checkbox", "radio"]) {
+        has_attribute(element, local_name!("checked"))
+    } else if html_name(element) == Some(&local_name!("option")) {
+        is_selected_option(element)
+    } else {
+        false
+    }
+}
+
+/// The selectedness of an `option` with no script,
+/// per the `select` element’s selectedness setting algorithm.
+fn is_selected_option(option: &NodeDataRef<ElementData>) -> bool {
+    if has_attribute(option, local_name!("selected")) {
+        return true;
+    }
+    let select = match owner_select(option) {
+        Some(select) => select,
+        None => return false,
+    };
+    let display_size = select
+        .attributes
+        .borrow()
+        .get(local_name!("size"))
+        .and_then(|size| size.trim().parse::<u32>().ok())
+        .unwrap_or(1);
+    if has_attribute(&select, local_name!("multiple")) || display_size > 1 {
+        return false;
+    }
+    // With no explicitly selected option, a drop-down selects its first non-disabled option.
+    let mut options = select_options(&select);
+    if options
+        .iter()
+        .any(|o| has_attribute(o, local_name!("selected")))
+    {
+        return false;
+    }
+    options.retain(|o| !is_actually_disabled(o));
+    options.first() == Some(option)
+}
+
+/// The `select` element whose list of options contains the given `option`.
+fn owner_select(option: &NodeDataRef<ElementData>) -> Option<NodeDataRef<ElementData>> {
+    let parent = option.as_node().parent()?.into_element_ref()?;
+    match html_name(&parent) {
+        Some(&local_name!("select")) => Some(parent),
+        Some(&local_name!("optgroup")) => {
+            let grandparent = parent.as_node().parent()?.into_element_ref()?;
+            if html_name(&grandparent) == Some(&local_name!("select")) {
+                Some(grandparent)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#concept-select-option-list>
+fn select_options(select: &NodeDataRef<ElementData>) -> Vec<NodeDataRef<ElementData>> {
+    let is_option = |e: &NodeDataRef<ElementData>| html_name(e) == Some(&local_name!("option"));
+    let mut options = Vec::new();
+    for child in select.as_node().children().elements() {
+        if is_option(&child) {
+            options.push(child)
+        } else if html_name(&child) == Some(&local_name!("optgroup")) {
+            options.extend(child.as_node().children().elements().filter(is_option))
+        }
+    }
+    options
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-indeterminate>
+pub fn is_indeterminate(element: &NodeDataRef<ElementData>) -> bool {
+    if is_input_of_type(element, &["radio"]) {
+        !radio_group(element)
+            .iter()
+            .any(|radio| has_attribute(radio, local_name!("checked")))
+    } else if html_name(element) == Some(&local_name!("progress")) {
+        !has_attribute(element, local_name!("value"))
+    } else {
+        // A checkbox’s indeterminate flag can only be set from script.
+        false
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#radio-button-group>
+///
+/// The form owner is approximated by the nearest `form` ancestor.
+fn radio_group(radio: &NodeDataRef<ElementData>) -> Vec<NodeDataRef<ElementData>> {
+    let name = match radio.attributes.borrow().get(local_name!("name")) {
+        Some(name) if !name.is_empty() => name.to_owned(),
+        _ => return vec![radio.clone()],
+    };
+    let form = form_owner(radio.as_node());
+    let root = match form {
+        Some(ref form) => form.as_node().clone(),
+        None => tree_root(radio.as_node()),
+    };
+    root.descendants()
+        .elements()
+        .filter(|e| {
+            is_input_of_type(e, &["radio"])
+                && e.attributes.borrow().get(local_name!("name")) == Some(&*name)
+                && form_owner(e.as_node()) == form
+        })
+        .collect()
+}
+
+fn form_owner(node: &NodeRef) -> Option<NodeDataRef<ElementData>> {
+    ancestor_elements(node).find(|e| html_name(e) == Some(&local_name!("form")))
+}
+
+fn tree_root(node: &NodeRef) -> NodeRef {
+    node.inclusive_ancestors().last().unwrap()
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-default>
+pub fn is_default(element: &NodeDataRef<ElementData>) -> bool {
+    if is_submit_button(element) {
+        // The default button is the first submit button in tree order of its form owner.
+        match form_owner(element.as_node()) {
+            Some(form) => form
+                .as_node()
+                .descendants()
+                .elements()
+                .find(|e| is_submit_button(e) && form_owner(e.as_node()).as_ref() == Some(&form))
+                .is_some_and(|first| first == *element),
+            None => false,
+        }
+    } else if is_input_of_type(element, &["checkbox", "radio"]) {
+        has_attribute(element, local_name!("checked"))
+    } else if html_name(element) == Some(&local_name!("option")) {
+        has_attribute(element, local_name!("selected"))
+    } else {
+        false
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#concept-submit-button>
+fn is_submit_button(element: &ElementData) -> bool {
+    match html_name(element) {
+        Some(&local_name!("input")) => matches!(&*input_type(element), "submit" | "image"),
+        Some(&local_name!("button")) => {
+            let attributes = element.attributes.borrow();
+            match attributes.get(local_name!("type")) {
+                Some(type_) => {
+                    type_.eq_ignore_ascii_case("submit")
+                        || !(type_.eq_ignore_ascii_case("reset")
+                            || type_.eq_ignore_ascii_case("button"))
+                }
+                None => true,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Input types to which the `required` attribute applies.
+const REQUIRED_APPLIES: &[&str] = &[
+    "text",
+    "search",
+    "url",
+    "tel",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+    "checkbox",
+    "radio",
+    "file",
+];
+
+/// Input types to which the `readonly` attribute applies.
+const READONLY_APPLIES: &[&str] = &[
+    "text",
+    "search",
+    "url",
+    "tel",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+];
+
+/// Input types to which the `placeholder` attribute applies.
+const PLACEHOLDER_APPLIES: &[&str] = &[
+    "text", "search", "url", "tel", "email", "password", "number",
+];
+
+/// `:required` and `:optional` only apply to these elements.
+fn can_be_required(element: &ElementData) -> bool {
+    matches!(
+        html_name(element),
+        Some(&local_name!("input")) | Some(&local_name!("select")) | Some(&local_name!("textarea"))
+    )
+}
+
+fn is_required_element(element: &ElementData) -> bool {
+    has_attribute(element, local_name!("required"))
+        && match html_name(element) {
+            Some(&local_name!("input")) => REQUIRED_APPLIES.contains(&&*input_type(element)),
+            _ => true,
+        }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-required>
+pub fn is_required(element: &NodeDataRef<ElementData>) -> bool {
+    can_be_required(element) && is_required_element(element)
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-optional>
+pub fn is_optional(element: &NodeDataRef<ElementData>) -> bool {
+    can_be_required(element) && !is_required_element(element)
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-read-write>
+pub fn is_read_write(element: &NodeDataRef<ElementData>) -> bool {
+    let mutable =
+        || !has_attribute(element, local_name!("readonly")) && !is_actually_disabled(element);
+    match html_name(element) {
+        Some(&local_name!("input")) => {
+            READONLY_APPLIES.contains(&&*input_type(element)) && mutable()
+        }
+        Some(&local_name!("textarea")) => mutable(),
+        Some(_) => is_editable(element.as_node()),
+        None => false,
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-read-only>
+pub fn is_read_only(element: &NodeDataRef<ElementData>) -> bool {
+    html_name(element).is_some() && !is_read_write(element)
+}
+
+/// Whether the element is an editing host or editable,
+/// per the inherited state of `contenteditable` attributes.
+fn is_editable(node: &NodeRef) -> bool {
+    for element in node.inclusive_ancestors().elements() {
+        if html_name(&element).is_none() {
+            continue;
+        }
+        let attributes = element.attributes.borrow();
+        if let Some(value) = attributes.get(local_name!("contenteditable")) {
+            let value = value.to_ascii_lowercase();
+            match &*value {
+                "" | "true" | "plaintext-only" => return true,
+                "false" => return false,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-placeholder-shown>
+pub fn is_placeholder_shown(element: &NodeDataRef<ElementData>) -> bool {
+    if !has_attribute(element, local_name!("placeholder")) {
+        return false;
+    }
+    match html_name(element) {
+        Some(&local_name!("input")) => {
+            PLACEHOLDER_APPLIES.contains(&&*input_type(element))
+                && element
+                    .attributes
+                    .borrow()
+                    .get(local_name!("value"))
+                    .map_or(true, str::is_empty)
+        }
+        Some(&local_name!("textarea")) => element.text_contents().is_empty(),
+        _ => false,
+    }
+}
+
+/// The language of an element, from the nearest `xml:lang` or `lang` attribute.
+///
+/// <https://html.spec.whatwg.org/multipage/#language>
+pub fn language(node: &NodeRef) -> Option<String> {
+    let xml_lang = crate::attributes::ExpandedName::new(ns!(xml), "lang");
+    for element in node.inclusive_ancestors().elements() {
+        let attributes = element.attributes.borrow();
+        if let Some(attr) = attributes.map.get(&xml_lang) {
+            return Some(attr.value.clone());
+        }
+        if element.name.ns == ns!(html) || element.name.ns == ns!() {
+            if let Some(lang) = attributes.get(local_name!("lang")) {
+                return Some(lang.to_owned());
+            }
+        }
+    }
+    None
+}
+
+/// <https://drafts.csswg.org/selectors-4/#the-lang-pseudo>
+///
+/// A range matches a language if it is equal, or a prefix followed by `-`, ignoring ASCII case.
+/// The range `*` matches any language.
+pub fn matches_lang(element: &NodeDataRef<ElementData>, ranges: &[String]) -> bool {
+    let lang = match language(element.as_node()) {
+        Some(lang) => lang,
+        None => return false,
+    };
+    ranges.iter().any(|range| language_matches(&lang, range))
+}
+
+/// Whether a language tag matches a range, as described above.
+pub fn language_matches(lang: &str, range: &str) -> bool {
+    if range == "*" {
+        return !lang.is_empty();
+    }
+    if range.is_empty() {
+        return lang.is_empty();
+    }
+    lang.len() >= range.len()
+        && lang.as_bytes()[..range.len()].eq_ignore_ascii_case(range.as_bytes())
+        && matches!(lang.as_bytes().get(range.len()), None | Some(b'-'))
+}
+
+/// A text direction, for the `:dir()` pseudo-class.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+/// <https://html.spec.whatwg.org/multipage/#the-directionality>
+pub fn directionality(element: &NodeDataRef<ElementData>) -> Direction {
+    let dir = element
+        .attributes
+        .borrow()
+        .get(local_name!("dir"))
+        .map(str::to_ascii_lowercase);
+    match dir.as_deref() {
+        Some("ltr") => return Direction::Ltr,
+        Some("rtl") => return Direction::Rtl,
+        Some("auto") => return auto_directionality(element).unwrap_or(Direction::Ltr),
+        _ => {}
+    }
+    if html_name(element) == Some(&local_name!("bdi")) {
+        return auto_directionality(element).unwrap_or(Direction::Ltr);
+    }
+    match element
+        .as_node()
+        .parent()
+        .and_then(NodeRef::into_element_ref)
+    {
+        Some(parent) => directionality(&parent),
+        None => Direction::Ltr,
+    }
+}
+
+/// The direction of the first character with a strong direction in the element’s text,
+/// skipping descendants that have their own directionality.
+fn auto_directionality(element: &NodeDataRef<ElementData>) -> Option<Direction> {
+    if is_input_of_type(element, &["text", "search", "url", "tel", "email"]) {
+        let attributes = element.attributes.borrow();
+        return first_strong_direction(attributes.get(local_name!("value")).unwrap_or(""));
+    }
+    if html_name(element) == Some(&local_name!("textarea")) {
+        return first_strong_direction(&element.text_contents());
+    }
+    fn search(node: &NodeRef) -> Option<Direction> {
+        for child in node.children() {
+            if let Some(text) = child.as_text() {
+                if let Some(direction) = first_strong_direction(&text.borrow()) {
+                    return Some(direction);
+                }
+            } else if let Some(element) = child.as_element() {
+                let skip = matches!(
+                    html_name(element),
+                    Some(&local_name!("bdi"))
+                        | Some(&local_name!("script"))
+                        | Some(&local_name!("style"))
+                        | Some(&local_name!("textarea"))
+                ) || has_attribute(element, local_name!("dir"));
+                if !skip {
+                    if let Some(direction) = search(&child) {
+                        return Some(direction);
+                    }
+                }
+            }
+        }
+        None
+    }
+    search(element.as_node())
+}
+
+/// An approximation of the bidirectional character types L, R and AL.
+fn first_strong_direction(text: &str) -> Option<Direction> {
+    text.chars().find_map(|c| {
+        let is_rtl = matches!(
+            c as u32,
+            0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF
+        );
+        if is_rtl && c.is_alphabetic() {
+            Some(Direction::Rtl)
+        } else if c.is_alphabetic() {
+            Some(Direction::Ltr)
+        } else {
+            None
+        }
+    })
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-target>
+///
+/// The target is the element whose ID is the fragment,
+/// or else an `a` element whose `name` is the fragment.
+pub fn is_target(element: &NodeDataRef<ElementData>, fragment: &str) -> bool {
+    if fragment.is_empty() {
+        return false;
+    }
+    let attributes = element.attributes.borrow();
+    if attributes.get(local_name!("id")) == Some(fragment) {
+        return true;
+    }
+    html_name(element) == Some(&local_name!("a"))
+        && attributes.get(local_name!("name")) == Some(fragment)
+        && !tree_root(element.as_node())
+            .descendants()
+            .elements()
+            .any(|e| e.attributes.borrow().get(local_name!("id")) == Some(fragment))
+}
+
+/// <https://html.spec.whatwg.org/multipage/#selector-defined>
+///
+/// There is no custom element registry, so autonomous and customized built-in custom elements
+/// are never defined.
+pub fn is_defined(element: &NodeDataRef<ElementData>) -> bool {
+    match html_name(element) {
+        Some(name) => {
+            !is_valid_custom_element_name(name) && !has_attribute(element, LocalName::from("is"))
+        }
+        None => true,
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#valid-custom-element-name>
+fn is_valid_custom_element_name(name: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "annotation-xml",
+        "color-profile",
+        "font-face",
+        "font-face-src",
+        "font-face-uri",
+        "font-face-format",
+        "font-face-name",
+        "missing-glyph",
+    ];
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.contains('-')
+        && !name.chars().any(|c| c.is_ascii_uppercase())
+        && !RESERVED.contains(&name)
+}
@@ -0,0 +1,184 @@
+//! Categories of HTML elements and whitespace handling,
+//! as used when serializing or rendering a tree.
+
+use html5ever::{LocalName, QualName};
+
+use crate::tree::ElementData;
+
+/// The element’s local name, if it is an HTML element.
+pub fn html_name(element: &ElementData) -> Option<&LocalName> {
+    if element.name.ns == ns!(html) {
+        Some(&element.name.local)
+    } else {
+        None
+    }
+}
+
+/// Whether this is an HTML element whose contents are never rendered,
+/// such as `<head>` or `<script>`, or only rendered without scripting (`<noscript>`)
+/// or in another browsing context (`<iframe>`).
+pub fn is_not_rendered(name: &QualName) -> bool {
+    name.ns == ns!(html)
+        && matches!(
+            name.local,
+            local_name!("head")
+                | local_name!("iframe")
+                | local_name!("noscript")
+                | local_name!("script")
+                | local_name!("style")
+                | local_name!("template")
+                | local_name!("title")
+        )
+}
+
+/// Whether this is an HTML element that does not take part in inline formatting:
+/// a block-level element by default, or an element that is not rendered at all.
+///
+/// Whitespace between such elements does not affect rendering.
+pub fn is_block(name: &QualName) -> bool {
+    name.ns == ns!(html)
+        && matches!(
+            name.local,
+            local_name!("address")
+                | local_name!("article")
+                | local_name!("aside")
+                | local_name!("base")
+                | local_name!("blockquote")
+                | local_name!("body")
+                | local_name!("caption")
+                | local_name!("center")
+                | local_name!("col")
+                | local_name!("colgroup")
+                | local_name!("dd")
+                | local_name!("details")
+                | local_name!("dialog")
+                | local_name!("dir")
+                | local_name!("div")
+                | local_name!("dl")
+                | local_name!("dt")
+                | local_name!("fieldset")
+                | local_name!("figcaption")
+                | local_name!("figure")
+                | local_name!("footer")
+                | local_name!("form")
+                | local_name!("frameset")
+                | local_name!("h1")
+                | local_name!("h2")
+                | local_name!("h3")
+                | local_name!("h4")
+                | local_name!("h5")
+                | local_name!("h6")
+                | local_name!("head")
+                | local_name!("header")
+                | local_name!("hgroup")
+                | local_name!("hr")
+                | local_name!("html")
+                | local_name!("legend")
+                | local_name!("li")
+                | local_name!("link")
+                | local_name!("listing")
+                | local_name!("main")
+                | local_name!("menu")
+                | local_name!("meta")
+                | local_name!("nav")
+                | local_name!("ol")
+                | local_name!("optgroup")
+                | local_name!("option")
+                | local_name!("p")
+                | local_name!("plaintext")
+                | local_name!("pre")
+                | local_name!("script")
+                | local_name!("section")
+                | local_name!("style")
+                | local_name!("summary")
+                | local_name!("table")
+                | local_name!("tbody")
+                | local_name!("td")
+                | local_name!("template")
+                | local_name!("tfoot")
+                | local_name!("th")
+                | local_name!("thead")
+                | local_name!("title")
+                | local_name!("tr")
+                | local_name!("ul")
+                | local_name!("xmp")
+        )
+}
+
+/// Whether whitespace in the contents of this element is significant,
+/// either because it is preformatted or because the contents are raw text.
+pub fn is_whitespace_sensitive(name: &QualName) -> bool {
+    name.ns == ns!(html)
+        && matches!(
+            name.local,
+            local_name!("listing")
+                | local_name!("plaintext")
+                | local_name!("pre")
+                | local_name!("script")
+                | local_name!("style")
+                | local_name!("textarea")
+                | local_name!("xmp")
+        )
+}
+
+/// Whether this is an HTML void element, which has a start tag but no contents or end tag.
+pub fn is_void(name: &QualName) -> bool {
+    name.ns == ns!(html)
+        && matches!(
+            name.local,
+            local_name!("area")
+                | local_name!("base")
+                | local_name!("basefont")
+                | local_name!("bgsound")
+                | local_name!("br")
+                | local_name!("col")
+                | local_name!("embed")
+                | local_name!("frame")
+                | local_name!("hr")
+                | local_name!("img")
+                | local_name!("input")
+                | local_name!("keygen")
+                | local_name!("link")
+                | local_name!("meta")
+                | local_name!("param")
+                | local_name!("source")
+                | local_name!("track")
+                | local_name!("wbr")
+        )
+}
+
+/// Whether the text contents of this element are written without escaping.
+///
+/// `<noscript>` is only parsed as raw text with scripting enabled.
+pub fn is_raw_text(name: &QualName, scripting_enabled: bool) -> bool {
+    name.ns == ns!(html)
+        && match name.local {
+            local_name!("style")
+            | local_name!("script")
+            | local_name!("xmp")
+            | local_name!("iframe")
+            | local_name!("noembed")
+            | local_name!("noframes")
+            | local_name!("plaintext") => true,
+            local_name!("noscript") => scripting_enabled,
+            _ => false,
+        }
+}
+
+/// Replace each run of ASCII whitespace with a single space.
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut after_whitespace = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !after_whitespace {
+                collapsed.push(' ')
+            }
+            after_whitespace = true
+        } else {
+            collapsed.push(c);
+            after_whitespace = false
+        }
+    }
+    collapsed
+}
@@ -4,10 +4,14 @@ use std::borrow::Borrow;
 use std::cell::RefCell;
 use std::iter::Rev;
 
+use selectors::context::QuirksMode;
+use selectors::matching;
+
 use crate::node_data_ref::NodeDataRef;
-use crate::select::Selectors;
+use crate::select::{IntoSelectors, KuchikiSelectors, SelectorCache, Selectors};
 use crate::tree::{ElementData, NodeRef};
 
+#[allow(clippy::result_unit_err)] // Selector errors, like `Selectors::compile`
 impl NodeRef {
     /// Return an iterator of references to this node and its ancestors.
     #[inline]
@@ -154,18 +158,80 @@ impl NodeRef {
         }
     }
 
-    /// Return an iterator of the inclusive descendants element that match the given selector list.
+    /// Return an iterator of the descendant elements that match the given selector list.
+    ///
+    /// If this node is an element, it is the scope element:
+    /// it is matched by `:scope`, and selectors can start with a combinator
+    /// to be relative to it, as in `> li`.
+    /// As with `querySelectorAll` in the DOM, the scope element itself is never returned.
     #[inline]
     pub fn select(&self, selectors: &str) -> Result<Select<Elements<Descendants>>, ()> {
-        self.inclusive_descendants().select(selectors)
+        let selectors = SelectorCache::with_thread_local(|cache| cache.compile_scoped(selectors))?;
+        Ok(Select::with_scope(
+            self.descendants().elements(),
+            selectors,
+            self.clone().into_element_ref(),
+        ))
     }
 
-    /// Return the first inclusive descendants element that match the given selector list.
+    /// Return the first descendant element that match the given selector list.
     #[inline]
     pub fn select_first(&self, selectors: &str) -> Result<NodeDataRef<ElementData>, ()> {
         let mut elements = self.select(selectors)?;
         elements.next().ok_or(())
     }
+
+    /// Return an iterator of the descendant elements that match
+    /// the given pre-compiled selector list.
+    ///
+    /// If this node is an element, it is the scope element, as in `select`.
+    /// Selectors starting with a combinator are compiled with `Selectors::compile_scoped`.
+    #[inline]
+    pub fn select_compiled<'a>(
+        &self,
+        selectors: &'a Selectors,
+    ) -> Select<Elements<Descendants>, &'a Selectors> {
+        Select::with_scope(
+            self.descendants().elements(),
+            selectors,
+            self.clone().into_element_ref(),
+        )
+    }
+
+    /// Return the first descendant element that match
+    /// the given pre-compiled selector list.
+    #[inline]
+    pub fn select_first_compiled(&self, selectors: &Selectors) -> Option<NodeDataRef<ElementData>> {
+        self.select_compiled(selectors).next()
+    }
+
+    /// Return whether this node is an element that matches the given selector list,
+    /// given as a string or as pre-compiled `Selectors`.
+    ///
+    /// The element itself is the scope element, as in `select`.
+    pub fn matches<'a, S: IntoSelectors<'a>>(&self, selectors: S) -> Result<bool, ()> {
+        let selectors = selectors.into_selectors()?;
+        Ok(self
+            .clone()
+            .into_element_ref()
+            .is_some_and(|element| selectors.matches_with_scope(&element, Some(&element))))
+    }
+
+    /// Return the nearest inclusive ancestor element that matches the given selector list,
+    /// given as a string or as pre-compiled `Selectors`.
+    ///
+    /// This node, if it is an element, is the scope element, as in `select`.
+    pub fn closest<'a, S: IntoSelectors<'a>>(
+        &self,
+        selectors: S,
+    ) -> Result<Option<NodeDataRef<ElementData>>, ()> {
+        let selectors = selectors.into_selectors()?;
+        let scope = self.clone().into_element_ref();
+        Ok(self
+            .inclusive_ancestors()
+            .elements()
+            .find(|element| selectors.matches_with_scope(element, scope.as_ref())))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -363,6 +429,11 @@ filter_map_like_iterator! {
 }
 
 /// An element iterator adaptor that yields elements maching given selectors.
+///
+/// Create one with `Select::new` or `Select::with_scope`.
+///
+/// Results of `:has()` are remembered for as long as the iterator,
+/// so changes to the tree made while iterating may not be taken into account.
 pub struct Select<I, S = Selectors>
 where
     I: Iterator<Item = NodeDataRef<ElementData>>,
@@ -373,6 +444,46 @@ where
 
     /// The selectors to be matched.
     pub selectors: S,
+
+    /// The element matched by `:scope`, if any.
+    /// The context only refers to it by address, so this keeps it alive.
+    _scope: Option<NodeDataRef<ElementData>>,
+
+    /// Shared by all elements, with `scope` as its scope element.
+    context: matching::MatchingContext<'static, KuchikiSelectors>,
+}
+
+impl<I, S> Select<I, S>
+where
+    I: Iterator<Item = NodeDataRef<ElementData>>,
+    S: Borrow<Selectors>,
+{
+    /// Filter an element iterator to elements matching the given selectors.
+    ///
+    /// `:scope` matches the root element.
+    #[inline]
+    pub fn new(iter: I, selectors: S) -> Self {
+        Select::with_scope(iter, selectors, None)
+    }
+
+    /// Filter an element iterator to elements matching the given selectors,
+    /// with `:scope` and selectors starting with a combinator relative to the given scope.
+    #[inline]
+    pub fn with_scope(iter: I, selectors: S, scope: Option<NodeDataRef<ElementData>>) -> Self {
+        let mut context = matching::MatchingContext::new(
+            matching::MatchingMode::Normal,
+            None,
+            None,
+            QuirksMode::NoQuirks,
+        );
+        context.scope_element = scope.as_ref().map(selectors::Element::opaque);
+        Select {
+            iter,
+            selectors,
+            _scope: scope,
+            context,
+        }
+    }
 }
 
 impl<I, S> Iterator for Select<I, S>
@@ -384,12 +495,14 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<NodeDataRef<ElementData>> {
-        for element in self.iter.by_ref() {
-            if self.selectors.borrow().matches(&element) {
-                return Some(element);
-            }
-        }
-        None
+        let Select {
+            iter,
+            selectors,
+            context,
+            ..
+        } = self;
+        let selectors = (*selectors).borrow();
+        iter.find(|element| selectors.matches_in_context(element, context))
     }
 }
 
@@ -400,16 +513,19 @@ where
 {
     #[inline]
     fn next_back(&mut self) -> Option<NodeDataRef<ElementData>> {
-        for element in self.iter.by_ref().rev() {
-            if self.selectors.borrow().matches(&element) {
-                return Some(element);
-            }
-        }
-        None
+        let Select {
+            iter,
+            selectors,
+            context,
+            ..
+        } = self;
+        let selectors = (*selectors).borrow();
+        iter.rfind(|element| selectors.matches_in_context(element, context))
     }
 }
 
 /// Convenience methods for node iterators.
+#[allow(clippy::result_unit_err)] // Selector errors, like `Selectors::compile`
 pub trait NodeIterator: Sized + Iterator<Item = NodeRef> {
     /// Filter this element iterator to elements.
     #[inline]
@@ -437,14 +553,15 @@ pub trait NodeIterator: Sized + Iterator<Item = NodeRef> {
 }
 
 /// Convenience methods for element iterators.
+#[allow(clippy::result_unit_err)] // Selector errors, like `Selectors::compile`
 pub trait ElementIterator: Sized + Iterator<Item = NodeDataRef<ElementData>> {
     /// Filter this element iterator to elements maching the given selectors.
+    ///
+    /// Compiled selectors are kept in the per-thread `SelectorCache`.
     #[inline]
     fn select(self, selectors: &str) -> Result<Select<Self>, ()> {
-        Selectors::compile(selectors).map(|s| Select {
-            iter: self,
-            selectors: s,
-        })
+        SelectorCache::with_thread_local(|cache| cache.compile(selectors))
+            .map(|s| Select::new(self, s))
     }
 }
 
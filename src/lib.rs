@@ -11,21 +11,41 @@ extern crate html5ever;
 #[macro_use]
 extern crate matches;
 
+mod attribute_views;
 mod attributes;
 mod cell_extras;
+mod element_state;
+mod html_elements;
 pub mod iter;
+pub mod markdown;
+mod microsyntax;
+mod minify;
 mod node_data_ref;
 mod parser;
+mod plain_text;
 mod select;
 mod serializer;
+pub mod style;
 #[cfg(test)]
 mod tests;
 mod tree;
+#[cfg(feature = "serde")]
+mod tree_serde;
+pub mod xpath;
 
+pub use attribute_views::{ClassList, Dataset};
 pub use attributes::{Attribute, Attributes, ExpandedName};
+pub use microsyntax::{
+    AttributeError, Date, DateOrTime, Dimension, ImageCandidate, ImageDescriptor, Time,
+};
 pub use node_data_ref::NodeDataRef;
 pub use parser::{parse_html, parse_html_with_options, parse_fragment, ParseOpts, Sink};
-pub use select::{Selector, Selectors, Specificity};
+pub use plain_text::PlainTextOpts;
+pub use select::{
+    IntoSelectors, PseudoClassRegistry, Selector, SelectorCache, SelectorOpts, Selectors,
+    Specificity,
+};
+pub use serializer::{AttributeOrder, FilterAction, FmtWriter, QuoteStyle, SerializeOptions};
 pub use tree::{Doctype, DocumentData, ElementData, Node, NodeData, NodeRef};
 
 /// This module re-exports a number of traits that are useful when using Kuchiki.
@@ -0,0 +1,667 @@
+//! Conversion of a tree to Markdown.
+//!
+//! The output is [CommonMark](https://spec.commonmark.org/)
+//! with the tables and strikethrough extensions of
+//! [GitHub Flavored Markdown](https://github.github.com/gfm/).
+//! Elements without a Markdown equivalent, such as `<dl>`, `<details>` or `<sup>`,
+//! and tables that cannot be represented (with merged cells or block-level contents)
+//! are written as raw HTML, which Markdown allows.
+//!
+//! ```rust
+//! use kuchiki::traits::*;
+//!
+//! let document = kuchiki::parse_html().one("<h1>Title</h1><p>Some <em>text</em>.</p>");
+//! assert_eq!(kuchiki::markdown::to_markdown(&document), "# Title\n\nSome *text*.\n");
+//! ```
+
+use crate::html_elements::{html_name, is_block, is_not_rendered};
+use crate::iter::{NodeEdge, NodeIterator};
+use crate::tree::{ElementData, NodeData, NodeRef};
+
+/// Convert this node and its descendants to Markdown.
+pub fn to_markdown(node: &NodeRef) -> String {
+    let lines = match node.as_element() {
+        Some(element) if is_not_rendered(&element.name) => Vec::new(),
+        Some(element) if is_block(&element.name) => {
+            render(Frame::block(node, element, Delivery::Root))
+        }
+        Some(_) => paragraph(&inline_contents(node)),
+        None => render(Frame::flow(node, false, Delivery::Root)),
+    };
+    let mut markdown = String::new();
+    for line in lines {
+        markdown.push_str(&line);
+        markdown.push('\n');
+    }
+    markdown
+}
+
+/// Where the lines of a block go once it is converted.
+enum Delivery {
+    /// Returned by `render`.
+    Root,
+    /// In the flow of the parent block.
+    Block { is_list: bool },
+    /// As an item of the parent list, after the given marker.
+    Item(String),
+}
+
+enum Content {
+    /// The children of a node as blocks separated by blank lines.
+    ///
+    /// In a tight flow, as in list items, nested lists are not preceded by a blank line.
+    Flow {
+        lines: Vec<String>,
+        inline: String,
+        next_child: Option<NodeRef>,
+        tight: bool,
+    },
+    /// List items, with their marker. `items` is in reverse order.
+    List {
+        lines: Vec<String>,
+        items: Vec<(NodeRef, String)>,
+    },
+    /// Lines that are already converted.
+    Lines(Vec<String>),
+}
+
+/// A block being converted.
+struct Frame {
+    content: Content,
+    /// Whether the lines are quoted, as in a `<blockquote>`.
+    quoted: bool,
+    delivery: Delivery,
+}
+
+impl Frame {
+    fn flow(node: &NodeRef, tight: bool, delivery: Delivery) -> Frame {
+        Frame {
+            content: Content::Flow {
+                lines: Vec::new(),
+                inline: String::new(),
+                next_child: node.first_child(),
+                tight,
+            },
+            quoted: false,
+            delivery,
+        }
+    }
+
+    fn lines(lines: Vec<String>, delivery: Delivery) -> Frame {
+        Frame {
+            content: Content::Lines(lines),
+            quoted: false,
+            delivery,
+        }
+    }
+
+    /// Start converting a block-level element.
+    fn block(node: &NodeRef, element: &ElementData, delivery: Delivery) -> Frame {
+        let name = match html_name(element) {
+            Some(name) => name,
+            None => return Frame::lines(raw_html(node), delivery),
+        };
+        match *name {
+            local_name!("h1")
+            | local_name!("h2")
+            | local_name!("h3")
+            | local_name!("h4")
+            | local_name!("h5")
+            | local_name!("h6") => {
+                let level = usize::from(name.as_bytes()[1] - b'0');
+                Frame::lines(heading(level, &inline_contents(node)), delivery)
+            }
+            local_name!("p") => Frame::lines(paragraph(&inline_contents(node)), delivery),
+            local_name!("ul") | local_name!("ol") => Frame {
+                content: Content::List {
+                    lines: Vec::new(),
+                    items: list_items(node, element),
+                },
+                quoted: false,
+                delivery,
+            },
+            local_name!("blockquote") => Frame {
+                quoted: true,
+                ..Frame::flow(node, false, delivery)
+            },
+            local_name!("pre") => Frame::lines(code_block(node, element), delivery),
+            local_name!("hr") => Frame::lines(vec!["---".to_owned()], delivery),
+            local_name!("table") => {
+                Frame::lines(table(node).unwrap_or_else(|| raw_html(node)), delivery)
+            }
+            local_name!("address")
+            | local_name!("article")
+            | local_name!("aside")
+            | local_name!("body")
+            | local_name!("center")
+            | local_name!("div")
+            | local_name!("figcaption")
+            | local_name!("figure")
+            | local_name!("footer")
+            | local_name!("header")
+            | local_name!("hgroup")
+            | local_name!("html")
+            | local_name!("li")
+            | local_name!("main")
+            | local_name!("nav")
+            | local_name!("section") => Frame::flow(node, false, delivery),
+            _ => Frame::lines(raw_html(node), delivery),
+        }
+    }
+
+    /// Add the lines of a child block.
+    fn receive(&mut self, block: Vec<String>, delivery: Delivery) {
+        match (&mut self.content, delivery) {
+            (Content::Flow { lines, tight, .. }, Delivery::Block { is_list }) => {
+                push_block(lines, block, *tight && is_list)
+            }
+            (Content::List { lines, .. }, Delivery::Item(marker)) => {
+                push_item(lines, block, &marker)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the lines of this block, and where they go.
+    fn finish(self) -> (Vec<String>, Delivery) {
+        let lines = match self.content {
+            Content::Flow {
+                mut lines, inline, ..
+            } => {
+                push_block(&mut lines, paragraph(&inline), false);
+                lines
+            }
+            Content::List { lines, .. } | Content::Lines(lines) => lines,
+        };
+        let lines = if self.quoted {
+            lines
+                .into_iter()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_owned()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect()
+        } else {
+            lines
+        };
+        (lines, self.delivery)
+    }
+}
+
+/// Convert a block and its descendants, one block at a time.
+fn render(root: Frame) -> Vec<String> {
+    let mut stack = vec![root];
+    loop {
+        let frame = stack.last_mut().unwrap();
+        let child = match &mut frame.content {
+            Content::Flow {
+                lines,
+                inline,
+                next_child,
+                ..
+            } => match next_child.take() {
+                Some(child) => {
+                    *next_child = child.next_sibling();
+                    match child.as_element() {
+                        Some(element) if is_not_rendered(&element.name) => continue,
+                        Some(element) if is_block(&element.name) => {
+                            push_block(lines, paragraph(inline), false);
+                            inline.clear();
+                            let is_list = matches!(
+                                html_name(element),
+                                Some(&local_name!("ul")) | Some(&local_name!("ol"))
+                            );
+                            Some(Frame::block(&child, element, Delivery::Block { is_list }))
+                        }
+                        _ => {
+                            write_inline(&child, inline);
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            },
+            Content::List { items, .. } => items.pop().map(|(item, marker)| {
+                let element = item.as_element().unwrap();
+                if html_name(element) == Some(&local_name!("li")) {
+                    Frame::flow(&item, true, Delivery::Item(marker))
+                } else {
+                    Frame::block(&item, element, Delivery::Item(marker))
+                }
+            }),
+            Content::Lines(_) => None,
+        };
+        if let Some(child) = child {
+            stack.push(child);
+            continue;
+        }
+        let (lines, delivery) = stack.pop().unwrap().finish();
+        match stack.last_mut() {
+            Some(parent) => parent.receive(lines, delivery),
+            None => return lines,
+        }
+    }
+}
+
+/// Add a block to a flow, after a blank line unless it is a list in a tight flow.
+fn push_block(lines: &mut Vec<String>, block: Vec<String>, tight_list: bool) {
+    if block.is_empty() {
+        return;
+    }
+    if !lines.is_empty() && !tight_list {
+        lines.push(String::new())
+    }
+    lines.extend(block)
+}
+
+/// An ATX heading, with a trailing run of `#` escaped
+/// so that it is not taken as a closing sequence.
+fn heading(level: usize, inline: &str) -> Vec<String> {
+    let text = inline.replace("\\\n", " ");
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let before_hashes = text.trim_end_matches('#');
+    let text = if before_hashes.len() < text.len()
+        && (before_hashes.is_empty() || before_hashes.ends_with(' '))
+    {
+        format!("{}\\{}", before_hashes, &text[before_hashes.len()..])
+    } else {
+        text.to_owned()
+    };
+    vec![format!("{} {}", "#".repeat(level), text)]
+}
+
+fn raw_html(node: &NodeRef) -> Vec<String> {
+    node.to_string().lines().map(str::to_owned).collect()
+}
+
+/// The largest number of an ordered list item, which has at most 9 digits in CommonMark.
+const MAX_LIST_NUMBER: u32 = 999_999_999;
+
+/// The rendered element children of a list, with their marker.
+fn list_items(node: &NodeRef, element: &ElementData) -> Vec<(NodeRef, String)> {
+    let ordered = html_name(element) == Some(&local_name!("ol"));
+    let mut number = element
+        .attributes
+        .borrow()
+        .get(local_name!("start"))
+        .and_then(|start| start.trim().parse::<u64>().ok())
+        .map_or(1, |start| start.min(MAX_LIST_NUMBER.into()) as u32);
+    let mut items = Vec::new();
+    for item in node.children().elements() {
+        if is_not_rendered(&item.name) {
+            continue;
+        }
+        let marker = if ordered {
+            format!("{}. ", number)
+        } else {
+            "- ".to_owned()
+        };
+        number = number.saturating_add(1).min(MAX_LIST_NUMBER);
+        items.push((item.as_node().clone(), marker))
+    }
+    items.reverse();
+    items
+}
+
+/// Add a list item, with its continuation lines indented to the width of its marker.
+fn push_item(lines: &mut Vec<String>, mut item_lines: Vec<String>, marker: &str) {
+    if item_lines.is_empty() {
+        item_lines.push(String::new())
+    }
+    for (i, line) in item_lines.into_iter().enumerate() {
+        if i == 0 {
+            lines.push(format!("{}{}", marker, line).trim_end().to_owned())
+        } else if line.is_empty() {
+            lines.push(line)
+        } else {
+            lines.push(format!("{}{}", " ".repeat(marker.len()), line))
+        }
+    }
+}
+
+/// A fenced code block, with the language of a `language-*` class
+/// of the `<pre>` element or of a `<code>` element that is its only child.
+fn code_block(node: &NodeRef, element: &ElementData) -> Vec<String> {
+    let mut children = node.children().filter(|child| {
+        child
+            .as_text()
+            .map_or(true, |text| !text.borrow().trim().is_empty())
+    });
+    let code = match (children.next(), children.next()) {
+        (Some(child), None) => child
+            .into_element_ref()
+            .filter(|code| html_name(code) == Some(&local_name!("code"))),
+        _ => None,
+    };
+    let language = code
+        .as_ref()
+        .and_then(|code| language(code))
+        .or_else(|| language(element))
+        .unwrap_or_default();
+
+    let text = node.text_contents();
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let mut longest_backticks = 0;
+    let mut backticks = 0;
+    for c in text.chars() {
+        backticks = if c == '`' { backticks + 1 } else { 0 };
+        longest_backticks = longest_backticks.max(backticks);
+    }
+    let fence = "`".repeat(longest_backticks.max(2) + 1);
+
+    let mut lines = vec![format!("{}{}", fence, language)];
+    lines.extend(text.split('\n').map(str::to_owned));
+    lines.push(fence);
+    lines
+}
+
+fn language(element: &ElementData) -> Option<String> {
+    let attributes = element.attributes.borrow();
+    attributes
+        .get(local_name!("class"))?
+        .split_ascii_whitespace()
+        .find_map(|class| class.strip_prefix("language-"))
+        // The info string of a backtick fence cannot contain backticks.
+        .map(|language| language.replace('`', ""))
+}
+
+/// A table with the first row as header, or `None` if it cannot be represented.
+fn table(node: &NodeRef) -> Option<Vec<String>> {
+    let mut rows = Vec::new();
+    for child in node.children() {
+        match child.as_element().and_then(html_name) {
+            Some(&local_name!("tr")) => rows.push(child),
+            Some(&local_name!("thead"))
+            | Some(&local_name!("tbody"))
+            | Some(&local_name!("tfoot")) => rows.extend(
+                child
+                    .children()
+                    .filter(|row| row.as_element().and_then(html_name) == Some(&local_name!("tr"))),
+            ),
+            Some(_) => return None,
+            None => {}
+        }
+    }
+
+    let mut cells = Vec::new();
+    for row in &rows {
+        let mut row_cells = Vec::new();
+        for cell in row.children().elements() {
+            if !matches!(
+                html_name(&cell),
+                Some(&local_name!("td")) | Some(&local_name!("th"))
+            ) {
+                continue;
+            }
+            let attributes = cell.attributes.borrow();
+            let is_merged = ["colspan", "rowspan"]
+                .iter()
+                .any(|&name| attributes.get(name).is_some_and(|span| span.trim() != "1"));
+            let has_blocks = cell
+                .as_node()
+                .descendants()
+                .elements()
+                .any(|element| is_block(&element.name));
+            if is_merged || has_blocks {
+                return None;
+            }
+            let text = inline_contents(cell.as_node());
+            row_cells.push(text.trim().replace("\\\n", "<br>").replace('|', "\\|"))
+        }
+        cells.push(row_cells)
+    }
+
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return None;
+    }
+    let format_row = |row: &[String]| {
+        let cells = (0..columns)
+            .map(|i| row.get(i).map_or("", String::as_str))
+            .collect::<Vec<_>>();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![
+        format_row(&cells[0]),
+        format!("|{}", " --- |".repeat(columns)),
+    ];
+    lines.extend(cells[1..].iter().map(|row| format_row(row)));
+    Some(lines)
+}
+
+/// Split inline Markdown into trimmed lines at hard line breaks,
+/// escaping what would otherwise start a block.
+fn paragraph(inline: &str) -> Vec<String> {
+    let inline = inline.trim();
+    let inline = inline.strip_suffix('\\').unwrap_or(inline).trim_end();
+    if inline.is_empty() {
+        return Vec::new();
+    }
+    inline
+        .split('\n')
+        .map(|line| {
+            let line = line.trim();
+            let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+            if line.starts_with(['#', '-', '+', '=']) {
+                format!("\\{}", line)
+            } else if digits > 0 && line[digits..].starts_with(['.', ')']) {
+                format!("{}\\{}", &line[..digits], &line[digits..])
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect()
+}
+
+fn inline_contents(node: &NodeRef) -> String {
+    let mut inline = String::new();
+    for child in node.children() {
+        write_inline(&child, &mut inline)
+    }
+    inline
+}
+
+/// What to do with the contents of an inline node.
+enum Contents {
+    Write,
+    Skip,
+    /// Write them between delimiters, as with `delimit`.
+    Delimit(&'static str, String),
+}
+
+/// An element whose inline contents are being written, to be delimited at its end.
+struct Delimited {
+    node: NodeRef,
+    start: &'static str,
+    end: String,
+    contents: String,
+}
+
+/// Write inline Markdown, with whitespace collapsed and `\` and a newline for line breaks.
+fn write_inline(node: &NodeRef, inline: &mut String) {
+    let mut open: Vec<Delimited> = Vec::new();
+    // A node whose contents are not written.
+    let mut skipped = None;
+    for edge in node.traverse_inclusive() {
+        match edge {
+            NodeEdge::Start(_) if skipped.is_some() => {}
+            NodeEdge::Start(node) => {
+                let inline = match open.last_mut() {
+                    Some(delimited) => &mut delimited.contents,
+                    None => &mut *inline,
+                };
+                match start_inline(&node, inline) {
+                    Contents::Write => {}
+                    Contents::Skip => skipped = Some(node),
+                    Contents::Delimit(start, end) => open.push(Delimited {
+                        node,
+                        start,
+                        end,
+                        contents: String::new(),
+                    }),
+                }
+            }
+            NodeEdge::End(node) => {
+                if skipped.is_some() {
+                    if skipped.as_ref() == Some(&node) {
+                        skipped = None
+                    }
+                } else if open.last().is_some_and(|delimited| delimited.node == node) {
+                    let delimited = open.pop().unwrap();
+                    let inline = match open.last_mut() {
+                        Some(parent) => &mut parent.contents,
+                        None => &mut *inline,
+                    };
+                    delimit(&delimited.contents, delimited.start, &delimited.end, inline)
+                }
+            }
+        }
+    }
+}
+
+/// Write what comes before the contents of an inline node.
+fn start_inline(node: &NodeRef, inline: &mut String) -> Contents {
+    let element = match node.data() {
+        NodeData::Text(text) => {
+            for c in text.borrow().chars() {
+                if c.is_ascii_whitespace() {
+                    if !inline.is_empty() && !inline.ends_with([' ', '\n']) {
+                        inline.push(' ')
+                    }
+                    continue;
+                }
+                if matches!(
+                    c,
+                    '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~' | '&'
+                ) {
+                    inline.push('\\')
+                }
+                inline.push(c)
+            }
+            return Contents::Skip;
+        }
+        NodeData::Element(element) => element,
+        _ => return Contents::Skip,
+    };
+    if is_not_rendered(&element.name) {
+        return Contents::Skip;
+    }
+    let name = match html_name(element) {
+        Some(name) => name,
+        None => {
+            inline.push_str(&node.to_string());
+            return Contents::Skip;
+        }
+    };
+    match *name {
+        local_name!("br") => {
+            if inline.ends_with(' ') {
+                inline.pop();
+            }
+            inline.push_str("\\\n")
+        }
+        local_name!("em") | local_name!("i") | local_name!("cite") | local_name!("var") => {
+            return Contents::Delimit("*", "*".to_owned())
+        }
+        local_name!("strong") | local_name!("b") => {
+            return Contents::Delimit("**", "**".to_owned())
+        }
+        local_name!("del") | local_name!("s") | local_name!("strike") => {
+            return Contents::Delimit("~~", "~~".to_owned())
+        }
+        local_name!("code") | local_name!("tt") | local_name!("samp") => {
+            let text = node.text_contents();
+            let text = text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                return Contents::Skip;
+            }
+            let mut fence = "`".to_owned();
+            while text.contains(&*fence) {
+                fence.push('`')
+            }
+            let padding = if text.starts_with('`') || text.ends_with('`') {
+                " "
+            } else {
+                ""
+            };
+            inline.push_str(&format!("{0}{1}{2}{1}{0}", fence, padding, text))
+        }
+        local_name!("a") => {
+            let attributes = element.attributes.borrow();
+            return match attributes.get(local_name!("href")) {
+                Some(href) => {
+                    let end = format!(
+                        "]({}{})",
+                        link_destination(href),
+                        link_title(attributes.get(local_name!("title")))
+                    );
+                    Contents::Delimit("[", end)
+                }
+                None => Contents::Write,
+            };
+        }
+        local_name!("img") => {
+            let attributes = element.attributes.borrow();
+            let alt = attributes.get(local_name!("alt")).unwrap_or("");
+            let alt = alt
+                .replace('\\', "\\\\")
+                .replace('[', "\\[")
+                .replace(']', "\\]");
+            let src = attributes.get(local_name!("src")).unwrap_or("");
+            inline.push_str(&format!(
+                "![{}]({}{})",
+                alt,
+                link_destination(src),
+                link_title(attributes.get(local_name!("title")))
+            ))
+        }
+        local_name!("abbr")
+        | local_name!("ins")
+        | local_name!("kbd")
+        | local_name!("mark")
+        | local_name!("sub")
+        | local_name!("sup")
+        | local_name!("u") => inline.push_str(&node.to_string()),
+        _ => return Contents::Write,
+    }
+    Contents::Skip
+}
+
+/// Write inline contents between delimiters,
+/// with surrounding whitespace moved outside of the delimiters.
+fn delimit(contents: &str, start: &str, end: &str, inline: &mut String) {
+    let trimmed = contents.trim_matches(' ');
+    if trimmed.is_empty() && start != "[" {
+        return inline.push_str(contents);
+    }
+    if contents.starts_with(' ') && !inline.is_empty() && !inline.ends_with([' ', '\n']) {
+        inline.push(' ')
+    }
+    inline.push_str(start);
+    inline.push_str(trimmed);
+    inline.push_str(end);
+    if contents.ends_with(' ') {
+        inline.push(' ')
+    }
+}
+
+fn link_destination(url: &str) -> String {
+    if url.is_empty() || url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_owned()
+    }
+}
+
+fn link_title(title: Option<&str>) -> String {
+    match title {
+        Some(title) => format!(" \"{}\"", title.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => String::new(),
+    }
+}
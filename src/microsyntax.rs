@@ -0,0 +1,474 @@
+//! Typed accessors for attribute values, following the HTML microsyntaxes.
+//!
+//! <https://html.spec.whatwg.org/multipage/#common-microsyntaxes>
+
+use html5ever::LocalName;
+use std::error::Error;
+use std::fmt;
+
+use crate::attributes::Attributes;
+
+/// An error from a typed attribute accessor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeError {
+    /// The attribute is not present.
+    Missing,
+    /// The value does not follow the expected microsyntax.
+    Invalid {
+        /// The attribute value.
+        value: String,
+        /// A description of the expected microsyntax.
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttributeError::Missing => f.write_str("missing attribute"),
+            AttributeError::Invalid { value, expected } => {
+                write!(
+                    f,
+                    "invalid attribute value {:?}, expected {}",
+                    value, expected
+                )
+            }
+        }
+    }
+}
+
+impl Error for AttributeError {}
+
+/// A dimension value, as in `width="50%"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    /// A number of CSS pixels.
+    Length(f64),
+    /// A percentage.
+    Percentage(f64),
+}
+
+/// An image candidate of a `srcset` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCandidate {
+    /// The URL, as written.
+    pub url: String,
+    /// The width or density descriptor, if any. No descriptor is equivalent to `1x`.
+    pub descriptor: Option<ImageDescriptor>,
+}
+
+/// A `srcset` descriptor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageDescriptor {
+    /// A width descriptor, such as `300w`.
+    Width(u64),
+    /// A pixel density descriptor, such as `1.5x`.
+    Density(f64),
+}
+
+/// A date, as in `2024-02-29`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    /// The year, greater than zero.
+    pub year: u32,
+    /// The month, from 1 to 12.
+    pub month: u8,
+    /// The day of the month, from 1.
+    pub day: u8,
+}
+
+/// A time of the day, as in `13:45:30.5`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Time {
+    /// The hour, from 0 to 23.
+    pub hour: u8,
+    /// The minute, from 0 to 59.
+    pub minute: u8,
+    /// The second, at least 0 and less than 60, with its fractional part.
+    pub second: f64,
+}
+
+/// The value of a `datetime` attribute, as on `<ins>`, `<del>` or `<time>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateOrTime {
+    /// A date without a time.
+    Date(Date),
+    /// A time without a date.
+    Time(Time),
+    /// A date and time in a given time zone.
+    Global {
+        /// The date, in the time zone of `offset`.
+        date: Date,
+        /// The time, in the time zone of `offset`.
+        time: Time,
+        /// The offset from UTC, in minutes.
+        offset: i32,
+    },
+}
+
+fn invalid<T>(value: &str, expected: &'static str) -> Result<T, AttributeError> {
+    Err(AttributeError::Invalid {
+        value: value.to_owned(),
+        expected,
+    })
+}
+
+fn skip_whitespace(s: &str) -> &str {
+    s.trim_start_matches(|c: char| c.is_ascii_whitespace())
+}
+
+fn digits_len(s: &str) -> usize {
+    s.bytes().take_while(u8::is_ascii_digit).count()
+}
+
+/// <https://html.spec.whatwg.org/multipage/#rules-for-parsing-integers>
+fn parse_integer(value: &str) -> Result<i64, AttributeError> {
+    let s = skip_whitespace(value);
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let digits = &s[..digits_len(s)];
+    let magnitude = match digits.parse::<i64>() {
+        Ok(magnitude) => magnitude,
+        Err(_) => return invalid(value, "an integer"),
+    };
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+/// <https://html.spec.whatwg.org/multipage/#rules-for-parsing-floating-point-number-values>
+fn parse_float(value: &str) -> Result<f64, AttributeError> {
+    let s = skip_whitespace(value);
+    let mut len = 0;
+    if s.starts_with('-') || s.starts_with('+') {
+        len += 1;
+    }
+    let integer = digits_len(&s[len..]);
+    len += integer;
+    let fraction = if s[len..].starts_with('.') {
+        digits_len(&s[len + 1..])
+    } else {
+        0
+    };
+    if fraction > 0 {
+        len += 1 + fraction;
+    }
+    if integer == 0 && fraction == 0 {
+        return invalid(value, "a floating-point number");
+    }
+    if s[len..].starts_with(['e', 'E']) {
+        let exponent = &s[len + 1..];
+        let sign = usize::from(exponent.starts_with('-') || exponent.starts_with('+'));
+        let exponent_digits = digits_len(&exponent[sign..]);
+        if exponent_digits > 0 {
+            len += 1 + sign + exponent_digits;
+        }
+    }
+    match s[..len].parse::<f64>() {
+        // Negative zero is zero.
+        Ok(number) if number.is_finite() => Ok(number + 0.),
+        _ => invalid(value, "a floating-point number"),
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#rules-for-parsing-dimension-values>
+fn parse_dimension(value: &str) -> Result<Dimension, AttributeError> {
+    let s = skip_whitespace(value);
+    let s = s.strip_prefix('+').unwrap_or(s);
+    let integer = digits_len(s);
+    if integer == 0 {
+        return invalid(value, "a dimension");
+    }
+    let mut len = integer;
+    if s[len..].starts_with('.') {
+        len += 1 + digits_len(&s[len + 1..]);
+    }
+    let number = match s[..len].trim_end_matches('.').parse::<f64>() {
+        Ok(number) if number.is_finite() => number,
+        _ => return invalid(value, "a dimension"),
+    };
+    if s[len..].starts_with('%') {
+        Ok(Dimension::Percentage(number))
+    } else {
+        Ok(Dimension::Length(number))
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#parse-a-srcset-attribute>
+///
+/// Candidates with invalid descriptors are dropped. Height descriptors are ignored.
+fn parse_srcset(value: &str) -> Vec<ImageCandidate> {
+    let mut candidates = Vec::new();
+    let mut s = value;
+    loop {
+        s = s.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if s.is_empty() {
+            return candidates;
+        }
+        let url_len = s.find(|c: char| c.is_ascii_whitespace()).unwrap_or(s.len());
+        let mut url = &s[..url_len];
+        s = &s[url_len..];
+        let mut descriptors = Vec::new();
+        if url.ends_with(',') {
+            url = url.trim_end_matches(',');
+        } else {
+            // Descriptors end at a comma outside of parentheses.
+            let mut in_parens = false;
+            let mut end = s.len();
+            for (i, c) in s.char_indices() {
+                match c {
+                    '(' => in_parens = true,
+                    ')' => in_parens = false,
+                    ',' if !in_parens => {
+                        end = i;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            descriptors.extend(s[..end].split_ascii_whitespace());
+            s = &s[end..];
+        }
+
+        let mut width = None;
+        let mut density = None;
+        let mut height = None;
+        let mut valid = true;
+        for descriptor in descriptors {
+            let last = descriptor.chars().last().map_or(0, char::len_utf8);
+            let (number, kind) = descriptor.split_at(descriptor.len() - last);
+            match kind {
+                "w" if width.is_none() && density.is_none() => match parse_integer(number) {
+                    Ok(w) if w > 0 && number.bytes().all(|b| b.is_ascii_digit()) => {
+                        width = Some(w as u64)
+                    }
+                    _ => valid = false,
+                },
+                "x" if width.is_none() && density.is_none() && height.is_none() => {
+                    match parse_float(number) {
+                        Ok(x) if x >= 0. => density = Some(x),
+                        _ => valid = false,
+                    }
+                }
+                "h" if height.is_none() && density.is_none() => match parse_integer(number) {
+                    Ok(h) if h > 0 && number.bytes().all(|b| b.is_ascii_digit()) => {
+                        height = Some(h)
+                    }
+                    _ => valid = false,
+                },
+                _ => valid = false,
+            }
+        }
+        if height.is_some() && width.is_none() {
+            valid = false;
+        }
+        if valid && !url.is_empty() {
+            candidates.push(ImageCandidate {
+                url: url.to_owned(),
+                descriptor: width
+                    .map(ImageDescriptor::Width)
+                    .or_else(|| density.map(ImageDescriptor::Density)),
+            });
+        }
+    }
+}
+
+/// Parse `count` ASCII digits at the start of `s`.
+fn fixed_digits(s: &str, count: usize) -> Option<(u32, &str)> {
+    if digits_len(s) < count {
+        return None;
+    }
+    Some((s[..count].parse().ok()?, &s[count..]))
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if year % 400 == 0 || (year % 4 == 0 && year % 100 != 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// <https://html.spec.whatwg.org/multipage/#parse-a-date-component>
+fn parse_date_component(s: &str) -> Option<(Date, &str)> {
+    let year_len = digits_len(s);
+    if year_len < 4 {
+        return None;
+    }
+    let year = s[..year_len].parse().ok().filter(|&year| year > 0)?;
+    let s = s[year_len..].strip_prefix('-')?;
+    let (month, s) = fixed_digits(s, 2)?;
+    if !(1..=12).contains(&month) || digits_len(s) > 0 {
+        return None;
+    }
+    let month = month as u8;
+    let s = s.strip_prefix('-')?;
+    let (day, s) = fixed_digits(s, 2)?;
+    if day < 1 || day > u32::from(days_in_month(year, month)) || digits_len(s) > 0 {
+        return None;
+    }
+    let day = day as u8;
+    Some((Date { year, month, day }, s))
+}
+
+/// <https://html.spec.whatwg.org/multipage/#parse-a-time-component>
+fn parse_time_component(s: &str) -> Option<(Time, &str)> {
+    let (hour, s) = fixed_digits(s, 2).filter(|&(hour, _)| hour <= 23)?;
+    let s = s.strip_prefix(':')?;
+    let (minute, s) = fixed_digits(s, 2).filter(|&(minute, _)| minute <= 59)?;
+    let mut rest = s;
+    let mut second = 0.;
+    if let Some(s) = s.strip_prefix(':') {
+        // Two digits, then optionally a period and one or more digits.
+        let (_, after) = fixed_digits(s, 2).filter(|&(_, s)| digits_len(s) == 0)?;
+        let len = match after.strip_prefix('.') {
+            Some(fraction) if digits_len(fraction) > 0 => 3 + digits_len(fraction),
+            Some(_) => return None,
+            None => 2,
+        };
+        second = s[..len].parse().ok().filter(|&second| second < 60.)?;
+        rest = &s[len..];
+    }
+    let time = Time {
+        hour: hour as u8,
+        minute: minute as u8,
+        second,
+    };
+    Some((time, rest))
+}
+
+/// <https://html.spec.whatwg.org/multipage/#parse-a-time-zone-offset-component>
+///
+/// Returns the offset in minutes.
+fn parse_time_zone_offset_component(s: &str) -> Option<(i32, &str)> {
+    if let Some(s) = s.strip_prefix('Z') {
+        return Some((0, s));
+    }
+    let (sign, s) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes, s) = if digits_len(s) >= 4 {
+        let (hours, s) = fixed_digits(s, 2)?;
+        let (minutes, s) = fixed_digits(s, 2)?;
+        (hours, minutes, s)
+    } else {
+        let (hours, s) = fixed_digits(s, 2)?;
+        let (minutes, s) = fixed_digits(s.strip_prefix(':')?, 2)?;
+        (hours, minutes, s)
+    };
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some((sign * (hours * 60 + minutes) as i32, s))
+}
+
+/// <https://html.spec.whatwg.org/multipage/#parse-a-date-or-time-string>
+fn parse_date_or_time(value: &str) -> Result<DateOrTime, AttributeError> {
+    let parse = || {
+        let (date, s) = match parse_date_component(value) {
+            Some((date, s)) => match s.strip_prefix(['T', ' ']) {
+                Some(s) => (Some(date), s),
+                None if s.is_empty() => return Some(DateOrTime::Date(date)),
+                None => return None,
+            },
+            None => (None, value),
+        };
+        let (time, s) = parse_time_component(s)?;
+        match date {
+            Some(date) => {
+                let (offset, s) = parse_time_zone_offset_component(s)?;
+                Some(DateOrTime::Global { date, time, offset }).filter(|_| s.is_empty())
+            }
+            None => Some(DateOrTime::Time(time)).filter(|_| s.is_empty()),
+        }
+    };
+    match parse() {
+        Some(date_or_time) => Ok(date_or_time),
+        None => invalid(value, "a date or time string"),
+    }
+}
+
+impl Attributes {
+    fn value<A: Into<LocalName>>(&self, local_name: A) -> Result<&str, AttributeError> {
+        self.get(local_name).ok_or(AttributeError::Missing)
+    }
+
+    /// Return whether a boolean attribute, such as `disabled`, is present.
+    #[inline]
+    pub fn get_bool<A: Into<LocalName>>(&self, local_name: A) -> bool {
+        self.contains(local_name)
+    }
+
+    /// Parse a signed integer, such as a `tabindex`.
+    ///
+    /// Leading whitespace is skipped and trailing characters are ignored, as in HTML.
+    pub fn get_integer<A: Into<LocalName>>(&self, local_name: A) -> Result<i64, AttributeError> {
+        parse_integer(self.value(local_name)?)
+    }
+
+    /// Parse a non-negative integer, such as a `colspan`.
+    pub fn get_non_negative_integer<A: Into<LocalName>>(
+        &self,
+        local_name: A,
+    ) -> Result<u64, AttributeError> {
+        let value = self.value(local_name)?;
+        match parse_integer(value)? {
+            n if n >= 0 => Ok(n as u64),
+            _ => invalid(value, "a non-negative integer"),
+        }
+    }
+
+    /// Parse a floating-point number, such as the `value` of a `<meter>`.
+    pub fn get_float<A: Into<LocalName>>(&self, local_name: A) -> Result<f64, AttributeError> {
+        parse_float(self.value(local_name)?)
+    }
+
+    /// Parse a dimension, such as the `width` of a `<td>`: a length or a percentage.
+    pub fn get_dimension<A: Into<LocalName>>(
+        &self,
+        local_name: A,
+    ) -> Result<Dimension, AttributeError> {
+        parse_dimension(self.value(local_name)?)
+    }
+
+    /// Parse a set of space-separated tokens, such as a `rel`,
+    /// in order and without duplicates.
+    pub fn get_tokens<A: Into<LocalName>>(
+        &self,
+        local_name: A,
+    ) -> Result<Vec<String>, AttributeError> {
+        let mut tokens: Vec<String> = Vec::new();
+        for token in self.value(local_name)?.split_ascii_whitespace() {
+            if !tokens.iter().any(|t| t == token) {
+                tokens.push(token.to_owned())
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Parse a date, a time, or a date and time with a time-zone offset,
+    /// such as the `datetime` of an `<ins>`.
+    ///
+    /// The other forms allowed on `<time>`, such as months, weeks and durations,
+    /// are not supported.
+    pub fn get_datetime<A: Into<LocalName>>(
+        &self,
+        local_name: A,
+    ) -> Result<DateOrTime, AttributeError> {
+        parse_date_or_time(self.value(local_name)?)
+    }
+
+    /// Parse the image candidates of a `srcset`.
+    ///
+    /// As in HTML, candidates with invalid descriptors are dropped rather than failing.
+    pub fn get_srcset<A: Into<LocalName>>(
+        &self,
+        local_name: A,
+    ) -> Result<Vec<ImageCandidate>, AttributeError> {
+        Ok(parse_srcset(self.value(local_name)?))
+    }
+}
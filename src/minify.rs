@@ -0,0 +1,490 @@
+//! Minified HTML serialization.
+//!
+//! <https://html.spec.whatwg.org/multipage/#syntax-tag-omission>
+
+use html5ever::tree_builder::QuirksMode;
+use html5ever::LocalName;
+use std::io::{self, Write};
+
+use crate::html_elements::{
+    collapse_whitespace, html_name, is_block, is_raw_text, is_void, is_whitespace_sensitive,
+};
+use crate::serializer::{into_ok, IoOutput, Output};
+use crate::tree::{ElementData, NodeData, NodeRef};
+
+impl NodeRef {
+    /// Serialize this node and its descendants in minified HTML syntax to the given stream.
+    ///
+    /// Whitespace that does not affect rendering is collapsed or removed,
+    /// comments other than conditional comments are dropped,
+    /// optional start and end tags of descendants are omitted,
+    /// attribute values are unquoted where possible,
+    /// and boolean attributes are written without a value.
+    ///
+    /// For a tree as produced by the parser, parsing the output gives back the same tree
+    /// except for these whitespace and comment changes.
+    /// As with the parser’s default options, scripting is assumed to be enabled:
+    /// the contents of `<noscript>` are written as raw text, like those of `<script>`.
+    pub fn serialize_minified<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        Minifier::new(&mut IoOutput(writer), self).write_node(self)
+    }
+
+    /// Return the result of `serialize_minified` as a string.
+    pub fn to_minified_string(&self) -> String {
+        let mut text = String::new();
+        into_ok(Minifier::new(&mut text, self).write_node(self));
+        text
+    }
+}
+
+/// Where an element is in its parent, for tag omission.
+struct Context<'a> {
+    parent: Option<&'a ElementData>,
+    previous: Option<&'a NodeRef>,
+    next: Option<&'a NodeRef>,
+}
+
+/// An element, document or document fragment whose children are being written.
+struct Frame {
+    node: NodeRef,
+    children: Vec<NodeRef>,
+    next_child: usize,
+    preserve_whitespace: bool,
+    write_end_tag: bool,
+}
+
+struct Minifier<'a, O> {
+    output: &'a mut O,
+    /// Whether the document being written is in quirks mode,
+    /// where a `<table>` start tag does not close a `<p>` element.
+    quirks_mode: bool,
+}
+
+impl<'a, O: Output> Minifier<'a, O> {
+    fn new(output: &'a mut O, node: &NodeRef) -> Self {
+        let root = node.inclusive_ancestors().last().unwrap();
+        let quirks_mode = root
+            .as_document()
+            .is_some_and(|document| document.quirks_mode() == QuirksMode::Quirks);
+        Minifier {
+            output,
+            quirks_mode,
+        }
+    }
+
+    fn write_node(&mut self, node: &NodeRef) -> Result<(), O::Error> {
+        let mut stack = Vec::new();
+        stack.extend(self.start_node(node, false, None)?);
+        while let Some(frame) = stack.last_mut() {
+            let i = frame.next_child;
+            let child = match frame.children.get(i) {
+                Some(child) => child.clone(),
+                None => {
+                    let frame = stack.pop().unwrap();
+                    if frame.write_end_tag {
+                        let element = frame.node.as_element().unwrap();
+                        self.output.write_str("</")?;
+                        self.output.write_str(&element.name.local)?;
+                        self.output.write_str(">")?
+                    }
+                    continue;
+                }
+            };
+            frame.next_child += 1;
+            let frame = &*frame;
+            let context = Context {
+                parent: frame.node.as_element(),
+                previous: i.checked_sub(1).map(|i| &frame.children[i]),
+                next: frame.children.get(i + 1),
+            };
+            let child_frame = self.start_node(&child, frame.preserve_whitespace, Some(context))?;
+            stack.extend(child_frame);
+        }
+        Ok(())
+    }
+
+    /// Write a node, or the start of one whose children are to be written next.
+    fn start_node(
+        &mut self,
+        node: &NodeRef,
+        preserve_whitespace: bool,
+        context: Option<Context>,
+    ) -> Result<Option<Frame>, O::Error> {
+        match node.data() {
+            NodeData::Element(element) => {
+                return self.start_element(node, element, preserve_whitespace, context)
+            }
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                return Ok(Some(Frame {
+                    node: node.clone(),
+                    children: output_children(node, None, preserve_whitespace),
+                    next_child: 0,
+                    preserve_whitespace,
+                    write_end_tag: false,
+                }))
+            }
+            NodeData::Text(text) => {
+                let text = text.borrow();
+                let parent = context.and_then(|context| context.parent);
+                if parent.is_some_and(|parent| is_raw_text(&parent.name, true)) {
+                    self.output.write_str(&text)?
+                } else if preserve_whitespace {
+                    self.write_escaped(&text, None)?
+                } else {
+                    self.write_escaped(&collapse_whitespace(&text), None)?
+                }
+            }
+            NodeData::Comment(text) => {
+                self.output.write_str("<!--")?;
+                self.output.write_str(&text.borrow())?;
+                self.output.write_str("-->")?
+            }
+            NodeData::Doctype(doctype) => {
+                self.output.write_str("<!DOCTYPE ")?;
+                self.output.write_str(&doctype.name)?;
+                self.output.write_str(">")?
+            }
+            NodeData::ProcessingInstruction(contents) => {
+                let contents = contents.borrow();
+                self.output.write_str("<?")?;
+                self.output.write_str(&contents.0)?;
+                self.output.write_str(" ")?;
+                self.output.write_str(&contents.1)?;
+                self.output.write_str(">")?
+            }
+        }
+        Ok(None)
+    }
+
+    fn start_element(
+        &mut self,
+        node: &NodeRef,
+        element: &ElementData,
+        preserve_whitespace: bool,
+        context: Option<Context>,
+    ) -> Result<Option<Frame>, O::Error> {
+        let preserve_whitespace = preserve_whitespace || is_whitespace_sensitive(&element.name);
+        let children = output_children(node, Some(element), preserve_whitespace);
+        let omit_start_tag = context
+            .as_ref()
+            .is_some_and(|context| can_omit_start_tag(element, &children, context));
+        if !omit_start_tag {
+            self.write_start_tag(element)?
+        }
+        if is_void(&element.name) {
+            return Ok(None);
+        }
+
+        // The parser drops a newline right after these start tags.
+        if element.name.ns == ns!(html)
+            && matches!(
+                element.name.local,
+                local_name!("pre") | local_name!("textarea") | local_name!("listing")
+            )
+            && children
+                .first()
+                .and_then(|child| child.as_text())
+                .is_some_and(|text| text.borrow().starts_with('\n'))
+        {
+            self.output.write_str("\n")?
+        }
+
+        let omit_end_tag = context
+            .as_ref()
+            .is_some_and(|context| can_omit_end_tag(element, context, self.quirks_mode));
+        Ok(Some(Frame {
+            node: node.clone(),
+            children,
+            next_child: 0,
+            preserve_whitespace,
+            write_end_tag: !omit_end_tag,
+        }))
+    }
+
+    fn write_start_tag(&mut self, element: &ElementData) -> Result<(), O::Error> {
+        self.output.write_str("<")?;
+        self.output.write_str(&element.name.local)?;
+        let attributes = element.attributes.borrow();
+        for (name, attribute) in &attributes.map {
+            self.output.write_str(" ")?;
+            match name.ns {
+                ns!() => {}
+                ns!(xml) => self.output.write_str("xml:")?,
+                ns!(xmlns) if name.local != local_name!("xmlns") => {
+                    self.output.write_str("xmlns:")?
+                }
+                ns!(xlink) => self.output.write_str("xlink:")?,
+                _ => {
+                    if let Some(prefix) = &attribute.prefix {
+                        self.output.write_str(prefix)?;
+                        self.output.write_str(":")?
+                    }
+                }
+            }
+            self.output.write_str(&name.local)?;
+
+            let value = &*attribute.value;
+            if value.is_empty()
+                || (name.ns == ns!()
+                    && element.name.ns == ns!(html)
+                    && is_boolean_attribute(&name.local)
+                    && value.eq_ignore_ascii_case(&name.local))
+            {
+                continue;
+            }
+            self.output.write_str("=")?;
+            if value.contains(|c: char| {
+                c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`')
+            }) {
+                let (quote, quote_str) = if value.contains('"') && !value.contains('\'') {
+                    ('\'', "'")
+                } else {
+                    ('"', "\"")
+                };
+                self.output.write_str(quote_str)?;
+                self.write_escaped(value, Some(quote))?;
+                self.output.write_str(quote_str)?
+            } else {
+                self.write_escaped(value, None)?
+            }
+        }
+        self.output.write_str(">")
+    }
+
+    /// Escape text, or an attribute value delimited by `quote`.
+    fn write_escaped(&mut self, text: &str, quote: Option<char>) -> Result<(), O::Error> {
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            let escaped = match c {
+                '&' => "&amp;",
+                '<' if quote.is_none() => "&lt;",
+                '"' if quote == Some('"') => "&quot;",
+                '\'' if quote == Some('\'') => "&#39;",
+                _ => continue,
+            };
+            self.output.write_str(&text[start..i])?;
+            self.output.write_str(escaped)?;
+            start = i + c.len_utf8();
+        }
+        self.output.write_str(&text[start..])
+    }
+}
+
+/// The children of a node that are written: without comments (except conditional comments)
+/// or whitespace-only text next to block-level elements.
+fn output_children(
+    node: &NodeRef,
+    element: Option<&ElementData>,
+    preserve_whitespace: bool,
+) -> Vec<NodeRef> {
+    let children: Vec<NodeRef> = node
+        .children()
+        .filter(|child| match child.data() {
+            NodeData::Comment(text) => is_conditional_comment(&text.borrow()),
+            NodeData::Text(text) => !text.borrow().is_empty(),
+            _ => true,
+        })
+        .collect();
+    if preserve_whitespace || element.is_some_and(|element| !is_block(&element.name)) {
+        return children;
+    }
+
+    let is_inline = |child: Option<&NodeRef>| match child.map(|child| child.data()) {
+        Some(NodeData::Element(element)) => !is_block(&element.name),
+        Some(NodeData::Text(_)) => true,
+        _ => false,
+    };
+    (0..children.len())
+        .filter(|&i| {
+            let is_whitespace = children[i]
+                .as_text()
+                .is_some_and(|text| text.borrow().bytes().all(|b| b.is_ascii_whitespace()));
+            !is_whitespace
+                || (is_inline(i.checked_sub(1).map(|i| &children[i]))
+                    && is_inline(children.get(i + 1)))
+        })
+        .map(|i| children[i].clone())
+        .collect()
+}
+
+/// Comments that old versions of Internet Explorer interpret, such as
+/// `<!--[if IE]>…<![endif]-->`, or the `<!--[if !IE]><!-->`, `<!--<![endif]-->`,
+/// `<![if !IE]>` and `<![endif]>` that surround content for other browsers.
+fn is_conditional_comment(text: &str) -> bool {
+    let text = text.strip_prefix("<!").unwrap_or(text).as_bytes();
+    let starts_with = |prefix: &[u8]| {
+        text.len() >= prefix.len() && text[..prefix.len()].eq_ignore_ascii_case(prefix)
+    };
+    starts_with(b"[if") || starts_with(b"[endif]")
+}
+
+fn node_html_name(node: Option<&NodeRef>) -> Option<LocalName> {
+    node.and_then(|node| html_name(node.as_element()?).cloned())
+}
+
+/// <https://html.spec.whatwg.org/multipage/#boolean-attribute>
+fn is_boolean_attribute(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("allowfullscreen")
+            | local_name!("async")
+            | local_name!("autofocus")
+            | local_name!("autoplay")
+            | local_name!("checked")
+            | local_name!("controls")
+            | local_name!("default")
+            | local_name!("defer")
+            | local_name!("disabled")
+            | local_name!("formnovalidate")
+            | local_name!("hidden")
+            | local_name!("ismap")
+            | local_name!("itemscope")
+            | local_name!("loop")
+            | local_name!("multiple")
+            | local_name!("muted")
+            | local_name!("nomodule")
+            | local_name!("novalidate")
+            | local_name!("open")
+            | local_name!("readonly")
+            | local_name!("required")
+            | local_name!("reversed")
+            | local_name!("selected")
+    )
+}
+
+fn is_whitespace_text(node: Option<&NodeRef>) -> bool {
+    node.and_then(|node| node.as_text())
+        .is_some_and(|text| text.borrow().starts_with(|c: char| c.is_ascii_whitespace()))
+}
+
+fn is_comment(node: Option<&NodeRef>) -> bool {
+    node.is_some_and(|node| node.as_comment().is_some())
+}
+
+fn can_omit_start_tag(element: &ElementData, children: &[NodeRef], context: &Context) -> bool {
+    if !element.attributes.borrow().map.is_empty() {
+        return false;
+    }
+    let first = children.first();
+    match html_name(element) {
+        Some(&local_name!("html")) => !is_comment(first),
+        Some(&local_name!("head")) => first.map_or(true, |first| first.as_element().is_some()),
+        Some(&local_name!("body")) => {
+            !is_whitespace_text(first)
+                && !is_comment(first)
+                // Mostly elements that the "after head" insertion mode puts in the head.
+                && !matches!(
+                    node_html_name(first),
+                    Some(local_name!("base"))
+                        | Some(local_name!("basefont"))
+                        | Some(local_name!("bgsound"))
+                        | Some(local_name!("link"))
+                        | Some(local_name!("meta"))
+                        | Some(local_name!("noframes"))
+                        | Some(local_name!("noscript"))
+                        | Some(local_name!("script"))
+                        | Some(local_name!("style"))
+                        | Some(local_name!("template"))
+                        | Some(local_name!("title"))
+                )
+        }
+        // Not after a table section or column group whose end tag may have been omitted.
+        Some(&local_name!("colgroup")) => {
+            node_html_name(first) == Some(local_name!("col"))
+                && node_html_name(context.previous) != Some(local_name!("colgroup"))
+        }
+        Some(&local_name!("tbody")) => {
+            node_html_name(first) == Some(local_name!("tr"))
+                && !matches!(
+                    node_html_name(context.previous),
+                    Some(local_name!("tbody"))
+                        | Some(local_name!("thead"))
+                        | Some(local_name!("tfoot"))
+                )
+        }
+        _ => false,
+    }
+}
+
+fn can_omit_end_tag(element: &ElementData, context: &Context, quirks_mode: bool) -> bool {
+    let next = node_html_name(context.next);
+    let is_last = context.next.is_none();
+    let next_is = |names: &[LocalName]| next.as_ref().is_some_and(|next| names.contains(next));
+    match html_name(element) {
+        Some(&local_name!("html")) | Some(&local_name!("body")) => !is_comment(context.next),
+        Some(&local_name!("head"))
+        | Some(&local_name!("colgroup"))
+        | Some(&local_name!("caption")) => {
+            !is_whitespace_text(context.next) && !is_comment(context.next)
+        }
+        Some(&local_name!("li")) => is_last || next_is(&[local_name!("li")]),
+        Some(&local_name!("dt")) => next_is(&[local_name!("dt"), local_name!("dd")]),
+        Some(&local_name!("dd")) => is_last || next_is(&[local_name!("dd"), local_name!("dt")]),
+        Some(&local_name!("rt")) | Some(&local_name!("rp")) => {
+            is_last || next_is(&[local_name!("rt"), local_name!("rp")])
+        }
+        Some(&local_name!("optgroup")) => is_last || next_is(&[local_name!("optgroup")]),
+        Some(&local_name!("option")) => {
+            is_last || next_is(&[local_name!("option"), local_name!("optgroup")])
+        }
+        Some(&local_name!("thead")) => next_is(&[local_name!("tbody"), local_name!("tfoot")]),
+        Some(&local_name!("tbody")) => {
+            is_last || next_is(&[local_name!("tbody"), local_name!("tfoot")])
+        }
+        Some(&local_name!("tfoot")) => is_last,
+        Some(&local_name!("tr")) => is_last || next_is(&[local_name!("tr")]),
+        Some(&local_name!("td")) | Some(&local_name!("th")) => {
+            is_last || next_is(&[local_name!("td"), local_name!("th")])
+        }
+        Some(&local_name!("p")) => {
+            next_is(&[
+                local_name!("address"),
+                local_name!("article"),
+                local_name!("aside"),
+                local_name!("blockquote"),
+                local_name!("details"),
+                local_name!("dialog"),
+                local_name!("div"),
+                local_name!("dl"),
+                local_name!("fieldset"),
+                local_name!("figcaption"),
+                local_name!("figure"),
+                local_name!("footer"),
+                local_name!("form"),
+                local_name!("h1"),
+                local_name!("h2"),
+                local_name!("h3"),
+                local_name!("h4"),
+                local_name!("h5"),
+                local_name!("h6"),
+                local_name!("header"),
+                local_name!("hgroup"),
+                local_name!("hr"),
+                local_name!("main"),
+                local_name!("menu"),
+                local_name!("nav"),
+                local_name!("ol"),
+                local_name!("p"),
+                local_name!("pre"),
+                local_name!("section"),
+                local_name!("ul"),
+            ]) || (next_is(&[local_name!("table")]) && !quirks_mode)
+                || (is_last
+                    && context.parent.is_some_and(|parent| {
+                        parent.name.ns == ns!(html)
+                            && !parent.name.local.contains('-')
+                            && !matches!(
+                                parent.name.local,
+                                local_name!("a")
+                                    | local_name!("audio")
+                                    | local_name!("del")
+                                    | local_name!("ins")
+                                    | local_name!("map")
+                                    | local_name!("noscript")
+                                    | local_name!("video")
+                            )
+                    }))
+        }
+        _ => false,
+    }
+}
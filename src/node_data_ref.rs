@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 use std::fmt;
 use std::ops::Deref;
+use crate::select::IntoSelectors;
 use crate::tree::{Doctype, DocumentData, ElementData, Node, NodeRef};
 
 impl NodeRef {
@@ -50,7 +51,7 @@ impl<T> NodeDataRef<T> {
         F: FnOnce(&Node) -> &T,
     {
         NodeDataRef {
-            _reference: f(&*rc),
+            _reference: f(&rc),
             _keep_alive: rc,
         }
     }
@@ -61,7 +62,7 @@ impl<T> NodeDataRef<T> {
     where
         F: FnOnce(&Node) -> Option<&T>,
     {
-        f(&*rc).map(|r| r as *const T).map(move |r| NodeDataRef {
+        f(&rc).map(|r| r as *const T).map(move |r| NodeDataRef {
             _reference: r,
             _keep_alive: rc,
         })
@@ -108,9 +109,29 @@ impl<T: fmt::Debug> fmt::Debug for NodeDataRef<T> {
     }
 }
 
+#[allow(clippy::result_unit_err)] // Selector errors, like `Selectors::compile`
 impl NodeDataRef<ElementData> {
     /// Return the concatenation of all text nodes in this subtree.
     pub fn text_contents(&self) -> String {
         self.as_node().text_contents()
     }
+
+    /// Return whether this element matches the given selector list.
+    ///
+    /// See `NodeRef::matches`.
+    #[inline]
+    pub fn matches<'a, S: IntoSelectors<'a>>(&self, selectors: S) -> Result<bool, ()> {
+        self.as_node().matches(selectors)
+    }
+
+    /// Return the nearest inclusive ancestor element that matches the given selector list.
+    ///
+    /// See `NodeRef::closest`.
+    #[inline]
+    pub fn closest<'a, S: IntoSelectors<'a>>(
+        &self,
+        selectors: S,
+    ) -> Result<Option<NodeDataRef<ElementData>>, ()> {
+        self.as_node().closest(selectors)
+    }
 }
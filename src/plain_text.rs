@@ -0,0 +1,869 @@
+//! Rendering of a tree as plain text, with a simple block layout.
+
+use crate::html_elements::{
+    collapse_whitespace, html_name, is_block, is_not_rendered, is_whitespace_sensitive,
+};
+use crate::iter::{NodeEdge, NodeIterator};
+use crate::tree::{ElementData, NodeData, NodeRef};
+
+/// Options for `NodeRef::to_plain_text_with_options`.
+#[derive(Debug, Clone)]
+pub struct PlainTextOpts {
+    /// The maximum line length, in characters, at which to wrap text.
+    /// Words longer than this, preformatted text and tables are not wrapped.
+    ///
+    /// Default: `Some(80)`
+    pub wrap_width: Option<usize>,
+
+    /// Whether to number links with `[1]`, `[2]`, etc.
+    /// and list their URLs at the end of the text.
+    ///
+    /// Default: true
+    pub link_footnotes: bool,
+}
+
+impl Default for PlainTextOpts {
+    fn default() -> PlainTextOpts {
+        PlainTextOpts {
+            wrap_width: Some(80),
+            link_footnotes: true,
+        }
+    }
+}
+
+impl NodeRef {
+    /// Render this node and its descendants as plain text, with the default options.
+    ///
+    /// Unlike `text_contents`, this skips the same elements as `inner_text`,
+    /// such as `<script>` or those with a `hidden` attribute,
+    /// puts blocks on their own lines, and lays out lists, tables and preformatted text.
+    pub fn to_plain_text(&self) -> String {
+        self.to_plain_text_with_options(&PlainTextOpts::default())
+    }
+
+    /// Render this node and its descendants as plain text, with the given options.
+    pub fn to_plain_text_with_options(&self, opts: &PlainTextOpts) -> String {
+        let mut renderer = Renderer {
+            opts,
+            footnotes: Vec::new(),
+        };
+        let lines = match self.data() {
+            NodeData::Element(element) if is_rendered(element) => {
+                renderer.render(Frame::block(self, element, opts.wrap_width, Delivery::Root))
+            }
+            NodeData::Element(_) => Vec::new(),
+            NodeData::Text(_) => {
+                let mut inline = String::new();
+                renderer.inline(self, &mut inline);
+                wrap(&inline, opts.wrap_width)
+            }
+            _ => renderer.render(Frame::flow(self, opts.wrap_width, None, Delivery::Root)),
+        };
+
+        let mut text = String::new();
+        for line in &lines {
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        if !renderer.footnotes.is_empty() {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            for (i, url) in renderer.footnotes.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", i + 1, url));
+            }
+        }
+        text
+    }
+}
+
+impl NodeRef {
+    /// Return the text of this node and its descendants as rendered,
+    /// like the `innerText` DOM attribute but without layout.
+    ///
+    /// Elements that are not rendered are skipped: `<head>`, `<script>`, `<style>`,
+    /// `<template>` and the like, elements with a `hidden` attribute,
+    /// and elements with `display: none` in their `style` attribute.
+    /// Whitespace is collapsed except in preformatted elements such as `<pre>`.
+    /// Block-level elements are on their own lines, paragraphs are separated by blank lines,
+    /// table cells by tabs and table rows by line breaks.
+    ///
+    /// <https://html.spec.whatwg.org/multipage/#the-innertext-idl-attribute>
+    pub fn inner_text(&self) -> String {
+        let preformatted = self.inclusive_ancestors().any(|node| {
+            node.as_element()
+                .is_some_and(|element| is_whitespace_sensitive(&element.name))
+        });
+        let mut items = Vec::new();
+        if self.as_element().is_some() || self.as_document().is_some() {
+            for child in self.children() {
+                inner_text_items(&child, preformatted, &mut items)
+            }
+        } else {
+            inner_text_items(self, preformatted, &mut items)
+        }
+
+        let mut text = String::new();
+        let mut required_line_breaks = 0;
+        let mut pending_space = false;
+        for item in items {
+            let (string, collapsible) = match item {
+                InnerTextItem::RequiredLineBreaks(count) => {
+                    required_line_breaks = required_line_breaks.max(count);
+                    pending_space = false;
+                    continue;
+                }
+                InnerTextItem::Text(string, collapsible) => (string, collapsible),
+            };
+            for c in string.chars() {
+                let at_line_start =
+                    required_line_breaks > 0 || text.is_empty() || text.ends_with('\n');
+                if collapsible && c == ' ' {
+                    pending_space = !at_line_start;
+                    continue;
+                }
+                if required_line_breaks > 0 {
+                    if !text.is_empty() {
+                        text.push_str(&"\n".repeat(required_line_breaks))
+                    }
+                    required_line_breaks = 0;
+                } else if pending_space && c != '\n' {
+                    text.push(' ')
+                }
+                pending_space = false;
+                text.push(c)
+            }
+        }
+        text
+    }
+}
+
+enum InnerTextItem {
+    /// A string, and whether its spaces are collapsible.
+    Text(String, bool),
+    RequiredLineBreaks(usize),
+}
+
+/// <https://html.spec.whatwg.org/multipage/#rendered-text-collection-steps>
+fn inner_text_items(node: &NodeRef, preformatted: bool, items: &mut Vec<InnerTextItem>) {
+    // An element whose descendants are skipped, and the number of open preformatted elements.
+    let mut skipped = None;
+    let mut open_preformatted = 0;
+    for edge in node.traverse_inclusive() {
+        match edge {
+            NodeEdge::Start(_) if skipped.is_some() => {}
+            NodeEdge::Start(node) => {
+                let element = match node.data() {
+                    NodeData::Text(text) => {
+                        let text = text.borrow();
+                        if preformatted || open_preformatted > 0 {
+                            items.push(InnerTextItem::Text(text.clone(), false))
+                        } else {
+                            items.push(InnerTextItem::Text(collapse_whitespace(&text), true))
+                        }
+                        continue;
+                    }
+                    NodeData::Element(element) => element,
+                    _ => continue,
+                };
+                if !is_rendered(element) {
+                    skipped = Some(node.clone());
+                    continue;
+                }
+                if html_name(element) == Some(&local_name!("br")) {
+                    items.push(InnerTextItem::Text("\n".to_owned(), false));
+                    skipped = Some(node.clone());
+                    continue;
+                }
+                let required_line_breaks = required_line_breaks(element);
+                if required_line_breaks > 0 {
+                    items.push(InnerTextItem::RequiredLineBreaks(required_line_breaks))
+                }
+                if is_whitespace_sensitive(&element.name) {
+                    open_preformatted += 1
+                }
+            }
+            NodeEdge::End(node) => {
+                if skipped.is_some() {
+                    if skipped.as_ref() == Some(&node) {
+                        skipped = None
+                    }
+                    continue;
+                }
+                let element = match node.as_element() {
+                    Some(element) => element,
+                    None => continue,
+                };
+                if is_whitespace_sensitive(&element.name) {
+                    open_preformatted -= 1
+                }
+                let required_line_breaks = required_line_breaks(element);
+                if required_line_breaks > 0 {
+                    items.push(InnerTextItem::RequiredLineBreaks(required_line_breaks))
+                }
+                let is_followed_by_cell = || {
+                    node.following_siblings().elements().any(|sibling| {
+                        matches!(
+                            html_name(&sibling),
+                            Some(&local_name!("td")) | Some(&local_name!("th"))
+                        )
+                    })
+                };
+                match html_name(element) {
+                    Some(&local_name!("td")) | Some(&local_name!("th"))
+                        if is_followed_by_cell() =>
+                    {
+                        items.push(InnerTextItem::Text("\t".to_owned(), false))
+                    }
+                    Some(&local_name!("tr")) if is_followed_by_row(&node) => {
+                        items.push(InnerTextItem::Text("\n".to_owned(), false))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The number of line breaks required around an element’s text.
+fn required_line_breaks(element: &ElementData) -> usize {
+    match html_name(element) {
+        Some(&local_name!("p")) => 2,
+        Some(&local_name!("tbody"))
+        | Some(&local_name!("thead"))
+        | Some(&local_name!("tfoot"))
+        | Some(&local_name!("tr"))
+        | Some(&local_name!("td"))
+        | Some(&local_name!("th"))
+        | Some(&local_name!("col"))
+        | Some(&local_name!("colgroup")) => 0,
+        _ if is_block(&element.name) => 1,
+        _ => 0,
+    }
+}
+
+/// Whether an element and its descendants are rendered.
+fn is_rendered(element: &ElementData) -> bool {
+    !is_not_rendered(&element.name) && !is_hidden(element)
+}
+
+/// Whether an element is hidden with the `hidden` attribute or an inline `display: none`.
+fn is_hidden(element: &ElementData) -> bool {
+    let attributes = element.attributes.borrow();
+    attributes.contains(local_name!("hidden"))
+        || (attributes.contains(local_name!("style"))
+            && element
+                .style()
+                .get("display")
+                .is_some_and(|display| display.eq_ignore_ascii_case("none")))
+}
+
+/// Append the text of the rendered descendants of a node, as-is.
+fn rendered_text(node: &NodeRef, text: &mut String) {
+    let mut skipped = None;
+    for edge in node.traverse() {
+        match edge {
+            NodeEdge::Start(_) if skipped.is_some() => {}
+            NodeEdge::Start(child) => match child.data() {
+                NodeData::Text(contents) => text.push_str(&contents.borrow()),
+                NodeData::Element(element) if !is_rendered(element) => skipped = Some(child),
+                _ => {}
+            },
+            NodeEdge::End(child) => {
+                if skipped.as_ref() == Some(&child) {
+                    skipped = None
+                }
+            }
+        }
+    }
+}
+
+/// Whether a table row is followed by another in the same table.
+fn is_followed_by_row(row: &NodeRef) -> bool {
+    let is_row = |element: &ElementData| html_name(element) == Some(&local_name!("tr"));
+    if row
+        .following_siblings()
+        .elements()
+        .any(|sibling| is_row(&sibling))
+    {
+        return true;
+    }
+    let section = match row.parent() {
+        Some(section) => section,
+        None => return false,
+    };
+    section
+        .as_element()
+        .and_then(html_name)
+        .is_some_and(|name| {
+            matches!(
+                *name,
+                local_name!("thead") | local_name!("tbody") | local_name!("tfoot")
+            )
+        })
+        && section.following_siblings().elements().any(|sibling| {
+            sibling
+                .as_node()
+                .children()
+                .elements()
+                .any(|row| is_row(&row))
+        })
+}
+
+/// Lines of blocks, with blank lines around those with vertical margins.
+#[derive(Default)]
+struct Flow {
+    lines: Vec<String>,
+    blank_line_before_next: bool,
+}
+
+impl Flow {
+    fn push(&mut self, lines: Vec<String>, has_margins: bool) {
+        if lines.is_empty() {
+            return;
+        }
+        if !self.lines.is_empty() && (has_margins || self.blank_line_before_next) {
+            self.lines.push(String::new());
+        }
+        self.lines.extend(lines);
+        self.blank_line_before_next = has_margins;
+    }
+}
+
+/// Where the lines of a block go once it is rendered.
+enum Delivery {
+    /// Returned by `Renderer::render`.
+    Root,
+    /// In the flow of the parent block, with a blank line around if it has margins.
+    Block { has_margins: bool },
+    /// As the caption of the parent table.
+    Caption,
+    /// As the next cell of the given row of the parent table.
+    Cell(usize),
+}
+
+/// Prefixes for the first and other lines of a block, such as a list marker.
+struct Indent {
+    first: String,
+    rest: String,
+    /// Whether an empty block still has a line for the prefix, like an empty list item.
+    keep_empty: bool,
+}
+
+impl Indent {
+    fn new(first: &str, rest: &str) -> Indent {
+        Indent {
+            first: first.to_owned(),
+            rest: rest.to_owned(),
+            keep_empty: false,
+        }
+    }
+}
+
+/// A child of a list or table, rendered once the previous one is done.
+enum Item {
+    /// An element rendered as a block.
+    Block(NodeRef, Option<usize>),
+    /// The children of a node rendered as a flow.
+    Flow(NodeRef, Option<usize>, Option<Indent>, Delivery),
+}
+
+enum Content {
+    /// Paragraphs of inline content and blocks, from the children of a node.
+    Flow {
+        flow: Flow,
+        inline: String,
+        next_child: Option<NodeRef>,
+        width: Option<usize>,
+    },
+    /// Blocks from list or definition list items. `items` is in reverse order.
+    Items { flow: Flow, items: Vec<Item> },
+    /// A table: its caption, and for each row whether it is a header row
+    /// and the lines of each cell. `items` is in reverse order.
+    Table {
+        caption: Vec<String>,
+        rows: Vec<(bool, Vec<Vec<String>>)>,
+        items: Vec<Item>,
+    },
+    /// Lines that are already rendered.
+    Lines(Vec<String>),
+}
+
+/// A block being rendered.
+struct Frame {
+    content: Content,
+    indent: Option<Indent>,
+    delivery: Delivery,
+}
+
+impl Frame {
+    fn flow(
+        node: &NodeRef,
+        width: Option<usize>,
+        indent: Option<Indent>,
+        delivery: Delivery,
+    ) -> Frame {
+        Frame {
+            content: Content::Flow {
+                flow: Flow::default(),
+                inline: String::new(),
+                next_child: node.first_child(),
+                width,
+            },
+            indent,
+            delivery,
+        }
+    }
+
+    fn items(items: Vec<Item>, delivery: Delivery) -> Frame {
+        Frame {
+            content: Content::Items {
+                flow: Flow::default(),
+                items: items.into_iter().rev().collect(),
+            },
+            indent: None,
+            delivery,
+        }
+    }
+
+    /// Start rendering a block-level element.
+    fn block(
+        node: &NodeRef,
+        element: &ElementData,
+        width: Option<usize>,
+        delivery: Delivery,
+    ) -> Frame {
+        let name = match html_name(element) {
+            Some(name) => name,
+            None => return Frame::flow(node, width, None, delivery),
+        };
+        match *name {
+            local_name!("h1")
+            | local_name!("h2")
+            | local_name!("h3")
+            | local_name!("h4")
+            | local_name!("h5")
+            | local_name!("h6") => {
+                let level = usize::from(name.as_bytes()[1] - b'0');
+                let prefix = format!("{} ", "#".repeat(level));
+                let indent = Indent::new(&prefix, &" ".repeat(prefix.len()));
+                Frame::flow(node, narrow(width, prefix.len()), Some(indent), delivery)
+            }
+            local_name!("ul") | local_name!("ol") | local_name!("menu") => {
+                let ordered = *name == local_name!("ol");
+                let mut number = if ordered {
+                    element
+                        .attributes
+                        .borrow()
+                        .get(local_name!("start"))
+                        .and_then(|start| start.trim().parse::<i64>().ok())
+                        .unwrap_or(1)
+                } else {
+                    1
+                };
+                let mut items = Vec::new();
+                for child in node.children() {
+                    let item = match child.as_element() {
+                        Some(item) if is_rendered(item) => item,
+                        _ => continue,
+                    };
+                    if html_name(item) != Some(&local_name!("li")) {
+                        items.push(Item::Block(child.clone(), width));
+                        continue;
+                    }
+                    let marker = if ordered {
+                        format!("{}. ", number)
+                    } else {
+                        "* ".to_owned()
+                    };
+                    number = number.saturating_add(1);
+                    let indent = Indent {
+                        keep_empty: true,
+                        ..Indent::new(&marker, &" ".repeat(marker.len()))
+                    };
+                    let width = narrow(width, marker.len());
+                    let delivery = Delivery::Block { has_margins: false };
+                    items.push(Item::Flow(child.clone(), width, Some(indent), delivery))
+                }
+                Frame::items(items, delivery)
+            }
+            local_name!("dl") => {
+                let mut items = Vec::new();
+                for child in node.children() {
+                    let item = match child.as_element() {
+                        Some(item) if is_rendered(item) => item,
+                        _ => continue,
+                    };
+                    let delivery = Delivery::Block { has_margins: false };
+                    if html_name(item) == Some(&local_name!("dd")) {
+                        let indent = Indent::new("  ", "  ");
+                        items.push(Item::Flow(
+                            child.clone(),
+                            narrow(width, 2),
+                            Some(indent),
+                            delivery,
+                        ))
+                    } else {
+                        items.push(Item::Flow(child.clone(), width, None, delivery))
+                    }
+                }
+                Frame::items(items, delivery)
+            }
+            local_name!("blockquote") => {
+                let indent = Indent::new("> ", "> ");
+                Frame::flow(node, narrow(width, 2), Some(indent), delivery)
+            }
+            local_name!("pre") | local_name!("listing") | local_name!("xmp") => {
+                let mut text = String::new();
+                rendered_text(node, &mut text);
+                let text = text.strip_suffix('\n').unwrap_or(&text);
+                let lines = text.split('\n').map(str::to_owned).collect();
+                Frame::lines(lines, delivery)
+            }
+            local_name!("hr") => Frame::lines(vec!["-".repeat(width.unwrap_or(80))], delivery),
+            local_name!("table") => Frame::table(node, delivery),
+            _ => Frame::flow(node, width, None, delivery),
+        }
+    }
+
+    fn lines(lines: Vec<String>, delivery: Delivery) -> Frame {
+        Frame {
+            content: Content::Lines(lines),
+            indent: None,
+            delivery,
+        }
+    }
+
+    /// Start rendering a table, with its captions and then its cells.
+    fn table(table: &NodeRef, delivery: Delivery) -> Frame {
+        let mut items = Vec::new();
+        let mut rows = Vec::new();
+        for child in table.children() {
+            match child.as_element().and_then(html_name) {
+                Some(&local_name!("caption")) => {
+                    items.push(Item::Flow(child, None, None, Delivery::Caption))
+                }
+                Some(&local_name!("tr")) => rows.push(child),
+                Some(&local_name!("thead"))
+                | Some(&local_name!("tbody"))
+                | Some(&local_name!("tfoot")) => rows.extend(child.children().filter(|row| {
+                    row.as_element().and_then(html_name) == Some(&local_name!("tr"))
+                })),
+                _ => {}
+            }
+        }
+
+        let mut table_rows = Vec::new();
+        for row in &rows {
+            let mut is_header = true;
+            let mut cells = Vec::new();
+            for cell in row.children() {
+                match cell.as_element().and_then(html_name) {
+                    Some(&local_name!("th")) => {}
+                    Some(&local_name!("td")) => is_header = false,
+                    _ => continue,
+                }
+                cells.push(cell)
+            }
+            if cells.is_empty() {
+                continue;
+            }
+            let row = table_rows.len();
+            table_rows.push((is_header, Vec::new()));
+            for cell in cells {
+                items.push(Item::Flow(cell, None, None, Delivery::Cell(row)))
+            }
+        }
+
+        Frame {
+            content: Content::Table {
+                caption: Vec::new(),
+                rows: table_rows,
+                items: items.into_iter().rev().collect(),
+            },
+            indent: None,
+            delivery,
+        }
+    }
+
+    /// Add the lines of a child block.
+    fn receive(&mut self, lines: Vec<String>, delivery: Delivery) {
+        match (&mut self.content, delivery) {
+            (Content::Flow { flow, .. }, Delivery::Block { has_margins })
+            | (Content::Items { flow, .. }, Delivery::Block { has_margins }) => {
+                flow.push(lines, has_margins)
+            }
+            (Content::Table { caption, .. }, Delivery::Caption) => *caption = lines,
+            (Content::Table { rows, .. }, Delivery::Cell(row)) => rows[row].1.push(lines),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the lines of this block, and where they go.
+    fn finish(self) -> (Vec<String>, Delivery) {
+        let lines = match self.content {
+            Content::Flow {
+                mut flow,
+                inline,
+                width,
+                ..
+            } => {
+                flow.push(wrap(&inline, width), false);
+                flow.lines
+            }
+            Content::Items { flow, .. } => flow.lines,
+            Content::Table { caption, rows, .. } => table_lines(caption, &rows),
+            Content::Lines(lines) => lines,
+        };
+        let lines = match self.indent {
+            Some(indent) if lines.is_empty() && indent.keep_empty => vec![indent.first],
+            Some(indent) => indent_lines(lines, &indent.first, &indent.rest),
+            None => lines,
+        };
+        (lines, self.delivery)
+    }
+}
+
+struct Renderer<'a> {
+    opts: &'a PlainTextOpts,
+    footnotes: Vec<String>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Render a block and its descendants, one block at a time.
+    fn render(&mut self, root: Frame) -> Vec<String> {
+        let mut stack = vec![root];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let child = match &mut frame.content {
+                Content::Flow {
+                    flow,
+                    inline,
+                    next_child,
+                    width,
+                } => match next_child.take() {
+                    Some(child) => {
+                        *next_child = child.next_sibling();
+                        match child.as_element() {
+                            Some(element) if !is_rendered(element) => continue,
+                            Some(element) if is_block(&element.name) => {
+                                flow.push(wrap(inline, *width), false);
+                                inline.clear();
+                                let delivery = Delivery::Block {
+                                    has_margins: has_margins(element),
+                                };
+                                Some(Frame::block(&child, element, *width, delivery))
+                            }
+                            _ => {
+                                self.inline(&child, inline);
+                                continue;
+                            }
+                        }
+                    }
+                    None => None,
+                },
+                Content::Items { items, .. } | Content::Table { items, .. } => {
+                    items.pop().map(|item| match item {
+                        Item::Block(node, width) => {
+                            let element = node.as_element().unwrap();
+                            let delivery = Delivery::Block { has_margins: false };
+                            Frame::block(&node, element, width, delivery)
+                        }
+                        Item::Flow(node, width, indent, delivery) => {
+                            Frame::flow(&node, width, indent, delivery)
+                        }
+                    })
+                }
+                Content::Lines(_) => None,
+            };
+            if let Some(child) = child {
+                stack.push(child);
+                continue;
+            }
+            let (lines, delivery) = stack.pop().unwrap().finish();
+            match stack.last_mut() {
+                Some(parent) => parent.receive(lines, delivery),
+                None => return lines,
+            }
+        }
+    }
+
+    /// Render inline content, with whitespace collapsed and `\n` for line breaks.
+    fn inline(&mut self, node: &NodeRef, inline: &mut String) {
+        // An element whose descendants are not rendered as inline content.
+        let mut skipped = None;
+        for edge in node.traverse_inclusive() {
+            let node = match edge {
+                NodeEdge::Start(_) if skipped.is_some() => continue,
+                NodeEdge::Start(node) => node,
+                NodeEdge::End(node) => {
+                    if skipped.as_ref() == Some(&node) {
+                        skipped = None
+                    } else if skipped.is_none() {
+                        if let Some(element) = node.as_element() {
+                            self.end_inline_element(element, inline)
+                        }
+                    }
+                    continue;
+                }
+            };
+            match node.data() {
+                NodeData::Text(text) => {
+                    for c in text.borrow().chars() {
+                        if !c.is_ascii_whitespace() {
+                            inline.push(c)
+                        } else if !inline.is_empty() && !inline.ends_with([' ', '\n']) {
+                            inline.push(' ')
+                        }
+                    }
+                }
+                NodeData::Element(element) if !is_rendered(element) => skipped = Some(node),
+                NodeData::Element(element) => match html_name(element) {
+                    Some(&local_name!("br")) => {
+                        line_break(inline);
+                        skipped = Some(node)
+                    }
+                    Some(&local_name!("img")) => {
+                        if let Some(alt) = element.attributes.borrow().get(local_name!("alt")) {
+                            if !alt.is_empty() {
+                                inline.push_str(&format!("[{}]", alt))
+                            }
+                        }
+                        skipped = Some(node)
+                    }
+                    // Blocks nested in inline content still start on a new line.
+                    _ if is_block(&element.name) => line_break(inline),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn end_inline_element(&mut self, element: &ElementData, inline: &mut String) {
+        if is_block(&element.name) {
+            line_break(inline)
+        }
+        if html_name(element) == Some(&local_name!("a")) && self.opts.link_footnotes {
+            let attributes = element.attributes.borrow();
+            match attributes.get(local_name!("href")) {
+                Some(href) if !href.is_empty() && !href.starts_with('#') => {
+                    self.footnotes.push(href.to_owned());
+                    inline.push_str(&format!("[{}]", self.footnotes.len()))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Lay out a table with aligned columns, without wrapping.
+fn table_lines(caption: Vec<String>, rows: &[(bool, Vec<Vec<String>>)]) -> Vec<String> {
+    let columns = rows.iter().map(|(_, row)| row.len()).max().unwrap_or(0);
+    let mut column_widths = vec![0; columns];
+    for (_, row) in rows {
+        for (i, cell) in row.iter().enumerate() {
+            for line in cell {
+                column_widths[i] = column_widths[i].max(line.chars().count())
+            }
+        }
+    }
+
+    let mut lines = caption;
+    for (is_header, row) in rows {
+        let height = row.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        for i in 0..height {
+            let line = column_widths
+                .iter()
+                .enumerate()
+                .map(|(column, &column_width)| {
+                    let text = row
+                        .get(column)
+                        .and_then(|cell| cell.get(i))
+                        .map_or("", String::as_str);
+                    let padding = column_width - text.chars().count();
+                    format!("{}{}", text, " ".repeat(padding))
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+            lines.push(line)
+        }
+        if *is_header {
+            let separator = column_widths
+                .iter()
+                .map(|&column_width| "-".repeat(column_width))
+                .collect::<Vec<_>>()
+                .join("-+-");
+            lines.push(separator)
+        }
+    }
+    lines
+}
+
+/// Whether this element is separated from other blocks by a blank line.
+fn has_margins(element: &ElementData) -> bool {
+    matches!(
+        html_name(element),
+        Some(&local_name!("p"))
+            | Some(&local_name!("h1"))
+            | Some(&local_name!("h2"))
+            | Some(&local_name!("h3"))
+            | Some(&local_name!("h4"))
+            | Some(&local_name!("h5"))
+            | Some(&local_name!("h6"))
+            | Some(&local_name!("ul"))
+            | Some(&local_name!("ol"))
+            | Some(&local_name!("dl"))
+            | Some(&local_name!("blockquote"))
+            | Some(&local_name!("pre"))
+            | Some(&local_name!("table"))
+            | Some(&local_name!("figure"))
+    )
+}
+
+fn line_break(inline: &mut String) {
+    if inline.ends_with(' ') {
+        inline.pop();
+    }
+    inline.push('\n')
+}
+
+fn narrow(width: Option<usize>, by: usize) -> Option<usize> {
+    width.map(|width| width.saturating_sub(by).max(1))
+}
+
+fn indent_lines(lines: Vec<String>, first: &str, rest: &str) -> Vec<String> {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| format!("{}{}", if i == 0 { first } else { rest }, line))
+        .collect()
+}
+
+/// Split inline content into lines at line breaks and, if given a width, between words.
+fn wrap(inline: &str, width: Option<usize>) -> Vec<String> {
+    let inline = inline.strip_suffix('\n').unwrap_or(inline);
+    if inline.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut lines = Vec::new();
+    for hard_line in inline.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in hard_line.split(' ').filter(|word| !word.is_empty()) {
+            let word_width = word.chars().count();
+            if line_width > 0 && width.is_some_and(|width| line_width + 1 + word_width > width) {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+        }
+        lines.push(line)
+    }
+    lines
+}
@@ -1,16 +1,26 @@
 use crate::attributes::ExpandedName;
-use cssparser::{self, CowRcStr, ParseError, SourceLocation, ToCss};
+use crate::element_state::{self, Direction};
+use cssparser::{self, CowRcStr, ParseError, SourceLocation, ToCss, Token};
 use html5ever::{LocalName, Namespace};
+use indexmap::IndexMap;
+#[cfg(feature = "regex")]
+use regex::{Regex, RegexBuilder};
 use crate::iter::{NodeIterator, Select};
 use crate::node_data_ref::NodeDataRef;
 use selectors::attr::{AttrSelectorOperation, CaseSensitivity, NamespaceConstraint};
 use selectors::context::QuirksMode;
 use selectors::parser::SelectorParseErrorKind;
 use selectors::parser::{
-    NonTSPseudoClass, Parser, Selector as GenericSelector, SelectorImpl, SelectorList,
+    Component, NonTSPseudoClass, Parser, Selector as GenericSelector, SelectorImpl,
 };
 use selectors::{self, matching, OpaqueElement};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::sync::Arc;
 use crate::tree::{ElementData, Node, NodeData, NodeRef};
 
 /// The definition of whitespace per CSS Selectors Level 3 § 4.
@@ -18,7 +28,7 @@ use crate::tree::{ElementData, Node, NodeData, NodeRef};
 /// Copied from rust-selectors.
 static SELECTOR_WHITESPACE: &[char] = &[' ', '\t', '\n', '\r', '\x0C'];
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KuchikiSelectors;
 
 impl SelectorImpl for KuchikiSelectors {
@@ -35,12 +45,196 @@ impl SelectorImpl for KuchikiSelectors {
     type NonTSPseudoClass = PseudoClass;
     type PseudoElement = PseudoElement;
 
-    type ExtraMatchingData = ();
+    type ExtraMatchingData = HasCache;
 }
 
-struct KuchikiParser;
+/// Results of `:has()` for anchor elements already seen while matching,
+/// kept for as long as the matching context.
+///
+/// Entries hold a reference to their node so that its address is not reused.
+#[derive(Default)]
+pub struct HasCache {
+    results: HashMap<(*const RelativeSelector, *const Node), (NodeRef, bool)>,
+}
+
+impl HasCache {
+    fn get(&self, selector: &RelativeSelector, node: &NodeRef) -> Option<bool> {
+        self.results
+            .get(&(selector as *const _, Rc::as_ptr(&node.0)))
+            .map(|&(_, result)| result)
+    }
+
+    fn insert(&mut self, selector: &RelativeSelector, node: &NodeRef, result: bool) {
+        self.results.insert(
+            (selector as *const _, Rc::as_ptr(&node.0)),
+            (node.clone(), result),
+        );
+    }
+}
+
+/// Options for compiling selectors.
+#[derive(Clone, Debug, Default)]
+pub struct SelectorOpts {
+    /// Whether to accept the non-standard text pseudo-classes.
+    ///
+    /// * `:contains("text")` matches elements whose text contents contain the given string.
+    /// * `:icontains("text")` is the same, but ignores case.
+    /// * `:matches-text(/regex/flags)` matches elements whose text contents match
+    ///   the given regular expression. Supported flags are `i`, `m`, `s` and `x`.
+    ///   The pattern can also be given as a quoted string, without flags.
+    ///   Requires the `regex` cargo feature.
+    /// * `:own-text("text")` matches elements whose own text,
+    ///   from child text nodes only, contains the given string.
+    ///
+    /// Default: false
+    pub text_pseudo_classes: bool,
+
+    /// User-defined pseudo-classes to accept, in addition to the built-in ones.
+    pub custom_pseudo_classes: PseudoClassRegistry,
+
+    /// The fragment identifier of the document’s URL, without the leading `#`.
+    ///
+    /// `:target` matches the element indicated by this fragment, and nothing if it is `None`.
+    pub target_fragment: Option<String>,
+}
+
+type CustomPredicate = dyn Fn(&NodeDataRef<ElementData>) -> bool + Send + Sync;
+type CustomPredicateWithArgument = dyn Fn(&NodeDataRef<ElementData>, &str) -> bool + Send + Sync;
+
+#[derive(Clone)]
+enum CustomMatcher {
+    Plain(Arc<CustomPredicate>),
+    WithArgument(Arc<CustomPredicateWithArgument>),
+}
+
+/// A set of user-defined pseudo-classes.
+///
+/// Selectors compiled with a registry in `SelectorOpts::custom_pseudo_classes`
+/// can use its pseudo-classes, which match elements for which the registered predicate
+/// returns true. Names are ASCII case-insensitive, and cannot override built-in pseudo-classes.
+///
+/// ```rust
+/// use kuchiki::{PseudoClassRegistry, SelectorOpts, Selectors};
+///
+/// let mut registry = PseudoClassRegistry::new();
+/// registry.register("has-tracking-pixel", |element| {
+///     element.as_node().select("img[width='1'][height='1']").unwrap().next().is_some()
+/// });
+/// registry.register_with_argument("data-state", |element, state| {
+///     element.attributes.borrow().get("data-state") == Some(state)
+/// });
+/// let opts = SelectorOpts {
+///     custom_pseudo_classes: registry,
+///     ..SelectorOpts::default()
+/// };
+/// let selectors = Selectors::compile_with_options("div:data-state(open)", &opts).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct PseudoClassRegistry {
+    matchers: HashMap<String, CustomMatcher>,
+}
+
+impl PseudoClassRegistry {
+    /// Create an empty registry.
+    pub fn new() -> PseudoClassRegistry {
+        PseudoClassRegistry::default()
+    }
+
+    /// Register a pseudo-class without argument, such as `:visible-in-print`.
+    ///
+    /// This replaces any pseudo-class previously registered with the same name.
+    pub fn register<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&NodeDataRef<ElementData>) -> bool + Send + Sync + 'static,
+    {
+        self.matchers.insert(
+            name.to_ascii_lowercase(),
+            CustomMatcher::Plain(Arc::new(predicate)),
+        );
+    }
+
+    /// Register a functional pseudo-class, such as `:data-state(open)`.
+    ///
+    /// The predicate is given the argument as written in the selector,
+    /// with surrounding whitespace removed and quotes removed if it is a single string.
+    ///
+    /// This replaces any pseudo-class previously registered with the same name.
+    pub fn register_with_argument<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&NodeDataRef<ElementData>, &str) -> bool + Send + Sync + 'static,
+    {
+        self.matchers.insert(
+            name.to_ascii_lowercase(),
+            CustomMatcher::WithArgument(Arc::new(predicate)),
+        );
+    }
+
+    /// Returns whether a pseudo-class is registered with this name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.matchers.contains_key(&name.to_ascii_lowercase())
+    }
+
+    fn get(&self, name: &str) -> Option<&CustomMatcher> {
+        self.matchers.get(&name.to_ascii_lowercase())
+    }
+}
+
+impl fmt::Debug for PseudoClassRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = self.matchers.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_set().entries(names).finish()
+    }
+}
+
+/// A use of a user-defined pseudo-class in a selector.
+#[derive(Clone)]
+pub struct CustomPseudoClass {
+    name: String,
+    /// The argument as passed to the predicate, and as written in the selector.
+    argument: Option<(String, String)>,
+    matcher: CustomMatcher,
+}
+
+impl CustomPseudoClass {
+    fn matches(&self, element: &NodeDataRef<ElementData>) -> bool {
+        match (&self.matcher, &self.argument) {
+            (CustomMatcher::Plain(predicate), None) => predicate(element),
+            (CustomMatcher::WithArgument(predicate), Some((argument, _))) => {
+                predicate(element, argument)
+            }
+            _ => false,
+        }
+    }
+}
 
-impl<'i> Parser<'i> for KuchikiParser {
+impl PartialEq for CustomPseudoClass {
+    fn eq(&self, other: &Self) -> bool {
+        let same_matcher = match (&self.matcher, &other.matcher) {
+            (CustomMatcher::Plain(a), CustomMatcher::Plain(b)) => Arc::ptr_eq(a, b),
+            (CustomMatcher::WithArgument(a), CustomMatcher::WithArgument(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        same_matcher && self.name == other.name && self.argument == other.argument
+    }
+}
+
+impl Eq for CustomPseudoClass {}
+
+impl fmt::Debug for CustomPseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomPseudoClass")
+            .field("name", &self.name)
+            .field("argument", &self.argument.as_ref().map(|a| &a.0))
+            .finish()
+    }
+}
+
+struct KuchikiParser<'a> {
+    opts: &'a SelectorOpts,
+}
+
+impl<'i, 'a> Parser<'i> for KuchikiParser<'a> {
     type Impl = KuchikiSelectors;
     type Error = SelectorParseErrorKind<'i>;
 
@@ -70,6 +264,32 @@ impl<'i> Parser<'i> for KuchikiParser {
             Ok(Checked)
         } else if name.eq_ignore_ascii_case("indeterminate") {
             Ok(Indeterminate)
+        } else if name.eq_ignore_ascii_case("required") {
+            Ok(Required)
+        } else if name.eq_ignore_ascii_case("optional") {
+            Ok(Optional)
+        } else if name.eq_ignore_ascii_case("read-only") {
+            Ok(ReadOnly)
+        } else if name.eq_ignore_ascii_case("read-write") {
+            Ok(ReadWrite)
+        } else if name.eq_ignore_ascii_case("placeholder-shown") {
+            Ok(PlaceholderShown)
+        } else if name.eq_ignore_ascii_case("default") {
+            Ok(Default)
+        } else if name.eq_ignore_ascii_case("target") {
+            Ok(Target(self.opts.target_fragment.as_ref().map(|fragment| {
+                fragment.strip_prefix('#').unwrap_or(fragment).to_owned()
+            })))
+        } else if name.eq_ignore_ascii_case("defined") {
+            Ok(Defined)
+        } else if let Some(matcher @ CustomMatcher::Plain(_)) =
+            self.opts.custom_pseudo_classes.get(&name)
+        {
+            Ok(Custom(CustomPseudoClass {
+                name: name.to_ascii_lowercase(),
+                argument: None,
+                matcher: matcher.clone(),
+            }))
         } else {
             Err(
                 location.new_custom_error(SelectorParseErrorKind::UnsupportedPseudoClassOrElement(
@@ -78,9 +298,401 @@ impl<'i> Parser<'i> for KuchikiParser {
             )
         }
     }
+
+    fn parse_non_ts_functional_pseudo_class<'t>(
+        &self,
+        name: CowRcStr<'i>,
+        arguments: &mut cssparser::Parser<'i, 't>,
+    ) -> Result<PseudoClass, ParseError<'i, SelectorParseErrorKind<'i>>> {
+        #[cfg(feature = "regex")]
+        {
+            if self.opts.text_pseudo_classes && name.eq_ignore_ascii_case("matches-text") {
+                return Ok(PseudoClass::MatchesText(TextRegex::parse(arguments)?));
+            }
+        }
+        if name.eq_ignore_ascii_case("lang") {
+            let ranges = arguments.parse_comma_separated(parse_text_argument)?;
+            Ok(PseudoClass::Lang(ranges.into_boxed_slice()))
+        } else if name.eq_ignore_ascii_case("dir") {
+            let location = arguments.current_source_location();
+            let direction = arguments.expect_ident()?;
+            if direction.eq_ignore_ascii_case("ltr") {
+                Ok(PseudoClass::Dir(Direction::Ltr))
+            } else if direction.eq_ignore_ascii_case("rtl") {
+                Ok(PseudoClass::Dir(Direction::Rtl))
+            } else {
+                let direction = direction.clone();
+                Err(location.new_custom_error(SelectorParseErrorKind::UnexpectedIdent(direction)))
+            }
+        } else if name.eq_ignore_ascii_case("has") {
+            let list =
+                arguments.parse_comma_separated(|input| parse_relative_selector(self, input))?;
+            Ok(PseudoClass::Has(list.into_boxed_slice()))
+        } else if self.opts.text_pseudo_classes && name.eq_ignore_ascii_case("contains") {
+            Ok(PseudoClass::Contains(parse_text_argument(arguments)?))
+        } else if self.opts.text_pseudo_classes && name.eq_ignore_ascii_case("icontains") {
+            Ok(PseudoClass::IContains(
+                parse_text_argument(arguments)?.to_lowercase(),
+            ))
+        } else if self.opts.text_pseudo_classes && name.eq_ignore_ascii_case("own-text") {
+            Ok(PseudoClass::OwnText(parse_text_argument(arguments)?))
+        } else if let Some(matcher @ CustomMatcher::WithArgument(_)) =
+            self.opts.custom_pseudo_classes.get(&name)
+        {
+            let argument = match arguments.try_parse(|input| {
+                let string = input.expect_string_cloned()?;
+                input.expect_exhausted()?;
+                Ok::<_, ParseError<'i, SelectorParseErrorKind<'i>>>(string)
+            }) {
+                Ok(string) => {
+                    let mut source = String::new();
+                    cssparser::serialize_string(&string, &mut source).unwrap();
+                    (string.as_ref().to_owned(), source)
+                }
+                Err(_) => {
+                    let start = arguments.position();
+                    while arguments.next().is_ok() {}
+                    let source = arguments
+                        .slice_from(start)
+                        .trim_matches(SELECTOR_WHITESPACE);
+                    (source.to_owned(), source.to_owned())
+                }
+            };
+            Ok(PseudoClass::Custom(CustomPseudoClass {
+                name: name.to_ascii_lowercase(),
+                argument: Some(argument),
+                matcher: matcher.clone(),
+            }))
+        } else {
+            Err(arguments.new_custom_error(
+                SelectorParseErrorKind::UnsupportedPseudoClassOrElement(name),
+            ))
+        }
+    }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+/// Parse a selector that may start with a combinator, such as `> img` in `:has(> img)`.
+///
+/// The selector is compiled with an explicit `:scope` compound on its left,
+/// so that it can be matched with the anchor element as the scope element.
+fn parse_relative_selector<'i, 't>(
+    parser: &KuchikiParser<'_>,
+    input: &mut cssparser::Parser<'i, 't>,
+) -> Result<RelativeSelector, ParseError<'i, SelectorParseErrorKind<'i>>> {
+    let start = input.position();
+    while input.next().is_ok() {}
+    let source = input.slice_from(start).trim_matches(SELECTOR_WHITESPACE);
+    if source.is_empty() {
+        return Err(input.new_custom_error(SelectorParseErrorKind::EmptySelector));
+    }
+    let combinator = match source.chars().next() {
+        Some('>') => RelativeCombinator::Child,
+        Some('+') => RelativeCombinator::NextSibling,
+        Some('~') => RelativeCombinator::LaterSibling,
+        _ => RelativeCombinator::Descendant,
+    };
+
+    let scoped = format!(":scope {}", source);
+    let mut scoped_input = cssparser::ParserInput::new(&scoped);
+    let mut scoped_parser = cssparser::Parser::new(&mut scoped_input);
+    let selector = scoped_parser
+        .parse_entirely(|input| GenericSelector::parse(parser, input))
+        .map_err(|_| input.new_custom_error(SelectorParseErrorKind::InvalidState))?;
+
+    fn count_scopes<'a, I>(components: I) -> usize
+    where
+        I: Iterator<Item = &'a Component<KuchikiSelectors>>,
+    {
+        components
+            .map(|component| match component {
+                Component::Scope => 1,
+                Component::Negation(negated) => count_scopes(negated.iter()),
+                _ => 0,
+            })
+            .sum()
+    }
+    let combinators = selector
+        .iter_raw_match_order()
+        .filter(|component| matches!(component, Component::Combinator(_)))
+        .count();
+    let scopes = count_scopes(selector.iter_raw_match_order());
+
+    Ok(RelativeSelector {
+        combinator,
+        compound: combinators == 1 && scopes == 1,
+        selector,
+        source: source.to_owned(),
+    })
+}
+
+/// Parse a selector in a selector list, which may be relative to the scope element
+/// if it starts with a combinator, as in `> li`.
+fn parse_scoped_selector<'i, 't>(
+    parser: &KuchikiParser<'_>,
+    input: &mut cssparser::Parser<'i, 't>,
+) -> Result<GenericSelector<KuchikiSelectors>, ParseError<'i, SelectorParseErrorKind<'i>>> {
+    let state = input.state();
+    let relative = matches!(
+        input.next(),
+        Ok(&Token::Delim('>')) | Ok(&Token::Delim('+')) | Ok(&Token::Delim('~'))
+    );
+    input.reset(&state);
+    if relative {
+        Ok(parse_relative_selector(parser, input)?.selector)
+    } else {
+        GenericSelector::parse(parser, input)
+    }
+}
+
+/// The combinator at the start of a relative selector.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RelativeCombinator {
+    Descendant,
+    Child,
+    NextSibling,
+    LaterSibling,
+}
+
+/// A selector matched relative to an anchor element, such as the argument of `:has()`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RelativeSelector {
+    combinator: RelativeCombinator,
+    /// Whether there is no other combinator than the leading one, and no other `:scope`,
+    /// so that only children or siblings of the anchor can match
+    /// and whether they match does not depend on which element is the anchor.
+    compound: bool,
+    /// The selector with an explicit `:scope` on its left.
+    selector: GenericSelector<KuchikiSelectors>,
+    source: String,
+}
+
+type Context<'a> = matching::MatchingContext<'a, KuchikiSelectors>;
+
+impl RelativeSelector {
+    /// The specificity of this selector, not counting the `:scope` implied on its left.
+    fn specificity(&self) -> Specificity {
+        let (ids, classes, elements) = selector_specificity(&self.selector).components();
+        Specificity::new(ids, classes.saturating_sub(1), elements)
+    }
+
+    /// Returns whether any element in the relevant part of the tree matches this selector
+    /// with `anchor` as the scope element.
+    ///
+    /// Results are remembered in `cache`, so that a query over a whole document
+    /// visits each element a bounded number of times for simple relative selectors
+    /// such as `:has(img)` or `:has(~ h2)`.
+    fn matches_relative_to(&self, anchor: &NodeDataRef<ElementData>, cache: &mut HasCache) -> bool {
+        if let Some(result) = cache.get(self, anchor.as_node()) {
+            return result;
+        }
+        let mut context = matching::MatchingContext::new(
+            matching::MatchingMode::Normal,
+            None,
+            None,
+            QuirksMode::NoQuirks,
+        );
+        context.extra_data = mem::take(cache);
+        let result = match (self.combinator, self.compound) {
+            (RelativeCombinator::Descendant, true) => {
+                self.has_matching_descendant(anchor, &mut context)
+            }
+            (RelativeCombinator::LaterSibling, true) => {
+                self.has_matching_later_sibling(anchor, &mut context)
+            }
+            _ => self.scan(anchor, &mut context),
+        };
+        *cache = mem::take(&mut context.extra_data);
+        cache.insert(self, anchor.as_node(), result);
+        result
+    }
+
+    fn matches(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        scope: &NodeDataRef<ElementData>,
+        context: &mut Context<'_>,
+    ) -> bool {
+        context.scope_element = Some(selectors::Element::opaque(scope));
+        matching::matches_selector(&self.selector, 0, None, element, context, &mut |_, _| {})
+    }
+
+    /// Whether an element in the relevant part of the tree matches,
+    /// trying each of them in turn.
+    fn scan(&self, anchor: &NodeDataRef<ElementData>, context: &mut Context<'_>) -> bool {
+        let mut matches =
+            |element: NodeDataRef<ElementData>| self.matches(&element, anchor, context);
+        let node = anchor.as_node();
+        match (self.combinator, self.compound) {
+            (RelativeCombinator::Descendant, _) | (RelativeCombinator::Child, false) => {
+                node.descendants().elements().any(matches)
+            }
+            (RelativeCombinator::Child, true) => node.children().elements().any(matches),
+            (RelativeCombinator::NextSibling, compound) => {
+                match node.following_siblings().elements().next() {
+                    Some(sibling) if compound => matches(sibling),
+                    Some(sibling) => sibling
+                        .as_node()
+                        .inclusive_descendants()
+                        .elements()
+                        .any(matches),
+                    None => false,
+                }
+            }
+            (RelativeCombinator::LaterSibling, true) => {
+                node.following_siblings().elements().any(matches)
+            }
+            (RelativeCombinator::LaterSibling, false) => node
+                .following_siblings()
+                .flat_map(|sibling| sibling.inclusive_descendants())
+                .elements()
+                .any(matches),
+        }
+    }
+
+    /// Whether a descendant matches, for a compound selector.
+    ///
+    /// A descendant matches if a child matches or if a descendant of a child matches,
+    /// so the result is also known for every element visited.
+    /// The tree is walked depth-first with an explicit stack, skipping subtrees
+    /// whose result is already known.
+    fn has_matching_descendant(
+        &self,
+        anchor: &NodeDataRef<ElementData>,
+        context: &mut Context<'_>,
+    ) -> bool {
+        // Elements whose children are being visited, with the next child to visit.
+        let mut stack = vec![(anchor.clone(), anchor.as_node().first_child())];
+        while let Some((parent, next_child)) = stack.last_mut() {
+            let child = match next_child.take() {
+                Some(child) => child,
+                None => {
+                    let parent = parent.clone();
+                    stack.pop();
+                    context.extra_data.insert(self, parent.as_node(), false);
+                    continue;
+                }
+            };
+            *next_child = child.next_sibling();
+            let parent = parent.clone();
+            let element = match child.into_element_ref() {
+                Some(element) => element,
+                None => continue,
+            };
+            let found = self.matches(&element, &parent, context)
+                || match context.extra_data.get(self, element.as_node()) {
+                    Some(result) => result,
+                    None => {
+                        let first_child = element.as_node().first_child();
+                        stack.push((element, first_child));
+                        false
+                    }
+                };
+            if found {
+                for (ancestor, _) in stack {
+                    context.extra_data.insert(self, ancestor.as_node(), true);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether a later sibling matches, for a compound selector.
+    ///
+    /// The result is also known for the siblings visited before the one that matches.
+    fn has_matching_later_sibling(
+        &self,
+        anchor: &NodeDataRef<ElementData>,
+        context: &mut Context<'_>,
+    ) -> bool {
+        let mut visited = Vec::new();
+        let mut result = false;
+        for sibling in anchor.as_node().following_siblings().elements() {
+            if self.matches(&sibling, anchor, context) {
+                result = true;
+                break;
+            }
+            if let Some(known) = context.extra_data.get(self, sibling.as_node()) {
+                result = known;
+                break;
+            }
+            visited.push(sibling);
+        }
+        for sibling in visited {
+            context.extra_data.insert(self, sibling.as_node(), result);
+        }
+        result
+    }
+}
+
+/// Parse the argument of a text pseudo-class, either a quoted string or an identifier.
+fn parse_text_argument<'i, 't>(
+    input: &mut cssparser::Parser<'i, 't>,
+) -> Result<String, ParseError<'i, SelectorParseErrorKind<'i>>> {
+    Ok(input.expect_ident_or_string()?.as_ref().to_owned())
+}
+
+/// The regular expression argument of `:matches-text()`.
+#[cfg(feature = "regex")]
+#[derive(Clone, Debug)]
+pub struct TextRegex {
+    /// The argument as written, used for serialization and comparison.
+    source: String,
+    regex: Regex,
+}
+
+#[cfg(feature = "regex")]
+impl TextRegex {
+    fn parse<'i, 't>(
+        input: &mut cssparser::Parser<'i, 't>,
+    ) -> Result<TextRegex, ParseError<'i, SelectorParseErrorKind<'i>>> {
+        let location = input.current_source_location();
+        let invalid = || location.new_custom_error(SelectorParseErrorKind::InvalidState);
+
+        if let Ok(pattern) = input.try_parse(|input| input.expect_string_cloned()) {
+            let mut source = String::new();
+            cssparser::serialize_string(&pattern, &mut source).unwrap();
+            let regex = Regex::new(&pattern).map_err(|_| invalid())?;
+            return Ok(TextRegex { source, regex });
+        }
+
+        // `/pattern/flags` is not a CSS token, take the source text as-is.
+        let start = input.position();
+        while input.next().is_ok() {}
+        let source = input.slice_from(start).trim_matches(SELECTOR_WHITESPACE);
+        let (pattern, flags) = match source.strip_prefix('/').and_then(|s| s.rsplit_once('/')) {
+            Some(split) => split,
+            None => return Err(invalid()),
+        };
+        let mut builder = RegexBuilder::new(pattern);
+        for flag in flags.chars() {
+            match flag {
+                'i' => builder.case_insensitive(true),
+                'm' => builder.multi_line(true),
+                's' => builder.dot_matches_new_line(true),
+                'x' => builder.ignore_whitespace(true),
+                _ => return Err(invalid()),
+            };
+        }
+        let regex = builder.build().map_err(|_| invalid())?;
+        Ok(TextRegex {
+            source: source.to_owned(),
+            regex,
+        })
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for TextRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for TextRegex {}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub enum PseudoClass {
     AnyLink,
     Link,
@@ -92,6 +704,25 @@ pub enum PseudoClass {
     Disabled,
     Checked,
     Indeterminate,
+    Required,
+    Optional,
+    ReadOnly,
+    ReadWrite,
+    PlaceholderShown,
+    Default,
+    /// The fragment from `SelectorOpts::target_fragment`.
+    Target(Option<String>),
+    Defined,
+    Lang(Box<[String]>),
+    Dir(Direction),
+    Has(Box<[RelativeSelector]>),
+    Contains(String),
+    /// The argument is stored lower-cased.
+    IContains(String),
+    #[cfg(feature = "regex")]
+    MatchesText(TextRegex),
+    OwnText(String),
+    Custom(CustomPseudoClass),
 }
 
 impl NonTSPseudoClass for PseudoClass {
@@ -115,7 +746,17 @@ impl ToCss for PseudoClass {
     where
         W: fmt::Write,
     {
-        dest.write_str(match *self {
+        fn write_string_argument<W: fmt::Write>(
+            dest: &mut W,
+            function: &str,
+            text: &str,
+        ) -> fmt::Result {
+            dest.write_str(function)?;
+            cssparser::serialize_string(text, dest)?;
+            dest.write_str(")")
+        }
+
+        let keyword = match *self {
             PseudoClass::AnyLink => ":any-link",
             PseudoClass::Link => ":link",
             PseudoClass::Visited => ":visited",
@@ -126,7 +767,63 @@ impl ToCss for PseudoClass {
             PseudoClass::Disabled => ":disabled",
             PseudoClass::Checked => ":checked",
             PseudoClass::Indeterminate => ":indeterminate",
-        })
+            PseudoClass::Required => ":required",
+            PseudoClass::Optional => ":optional",
+            PseudoClass::ReadOnly => ":read-only",
+            PseudoClass::ReadWrite => ":read-write",
+            PseudoClass::PlaceholderShown => ":placeholder-shown",
+            PseudoClass::Default => ":default",
+            PseudoClass::Target(_) => ":target",
+            PseudoClass::Defined => ":defined",
+            PseudoClass::Dir(Direction::Ltr) => ":dir(ltr)",
+            PseudoClass::Dir(Direction::Rtl) => ":dir(rtl)",
+            PseudoClass::Lang(ref ranges) => {
+                dest.write_str(":lang(")?;
+                for (i, range) in ranges.iter().enumerate() {
+                    if i > 0 {
+                        dest.write_str(", ")?;
+                    }
+                    cssparser::serialize_string(range, dest)?;
+                }
+                return dest.write_str(")");
+            }
+            PseudoClass::Has(ref list) => {
+                dest.write_str(":has(")?;
+                for (i, relative) in list.iter().enumerate() {
+                    if i > 0 {
+                        dest.write_str(", ")?;
+                    }
+                    dest.write_str(&relative.source)?;
+                }
+                return dest.write_str(")");
+            }
+            PseudoClass::Contains(ref text) => {
+                return write_string_argument(dest, ":contains(", text)
+            }
+            PseudoClass::IContains(ref text) => {
+                return write_string_argument(dest, ":icontains(", text)
+            }
+            #[cfg(feature = "regex")]
+            PseudoClass::MatchesText(ref regex) => {
+                dest.write_str(":matches-text(")?;
+                dest.write_str(&regex.source)?;
+                return dest.write_str(")");
+            }
+            PseudoClass::OwnText(ref text) => {
+                return write_string_argument(dest, ":own-text(", text)
+            }
+            PseudoClass::Custom(ref custom) => {
+                dest.write_str(":")?;
+                dest.write_str(&custom.name)?;
+                if let Some((_, ref source)) = custom.argument {
+                    dest.write_str("(")?;
+                    dest.write_str(source)?;
+                    dest.write_str(")")?;
+                }
+                return Ok(());
+            }
+        };
+        dest.write_str(keyword)
     }
 }
 
@@ -255,9 +952,7 @@ impl selectors::Element for NodeDataRef<ElementData> {
         self.attributes
             .borrow()
             .get(local_name!("id"))
-            .map_or(false, |id_attr| {
-                case_sensitivity.eq(id.as_bytes(), id_attr.as_bytes())
-            })
+            .is_some_and(|id_attr| case_sensitivity.eq(id.as_bytes(), id_attr.as_bytes()))
     }
 
     #[inline]
@@ -289,7 +984,7 @@ impl selectors::Element for NodeDataRef<ElementData> {
             NamespaceConstraint::Specific(ns_url) => attrs
                 .map
                 .get(&ExpandedName::new(ns_url, local_name.clone()))
-                .map_or(false, |attr| operation.eval_str(&attr.value)),
+                .is_some_and(|attr| operation.eval_str(&attr.value)),
         }
     }
 
@@ -304,7 +999,7 @@ impl selectors::Element for NodeDataRef<ElementData> {
     fn match_non_ts_pseudo_class<F>(
         &self,
         pseudo: &PseudoClass,
-        _context: &mut matching::MatchingContext<KuchikiSelectors>,
+        context: &mut matching::MatchingContext<KuchikiSelectors>,
         _flags_setter: &mut F,
     ) -> bool
     where
@@ -312,9 +1007,23 @@ impl selectors::Element for NodeDataRef<ElementData> {
     {
         use self::PseudoClass::*;
         match *pseudo {
-            Active | Focus | Hover | Enabled | Disabled | Checked | Indeterminate | Visited => {
-                false
-            }
+            Active | Focus | Hover | Visited => false,
+            Enabled => element_state::is_enabled(self),
+            Disabled => element_state::is_disabled(self),
+            Checked => element_state::is_checked(self),
+            Indeterminate => element_state::is_indeterminate(self),
+            Required => element_state::is_required(self),
+            Optional => element_state::is_optional(self),
+            ReadOnly => element_state::is_read_only(self),
+            ReadWrite => element_state::is_read_write(self),
+            PlaceholderShown => element_state::is_placeholder_shown(self),
+            Default => element_state::is_default(self),
+            Target(ref fragment) => fragment
+                .as_ref()
+                .is_some_and(|fragment| element_state::is_target(self, fragment)),
+            Defined => element_state::is_defined(self),
+            Lang(ref ranges) => element_state::matches_lang(self, ranges),
+            Dir(direction) => element_state::directionality(self) == direction,
             AnyLink | Link => {
                 self.name.ns == ns!(html)
                     && matches!(
@@ -323,32 +1032,182 @@ impl selectors::Element for NodeDataRef<ElementData> {
                     )
                     && self.attributes.borrow().contains(local_name!("href"))
             }
+            Has(ref list) => list
+                .iter()
+                .any(|relative| relative.matches_relative_to(self, &mut context.extra_data)),
+            Contains(ref text) => self.text_contents().contains(&**text),
+            IContains(ref text) => self.text_contents().to_lowercase().contains(&**text),
+            #[cfg(feature = "regex")]
+            MatchesText(ref regex) => regex.regex.is_match(&self.text_contents()),
+            OwnText(ref text) => {
+                let mut own_text = String::new();
+                for child in self.as_node().children().text_nodes() {
+                    own_text.push_str(&child.borrow());
+                }
+                own_text.contains(&**text)
+            }
+            Custom(ref custom) => custom.matches(self),
         }
     }
 }
 
 /// A pre-compiled list of CSS Selectors.
+#[derive(Clone)]
 pub struct Selectors(pub Vec<Selector>);
 
 /// A pre-compiled CSS Selector.
+#[derive(Clone)]
 pub struct Selector(GenericSelector<KuchikiSelectors>);
 
 /// The specificity of a selector.
 ///
-/// Opaque, but ordered.
+/// Ordered, and made of three components:
+/// the number of ID selectors, of class-like selectors (classes, attributes and pseudo-classes)
+/// and of type selectors (and pseudo-elements). Each component saturates at 1023.
 ///
 /// Determines precedence in the cascading algorithm.
 /// When equal, a rule later in source order takes precedence.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Specificity(u32);
 
+const MAX_SPECIFICITY_COMPONENT: u32 = (1 << 10) - 1;
+
+impl Specificity {
+    /// Build a specificity from its (a, b, c) components, saturating each at 1023.
+    pub fn new(ids: u32, classes: u32, elements: u32) -> Specificity {
+        Specificity(
+            ids.min(MAX_SPECIFICITY_COMPONENT) << 20
+                | classes.min(MAX_SPECIFICITY_COMPONENT) << 10
+                | elements.min(MAX_SPECIFICITY_COMPONENT),
+        )
+    }
+
+    /// The number of ID selectors: the “a” component.
+    #[inline]
+    pub fn ids(&self) -> u32 {
+        self.0 >> 20
+    }
+
+    /// The number of class, attribute and pseudo-class selectors: the “b” component.
+    #[inline]
+    pub fn classes(&self) -> u32 {
+        (self.0 >> 10) & MAX_SPECIFICITY_COMPONENT
+    }
+
+    /// The number of type selectors and pseudo-elements: the “c” component.
+    #[inline]
+    pub fn elements(&self) -> u32 {
+        self.0 & MAX_SPECIFICITY_COMPONENT
+    }
+
+    /// The (a, b, c) components.
+    #[inline]
+    pub fn components(&self) -> (u32, u32, u32) {
+        (self.ids(), self.classes(), self.elements())
+    }
+}
+
+impl fmt::Debug for Specificity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Specificity{:?}", self.components())
+    }
+}
+
+/// The specificity of a selector, where `selectors` counts each `:has()` as a pseudo-class.
+fn selector_specificity(selector: &GenericSelector<KuchikiSelectors>) -> Specificity {
+    fn add_has_arguments<'a>(
+        components: impl Iterator<Item = &'a Component<KuchikiSelectors>>,
+        has_count: &mut u32,
+        arguments: &mut (u32, u32, u32),
+    ) {
+        for component in components {
+            match component {
+                Component::NonTSPseudoClass(PseudoClass::Has(list)) => {
+                    let most_specific = list.iter().map(RelativeSelector::specificity).max();
+                    let (ids, classes, elements) =
+                        most_specific.map_or((0, 0, 0), |s| s.components());
+                    *has_count += 1;
+                    arguments.0 += ids;
+                    arguments.1 += classes;
+                    arguments.2 += elements;
+                }
+                Component::Negation(negated) => {
+                    add_has_arguments(negated.iter(), has_count, arguments)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let specificity = Specificity(selector.specificity());
+    let mut has_count = 0;
+    let mut arguments = (0, 0, 0);
+    add_has_arguments(
+        selector.iter_raw_match_order(),
+        &mut has_count,
+        &mut arguments,
+    );
+    if has_count == 0 {
+        return specificity;
+    }
+    let (ids, classes, elements) = specificity.components();
+    Specificity::new(
+        ids + arguments.0,
+        classes.saturating_sub(has_count) + arguments.1,
+        elements + arguments.2,
+    )
+}
+
+// Errors are `()`: the parser's error type borrows the input,
+// and exposing it would tie the public API to the `selectors` crate.
+#[allow(clippy::result_unit_err)]
 impl Selectors {
     /// Compile a list of selectors. This may fail on syntax errors or unsupported selectors.
     #[inline]
     pub fn compile(s: &str) -> Result<Selectors, ()> {
+        Selectors::compile_with_options(s, &SelectorOpts::default())
+    }
+
+    /// Compile a list of selectors with custom options.
+    /// This may fail on syntax errors or unsupported selectors.
+    pub fn compile_with_options(s: &str, opts: &SelectorOpts) -> Result<Selectors, ()> {
+        Selectors::compile_list(s, opts, |parser, input| {
+            GenericSelector::parse(parser, input)
+        })
+    }
+
+    /// Compile a list of selectors that may start with a combinator,
+    /// as in `> li`, to be relative to the scope element.
+    ///
+    /// This is the syntax accepted by `NodeRef::select`,
+    /// for use with `NodeRef::select_compiled`.
+    #[inline]
+    pub fn compile_scoped(s: &str) -> Result<Selectors, ()> {
+        Selectors::compile_scoped_with_options(s, &SelectorOpts::default())
+    }
+
+    /// Compile a list of selectors that may start with a combinator, with custom options.
+    pub fn compile_scoped_with_options(s: &str, opts: &SelectorOpts) -> Result<Selectors, ()> {
+        Selectors::compile_list(s, opts, parse_scoped_selector)
+    }
+
+    fn compile_list<F>(s: &str, opts: &SelectorOpts, parse_selector: F) -> Result<Selectors, ()>
+    where
+        F: for<'i, 't> Fn(
+            &KuchikiParser<'_>,
+            &mut cssparser::Parser<'i, 't>,
+        ) -> Result<
+            GenericSelector<KuchikiSelectors>,
+            ParseError<'i, SelectorParseErrorKind<'i>>,
+        >,
+    {
+        let parser = KuchikiParser { opts };
         let mut input = cssparser::ParserInput::new(s);
-        match SelectorList::parse(&KuchikiParser, &mut cssparser::Parser::new(&mut input)) {
-            Ok(list) => Ok(Selectors(list.0.into_iter().map(Selector).collect())),
+        let result = cssparser::Parser::new(&mut input).parse_entirely(|input| {
+            input.parse_comma_separated(|input| parse_selector(&parser, input))
+        });
+        match result {
+            Ok(list) => Ok(Selectors(list.into_iter().map(Selector).collect())),
             Err(_) => Err(()),
         }
     }
@@ -359,35 +1218,249 @@ impl Selectors {
         self.0.iter().any(|s| s.matches(element))
     }
 
+    /// Returns whether the given element matches this list of selectors,
+    /// with `:scope` and selectors starting with a combinator relative to the given scope.
+    #[inline]
+    pub fn matches_with_scope(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        scope: Option<&NodeDataRef<ElementData>>,
+    ) -> bool {
+        self.0.iter().any(|s| s.matches_with_scope(element, scope))
+    }
+
+    /// Returns whether the given element matches this list of selectors,
+    /// reusing a matching context that already has its scope element set.
+    #[inline]
+    pub(crate) fn matches_in_context(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        context: &mut matching::MatchingContext<KuchikiSelectors>,
+    ) -> bool {
+        self.0
+            .iter()
+            .any(|s| s.matches_in_context(element, context))
+    }
+
+    /// Return the matching selector with the highest specificity, if any matches.
+    ///
+    /// When several have the same specificity, the last one in the list wins,
+    /// as it would in the cascade.
+    pub fn matching_selector(&self, element: &NodeDataRef<ElementData>) -> Option<&Selector> {
+        self.0
+            .iter()
+            .filter(|s| s.matches(element))
+            .max_by_key(|s| s.specificity())
+    }
+
     /// Filter an element iterator, yielding those matching this list of selectors.
     #[inline]
     pub fn filter<I>(&self, iter: I) -> Select<I, &Selectors>
     where
         I: Iterator<Item = NodeDataRef<ElementData>>,
     {
-        Select {
-            iter,
-            selectors: self,
-        }
+        Select::new(iter, self)
     }
 }
 
 impl Selector {
     /// Returns whether the given element matches this selector.
+    ///
+    /// Without a scope element, `:scope` matches the root element.
     #[inline]
     pub fn matches(&self, element: &NodeDataRef<ElementData>) -> bool {
+        self.matches_with_scope(element, None)
+    }
+
+    /// Returns whether the given element matches this selector,
+    /// with `:scope` and selectors starting with a combinator relative to the given scope.
+    #[inline]
+    pub fn matches_with_scope(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        scope: Option<&NodeDataRef<ElementData>>,
+    ) -> bool {
         let mut context = matching::MatchingContext::new(
             matching::MatchingMode::Normal,
             None,
             None,
             QuirksMode::NoQuirks,
         );
-        matching::matches_selector(&self.0, 0, None, element, &mut context, &mut |_, _| {})
+        context.scope_element = scope.map(selectors::Element::opaque);
+        self.matches_in_context(element, &mut context)
+    }
+
+    #[inline]
+    pub(crate) fn matches_in_context(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        context: &mut matching::MatchingContext<KuchikiSelectors>,
+    ) -> bool {
+        matching::matches_selector(&self.0, 0, None, element, context, &mut |_, _| {})
     }
 
     /// Return the specificity of this selector.
+    ///
+    /// Like `:is()`, `:has()` counts as its most specific argument.
     pub fn specificity(&self) -> Specificity {
-        Specificity(self.0.specificity())
+        selector_specificity(&self.0)
+    }
+
+    /// Returns whether this selector depends on user interaction,
+    /// with `:hover`, `:active`, `:focus` or `:visited`.
+    /// Such selectors never match in a static document.
+    pub fn is_dynamic(&self) -> bool {
+        fn is_dynamic(component: &Component<KuchikiSelectors>) -> bool {
+            match component {
+                Component::NonTSPseudoClass(pseudo_class) => {
+                    pseudo_class.is_user_action_state() || *pseudo_class == PseudoClass::Visited
+                }
+                Component::Negation(negated) => negated.iter().any(is_dynamic),
+                _ => false,
+            }
+        }
+        self.0.iter_raw_match_order().any(is_dynamic)
+    }
+}
+
+/// A selector list argument, either a string to be compiled or pre-compiled `Selectors`.
+///
+/// Strings are compiled through the per-thread `SelectorCache`.
+#[allow(clippy::result_unit_err)] // Like `Selectors::compile`
+pub trait IntoSelectors<'a> {
+    /// Return the compiled selector list.
+    /// This may fail on syntax errors or unsupported selectors.
+    fn into_selectors(self) -> Result<Cow<'a, Selectors>, ()>;
+}
+
+impl<'a> IntoSelectors<'a> for &'a str {
+    #[inline]
+    fn into_selectors(self) -> Result<Cow<'a, Selectors>, ()> {
+        SelectorCache::with_thread_local(|cache| cache.compile(self)).map(Cow::Owned)
+    }
+}
+
+impl<'a> IntoSelectors<'a> for &'a String {
+    #[inline]
+    fn into_selectors(self) -> Result<Cow<'a, Selectors>, ()> {
+        self.as_str().into_selectors()
+    }
+}
+
+impl<'a> IntoSelectors<'a> for &'a Selectors {
+    #[inline]
+    fn into_selectors(self) -> Result<Cow<'a, Selectors>, ()> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+/// The capacity of the per-thread cache used by `NodeRef::select` and friends.
+const THREAD_LOCAL_CACHE_CAPACITY: usize = 64;
+
+thread_local! {
+    static THREAD_LOCAL_CACHE: SelectorCache = SelectorCache::new(THREAD_LOCAL_CACHE_CAPACITY);
+}
+
+/// A bounded cache of compiled selector lists, keyed by their source string.
+///
+/// When the cache is full, the least recently used entry is evicted.
+/// Strings that fail to compile are not cached.
+///
+/// `NodeRef::select`, `NodeRef::select_first` and the `select` methods of node iterators
+/// consult a per-thread cache, available through `SelectorCache::with_thread_local`.
+pub struct SelectorCache {
+    capacity: usize,
+    /// Compiled lists, and whether they have a selector starting with a combinator.
+    entries: RefCell<IndexMap<String, (Selectors, bool)>>,
+}
+
+#[allow(clippy::result_unit_err)] // Like `Selectors::compile`
+impl SelectorCache {
+    /// Create an empty cache holding at most `capacity` selector lists.
+    pub fn new(capacity: usize) -> SelectorCache {
+        SelectorCache {
+            capacity,
+            entries: RefCell::new(IndexMap::new()),
+        }
+    }
+
+    /// Run a function with the cache of the current thread.
+    pub fn with_thread_local<F, R>(f: F) -> R
+    where
+        F: FnOnce(&SelectorCache) -> R,
+    {
+        THREAD_LOCAL_CACHE.with(f)
+    }
+
+    /// Return the compiled form of `s`, compiling and caching it if necessary.
+    ///
+    /// This may fail on syntax errors or unsupported selectors.
+    pub fn compile(&self, s: &str) -> Result<Selectors, ()> {
+        self.get_or_compile(s, false)
+    }
+
+    /// Return the compiled form of `s`, which may start with a combinator
+    /// as with `Selectors::compile_scoped`, compiling and caching it if necessary.
+    pub fn compile_scoped(&self, s: &str) -> Result<Selectors, ()> {
+        self.get_or_compile(s, true)
+    }
+
+    fn get_or_compile(&self, s: &str, scoped: bool) -> Result<Selectors, ()> {
+        let mut entries = self.entries.borrow_mut();
+        if let Some(index) = entries.get_index_of(s) {
+            // The last entry is the most recently used.
+            let last = entries.len() - 1;
+            entries.move_index(index, last);
+            let (ref selectors, relative) = entries[last];
+            return if relative && !scoped {
+                Err(())
+            } else {
+                Ok(selectors.clone())
+            };
+        }
+        // A list without leading combinators compiles the same either way,
+        // so entries are shared and only remember whether one was needed.
+        let (selectors, relative) = match Selectors::compile(s) {
+            Ok(selectors) => (selectors, false),
+            Err(()) if scoped => (Selectors::compile_scoped(s)?, true),
+            Err(()) => return Err(()),
+        };
+        if self.capacity > 0 {
+            if entries.len() >= self.capacity {
+                entries.shift_remove_index(0);
+            }
+            entries.insert(s.to_owned(), (selectors.clone(), relative));
+        }
+        Ok(selectors)
+    }
+
+    /// The maximum number of selector lists held by this cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of selector lists currently held by this cache.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Returns whether this cache holds no selector list.
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Remove every entry from this cache.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear()
+    }
+}
+
+impl fmt::Debug for SelectorCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SelectorCache")
+            .field("capacity", &self.capacity)
+            .field("entries", &self.entries.borrow().keys().collect::<Vec<_>>())
+            .finish()
     }
 }
 
@@ -1,44 +1,31 @@
 use html5ever::serialize::TraversalScope::*;
-use html5ever::serialize::{serialize, Serialize, SerializeOpts, Serializer, TraversalScope};
-use html5ever::QualName;
+use html5ever::serialize::{Serialize, Serializer, TraversalScope};
+use html5ever::{Namespace, QualName};
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
 use std::fs::File;
-use std::io::{Result, Write};
+use std::io::{self, Error, ErrorKind, Write};
 use std::path::Path;
-use std::string::ToString;
+use std::str;
 
-use crate::tree::{NodeData, NodeRef};
+use crate::attributes::Attributes;
+use crate::html_elements::{
+    collapse_whitespace, html_name, is_block, is_raw_text, is_void, is_whitespace_sensitive,
+};
+use crate::iter::NodeEdge;
+use crate::tree::{ElementData, NodeData, NodeRef};
 
 impl Serialize for NodeRef {
     fn serialize<S: Serializer>(
         &self,
         serializer: &mut S,
         traversal_scope: TraversalScope,
-    ) -> Result<()> {
+    ) -> io::Result<()> {
         match (traversal_scope, self.data()) {
-            (ref scope, &NodeData::Element(ref element)) => {
+            (ref scope, NodeData::Element(element)) => {
                 if *scope == IncludeNode {
-                    let attrs = element.attributes.borrow();
-
-                    // Unfortunately we need to allocate something to hold these &'a QualName
-                    let attrs = attrs
-                        .map
-                        .iter()
-                        .map(|(name, attr)| {
-                            (
-                                QualName::new(
-                                    attr.prefix.clone(),
-                                    name.ns.clone(),
-                                    name.local.clone(),
-                                ),
-                                &attr.value,
-                            )
-                        })
-                        .collect::<Vec<_>>();
-
-                    serializer.start_elem(
-                        element.name.clone(),
-                        attrs.iter().map(|&(ref name, value)| (name, &**value)),
-                    )?
+                    start_element(serializer, element)?
                 }
 
                 for child in self.children() {
@@ -60,12 +47,10 @@ impl Serialize for NodeRef {
 
             (ChildrenOnly(_), _) => Ok(()),
 
-            (IncludeNode, &NodeData::Doctype(ref doctype)) => {
-                serializer.write_doctype(&doctype.name)
-            }
-            (IncludeNode, &NodeData::Text(ref text)) => serializer.write_text(&text.borrow()),
-            (IncludeNode, &NodeData::Comment(ref text)) => serializer.write_comment(&text.borrow()),
-            (IncludeNode, &NodeData::ProcessingInstruction(ref contents)) => {
+            (IncludeNode, NodeData::Doctype(doctype)) => serializer.write_doctype(&doctype.name),
+            (IncludeNode, NodeData::Text(text)) => serializer.write_text(&text.borrow()),
+            (IncludeNode, NodeData::Comment(text)) => serializer.write_comment(&text.borrow()),
+            (IncludeNode, NodeData::ProcessingInstruction(contents)) => {
                 let contents = contents.borrow();
                 serializer.write_processing_instruction(&contents.0, &contents.1)
             }
@@ -73,32 +58,928 @@ impl Serialize for NodeRef {
     }
 }
 
-impl ToString for NodeRef {
-    #[inline]
-    fn to_string(&self) -> String {
-        let mut u8_vec = Vec::new();
-        self.serialize(&mut u8_vec).unwrap();
-        String::from_utf8(u8_vec).unwrap()
+fn start_element<S: Serializer>(serializer: &mut S, element: &ElementData) -> io::Result<()> {
+    let attrs = element.attributes.borrow();
+
+    // Unfortunately we need to allocate something to hold these &'a QualName
+    let attrs = attrs
+        .map
+        .iter()
+        .map(|(name, attr)| {
+            (
+                QualName::new(attr.prefix.clone(), name.ns.clone(), name.local.clone()),
+                &attr.value,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    serializer.start_elem(
+        element.name.clone(),
+        attrs.iter().map(|&(ref name, value)| (name, &**value)),
+    )
+}
+
+/// What `NodeRef::serialize_filtered` does with a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Serialize the node, with any rewritten attributes, and filter its children.
+    Keep,
+    /// Skip the node and its descendants.
+    Skip,
+    /// Skip the tags of an element but filter its children.
+    /// For other nodes, this is the same as `Keep`.
+    Unwrap,
+}
+
+fn write_filtered<O, F>(
+    serializer: &mut OptionsSerializer<O>,
+    root: &NodeRef,
+    filter: &mut F,
+) -> Result<(), O::Error>
+where
+    O: Output,
+    F: FnMut(&NodeRef, &mut Cow<Attributes>) -> FilterAction,
+{
+    // Nodes whose children are being written, and whether their end tag is pending.
+    let mut stack: Vec<(NodeRef, bool)> = Vec::new();
+    let mut next = Some(root.clone());
+    loop {
+        let node = match next.take() {
+            Some(node) => node,
+            None => match stack.pop() {
+                Some((parent, end_tag_pending)) => {
+                    if end_tag_pending {
+                        serializer.end_elem(&parent.as_element().unwrap().name)?
+                    }
+                    next = parent.next_sibling().filter(|_| !stack.is_empty());
+                    continue;
+                }
+                None => return Ok(()),
+            },
+        };
+        let action = match node.as_element() {
+            Some(element) => {
+                let borrowed = element.attributes.borrow();
+                let mut attributes = Cow::Borrowed(&*borrowed);
+                let action = filter(&node, &mut attributes);
+                if action == FilterAction::Keep {
+                    serializer.start_elem(&element.name, &attributes)?
+                }
+                action
+            }
+            None => {
+                let mut no_attributes = Cow::Owned(Attributes {
+                    map: Default::default(),
+                });
+                match (filter(&node, &mut no_attributes), node.data()) {
+                    (FilterAction::Skip, _) => FilterAction::Skip,
+                    (_, NodeData::Document(_)) | (_, NodeData::DocumentFragment) => {
+                        FilterAction::Unwrap
+                    }
+                    _ => {
+                        // Written whole, there are no children to filter.
+                        serializer.write_node(&node)?;
+                        FilterAction::Skip
+                    }
+                }
+            }
+        };
+        if action == FilterAction::Skip {
+            next = node.next_sibling().filter(|_| !stack.is_empty());
+        } else {
+            next = node.first_child();
+            stack.push((node, action == FilterAction::Keep));
+        }
+    }
+}
+
+/// Serializes a tree with whitespace between block-level elements replaced by indentation.
+struct PrettyPrinter<'a, 'b, O> {
+    serializer: &'a mut OptionsSerializer<'b, O>,
+    indent: &'a str,
+}
+
+/// A node whose children `PrettyPrinter` is writing.
+struct PrettyFrame {
+    parent: NodeRef,
+    next_child: Option<NodeRef>,
+    /// The depth of the parent. Children of an element are one level deeper.
+    depth: usize,
+    /// Whether whitespace between children is replaced by indentation.
+    reindent: bool,
+    is_first: bool,
+}
+
+impl PrettyFrame {
+    fn new(parent: &NodeRef, is_block_parent: bool, depth: usize) -> PrettyFrame {
+        // Only reindent when no inline content would be affected.
+        let reindent = is_block_parent
+            && parent.children().all(|child| match child.data() {
+                NodeData::Element(element) => is_block(&element.name),
+                NodeData::Text(text) => text.borrow().trim_matches(is_ascii_whitespace).is_empty(),
+                _ => true,
+            });
+        PrettyFrame {
+            parent: parent.clone(),
+            next_child: parent.first_child(),
+            depth,
+            reindent,
+            is_first: true,
+        }
+    }
+}
+
+impl<'a, 'b, O: Output> PrettyPrinter<'a, 'b, O> {
+    fn write_node(&mut self, node: &NodeRef) -> Result<(), O::Error> {
+        let mut stack = Vec::new();
+        self.start_node(node, 0, &mut stack)?;
+        while let Some(frame) = stack.last_mut() {
+            let is_root = frame.parent.as_element().is_none();
+            let child_depth = if is_root {
+                frame.depth
+            } else {
+                frame.depth + 1
+            };
+            let mut next = frame.next_child.take();
+            if frame.reindent {
+                // Whitespace-only text is replaced by indentation.
+                while let Some(text) = next.as_ref().filter(|child| child.as_text().is_some()) {
+                    next = text.next_sibling();
+                }
+            }
+            match next {
+                Some(child) => {
+                    frame.next_child = child.next_sibling();
+                    if frame.reindent {
+                        if !(is_root && frame.is_first) {
+                            self.write_newline(child_depth)?
+                        }
+                        frame.is_first = false;
+                    }
+                    self.start_node(&child, child_depth, &mut stack)?
+                }
+                None => {
+                    let frame = stack.pop().unwrap();
+                    if frame.reindent && !is_root && !frame.is_first {
+                        self.write_newline(frame.depth)?
+                    }
+                    if let Some(element) = frame.parent.as_element() {
+                        self.serializer.end_elem(&element.name)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a node, or its start tag and push it on the stack if its children need reindenting.
+    fn start_node(
+        &mut self,
+        node: &NodeRef,
+        depth: usize,
+        stack: &mut Vec<PrettyFrame>,
+    ) -> Result<(), O::Error> {
+        match node.data() {
+            NodeData::Element(element) => {
+                self.serializer
+                    .start_elem(&element.name, &element.attributes.borrow())?;
+                if is_whitespace_sensitive(&element.name) {
+                    for child in node.children() {
+                        self.serializer.write_node(&child)?
+                    }
+                    self.serializer.end_elem(&element.name)
+                } else {
+                    stack.push(PrettyFrame::new(node, is_block(&element.name), depth));
+                    Ok(())
+                }
+            }
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                stack.push(PrettyFrame::new(node, true, depth));
+                Ok(())
+            }
+            _ => self.serializer.write_node(node),
+        }
+    }
+
+    fn write_newline(&mut self, depth: usize) -> Result<(), O::Error> {
+        self.serializer
+            .write_text(&format!("\n{}", self.indent.repeat(depth)))
+    }
+}
+
+/// The parents, outermost first, without which an element is not parsed back.
+fn missing_parents(element: &ElementData) -> Vec<QualName> {
+    let parents = match html_name(element) {
+        Some(&local_name!("li")) => vec![local_name!("ul")],
+        Some(&local_name!("dt")) | Some(&local_name!("dd")) => vec![local_name!("dl")],
+        Some(&local_name!("option")) | Some(&local_name!("optgroup")) => {
+            vec![local_name!("select")]
+        }
+        Some(&local_name!("caption"))
+        | Some(&local_name!("colgroup"))
+        | Some(&local_name!("thead"))
+        | Some(&local_name!("tbody"))
+        | Some(&local_name!("tfoot")) => vec![local_name!("table")],
+        Some(&local_name!("col")) => vec![local_name!("table"), local_name!("colgroup")],
+        Some(&local_name!("tr")) => vec![local_name!("table"), local_name!("tbody")],
+        Some(&local_name!("td")) | Some(&local_name!("th")) => vec![
+            local_name!("table"),
+            local_name!("tbody"),
+            local_name!("tr"),
+        ],
+        _ => Vec::new(),
+    };
+    parents
+        .into_iter()
+        .map(|local| QualName::new(None, ns!(html), local))
+        .collect()
+}
+
+fn is_ascii_whitespace(c: char) -> bool {
+    c.is_ascii_whitespace()
+}
+
+/// Options for `NodeRef::serialize_with_options`, built with chained methods:
+///
+/// ```rust
+/// use kuchiki::{AttributeOrder, SerializeOptions};
+///
+/// let options = SerializeOptions::new()
+///     .ascii_only(true)
+///     .attribute_order(AttributeOrder::Sorted);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SerializeOptions {
+    scripting_enabled: bool,
+    create_missing_parent: bool,
+    children_only: bool,
+    ascii_only: bool,
+    attribute_order: AttributeOrder,
+    quote_style: QuoteStyle,
+    normalize_whitespace: bool,
+    stable_prefixes: bool,
+}
+
+/// The order in which attributes are serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOrder {
+    /// The order of the source document, or of insertion.
+    Source,
+    /// Sorted by namespace, then local name.
+    Sorted,
+}
+
+/// The quotes around attribute values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// `name="value"`, with `"` escaped as `&quot;`.
+    Double,
+    /// `name='value'`, with `'` escaped as `&#39;`.
+    Single,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> SerializeOptions {
+        SerializeOptions {
+            scripting_enabled: true,
+            create_missing_parent: false,
+            children_only: false,
+            ascii_only: false,
+            attribute_order: AttributeOrder::Source,
+            quote_style: QuoteStyle::Double,
+            normalize_whitespace: false,
+            stable_prefixes: false,
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Return the default options, equivalent to `NodeRef::serialize`.
+    pub fn new() -> SerializeOptions {
+        SerializeOptions::default()
+    }
+
+    /// Return the options of canonical serialization, for hashing and snapshots.
+    ///
+    /// Attributes are sorted, whitespace is normalized,
+    /// and names in namespaces other than HTML, SVG, MathML, XML, XMLNS and XLink
+    /// are prefixed with `ns1`, `ns2`, etc. in order of first appearance.
+    /// Two trees that only differ in these respects have the same canonical serialization.
+    pub fn canonical() -> SerializeOptions {
+        SerializeOptions {
+            attribute_order: AttributeOrder::Sorted,
+            normalize_whitespace: true,
+            stable_prefixes: true,
+            ..SerializeOptions::default()
+        }
+    }
+
+    /// Whether scripting is considered enabled,
+    /// in which case the contents of `<noscript>` are written without escaping.
+    ///
+    /// Default: true
+    pub fn scripting_enabled(mut self, value: bool) -> SerializeOptions {
+        self.scripting_enabled = value;
+        self
+    }
+
+    /// Whether to write the start and end tags of the parents that an element needs
+    /// to be parsed back, such as `<table><tbody><tr>` around a `<td>`.
+    /// They are written without attributes,
+    /// around `<li>`, `<dt>`, `<dd>`, `<option>`, `<optgroup>` and table parts.
+    /// This does not apply with `children_only`, where the node is the context.
+    ///
+    /// Default: false
+    pub fn create_missing_parent(mut self, value: bool) -> SerializeOptions {
+        self.create_missing_parent = value;
+        self
+    }
+
+    /// Whether to only serialize the children of the node, like `innerHTML`,
+    /// rather than the node itself, like `outerHTML`.
+    ///
+    /// Default: false
+    pub fn children_only(mut self, value: bool) -> SerializeOptions {
+        self.children_only = value;
+        self
+    }
+
+    /// Whether to write non-ASCII characters as numeric character references.
+    /// Characters in comments and in raw text elements such as `<script>` cannot be escaped
+    /// and are written as-is.
+    ///
+    /// Default: false
+    pub fn ascii_only(mut self, value: bool) -> SerializeOptions {
+        self.ascii_only = value;
+        self
+    }
+
+    /// The order of attributes.
+    ///
+    /// Default: `AttributeOrder::Source`
+    pub fn attribute_order(mut self, value: AttributeOrder) -> SerializeOptions {
+        self.attribute_order = value;
+        self
+    }
+
+    /// The quotes around attribute values.
+    ///
+    /// Default: `QuoteStyle::Double`
+    pub fn quote_style(mut self, value: QuoteStyle) -> SerializeOptions {
+        self.quote_style = value;
+        self
+    }
+
+    /// Whether to collapse whitespace in text outside of whitespace-sensitive elements
+    /// such as `<pre>`: runs of ASCII whitespace are written as a single space,
+    /// or not at all next to the tags of block-level elements.
+    ///
+    /// Default: false
+    pub fn normalize_whitespace(mut self, value: bool) -> SerializeOptions {
+        self.normalize_whitespace = value;
+        self
+    }
+}
+
+struct ElemInfo {
+    raw_text: bool,
+    ignore_children: bool,
+    preserve_whitespace: bool,
+}
+
+/// A text stream that serialization writes to,
+/// so that the same serializers write to `io::Write` byte streams and `fmt::Write` text streams
+/// without converting between bytes and text.
+pub(crate) trait Output {
+    /// The error of a write: `Infallible` for in-memory streams.
+    type Error;
+
+    fn write_str(&mut self, text: &str) -> Result<(), Self::Error>;
+}
+
+impl Output for String {
+    type Error = Infallible;
+
+    fn write_str(&mut self, text: &str) -> Result<(), Infallible> {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+impl<'a> Output for fmt::Formatter<'a> {
+    type Error = fmt::Error;
+
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        fmt::Write::write_str(self, text)
+    }
+}
+
+/// An `io::Write` byte stream as an `Output`.
+pub(crate) struct IoOutput<W>(pub(crate) W);
+
+impl<W: Write> Output for IoOutput<W> {
+    type Error = Error;
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+}
+
+/// The value of a result that cannot be an error, such as that of writing to a `String`.
+pub(crate) fn into_ok<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(never) => match never {},
+    }
+}
+
+/// Like `html5ever::serialize::HtmlSerializer`, with `SerializeOptions` and any `Output`.
+struct OptionsSerializer<'a, O> {
+    output: &'a mut O,
+    options: &'a SerializeOptions,
+    stack: Vec<ElemInfo>,
+    /// With `normalize_whitespace`, whether a space is to be written before inline content.
+    pending_space: bool,
+    /// With `normalize_whitespace`, whether the last thing written is the tag of a block.
+    after_block_tag: bool,
+    /// With `stable_prefixes`, the namespaces that were given a prefix.
+    prefixed_namespaces: Vec<Namespace>,
+}
+
+impl<'a, O: Output> OptionsSerializer<'a, O> {
+    fn new(output: &'a mut O, options: &'a SerializeOptions, context: Option<&QualName>) -> Self {
+        OptionsSerializer {
+            output,
+            options,
+            stack: vec![ElemInfo {
+                raw_text: context.is_some_and(|name| is_raw_text(name, options.scripting_enabled)),
+                ignore_children: false,
+                preserve_whitespace: context.is_some_and(is_whitespace_sensitive),
+            }],
+            pending_space: false,
+            after_block_tag: true,
+            prefixed_namespaces: Vec::new(),
+        }
+    }
+
+    /// Write a node and its descendants, without recursion.
+    fn write_node(&mut self, node: &NodeRef) -> Result<(), O::Error> {
+        for edge in node.traverse_inclusive() {
+            match edge {
+                NodeEdge::Start(node) => match node.data() {
+                    NodeData::Element(element) => {
+                        self.start_elem(&element.name, &element.attributes.borrow())?
+                    }
+                    NodeData::Text(text) => self.write_text(&text.borrow())?,
+                    NodeData::Comment(text) => self.write_comment(&text.borrow())?,
+                    NodeData::Doctype(doctype) => self.write_doctype(&doctype.name)?,
+                    NodeData::ProcessingInstruction(contents) => {
+                        let contents = contents.borrow();
+                        self.write_processing_instruction(&contents.0, &contents.1)?
+                    }
+                    NodeData::Document(_) | NodeData::DocumentFragment => {}
+                },
+                NodeEdge::End(node) => {
+                    if let Some(element) = node.as_element() {
+                        self.end_elem(&element.name)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn preserve_whitespace(&self) -> bool {
+        !self.options.normalize_whitespace
+            || self
+                .stack
+                .last()
+                .is_some_and(|parent| parent.preserve_whitespace)
+    }
+
+    /// Drop or write a pending space before a tag.
+    fn before_tag(&mut self, name: &QualName) -> Result<(), O::Error> {
+        if self.preserve_whitespace() {
+            return Ok(());
+        }
+        if is_block(name) {
+            self.pending_space = false;
+            self.after_block_tag = true;
+        } else {
+            self.write_pending_space()?;
+            self.after_block_tag = false;
+        }
+        Ok(())
+    }
+
+    fn write_pending_space(&mut self) -> Result<(), O::Error> {
+        if self.pending_space {
+            self.pending_space = false;
+            self.output.write_str(" ")?
+        }
+        Ok(())
+    }
+
+    /// Write a prefix for a namespace without a well-known one.
+    fn write_prefix(&mut self, namespace: &Namespace) -> Result<(), O::Error> {
+        if !self.options.stable_prefixes {
+            return self.output.write_str("unknown_namespace:");
+        }
+        let index = match self
+            .prefixed_namespaces
+            .iter()
+            .position(|ns| ns == namespace)
+        {
+            Some(index) => index,
+            None => {
+                self.prefixed_namespaces.push(namespace.clone());
+                self.prefixed_namespaces.len() - 1
+            }
+        };
+        self.output.write_str(&format!("ns{}:", index + 1))
+    }
+
+    fn write_tag_name(&mut self, name: &QualName) -> Result<(), O::Error> {
+        match name.ns {
+            ns!(html) | ns!(svg) | ns!(mathml) => {}
+            ref ns if self.options.stable_prefixes => self.write_prefix(ns)?,
+            _ => {}
+        }
+        self.output.write_str(&name.local)
+    }
+
+    fn write_escaped(&mut self, text: &str, attr_mode: bool) -> Result<(), O::Error> {
+        let quote = match self.options.quote_style {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+        };
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            let escaped = match c {
+                '&' => Some("&amp;"),
+                '\u{00A0}' => Some("&nbsp;"),
+                '"' if attr_mode && quote == '"' => Some("&quot;"),
+                '\'' if attr_mode && quote == '\'' => Some("&#39;"),
+                '<' if !attr_mode => Some("&lt;"),
+                '>' if !attr_mode => Some("&gt;"),
+                c if self.options.ascii_only && !c.is_ascii() => None,
+                _ => continue,
+            };
+            self.output.write_str(&text[start..i])?;
+            match escaped {
+                Some(escaped) => self.output.write_str(escaped)?,
+                None => self.output.write_str(&format!("&#x{:X};", c as u32))?,
+            }
+            start = i + c.len_utf8();
+        }
+        self.output.write_str(&text[start..])
+    }
+
+    fn start_elem(&mut self, name: &QualName, attributes: &Attributes) -> Result<(), O::Error> {
+        let raw_text = is_raw_text(name, self.options.scripting_enabled);
+        let preserve_whitespace = self
+            .stack
+            .last()
+            .is_some_and(|parent| parent.preserve_whitespace)
+            || is_whitespace_sensitive(name);
+        if self
+            .stack
+            .last()
+            .is_some_and(|parent| parent.ignore_children)
+        {
+            self.stack.push(ElemInfo {
+                raw_text,
+                ignore_children: true,
+                preserve_whitespace,
+            });
+            return Ok(());
+        }
+        self.before_tag(name)?;
+
+        let mut attributes = attributes.map.iter().collect::<Vec<_>>();
+        if self.options.attribute_order == AttributeOrder::Sorted {
+            attributes.sort_by(|(a, _), (b, _)| (&*a.ns, &*a.local).cmp(&(&*b.ns, &*b.local)));
+        }
+        let quote = match self.options.quote_style {
+            QuoteStyle::Double => "\"",
+            QuoteStyle::Single => "'",
+        };
+        self.output.write_str("<")?;
+        self.write_tag_name(name)?;
+        for (name, attribute) in attributes {
+            self.output.write_str(" ")?;
+            match name.ns {
+                ns!() => {}
+                ns!(xml) => self.output.write_str("xml:")?,
+                ns!(xmlns) if name.local != local_name!("xmlns") => {
+                    self.output.write_str("xmlns:")?
+                }
+                ns!(xmlns) => {}
+                ns!(xlink) => self.output.write_str("xlink:")?,
+                ref ns => self.write_prefix(ns)?,
+            }
+            self.output.write_str(&name.local)?;
+            self.output.write_str("=")?;
+            self.output.write_str(quote)?;
+            self.write_escaped(&attribute.value, true)?;
+            self.output.write_str(quote)?;
+        }
+        self.output.write_str(">")?;
+
+        self.stack.push(ElemInfo {
+            raw_text,
+            ignore_children: is_void(name),
+            preserve_whitespace,
+        });
+        Ok(())
+    }
+
+    fn end_elem(&mut self, name: &QualName) -> Result<(), O::Error> {
+        // Start and end tags are balanced, so the context at the bottom of the stack stays.
+        if self.stack.pop().is_some_and(|info| info.ignore_children) {
+            return Ok(());
+        }
+        self.before_tag(name)?;
+        self.output.write_str("</")?;
+        self.write_tag_name(name)?;
+        self.output.write_str(">")
+    }
+
+    fn write_text(&mut self, text: &str) -> Result<(), O::Error> {
+        let escape = !self.stack.last().is_some_and(|parent| parent.raw_text);
+        if self.preserve_whitespace() {
+            return if escape {
+                self.write_escaped(text, false)
+            } else {
+                self.output.write_str(text)
+            };
+        }
+
+        let collapsed = collapse_whitespace(text);
+        let mut text = &*collapsed;
+        if self.after_block_tag || self.pending_space {
+            text = text.strip_prefix(' ').unwrap_or(text)
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.write_pending_space()?;
+        self.after_block_tag = false;
+        if let Some(trimmed) = text.strip_suffix(' ') {
+            self.pending_space = true;
+            text = trimmed
+        }
+        if escape {
+            self.write_escaped(text, false)
+        } else {
+            self.output.write_str(text)
+        }
+    }
+
+    fn write_comment(&mut self, text: &str) -> Result<(), O::Error> {
+        self.output.write_str("<!--")?;
+        self.output.write_str(text)?;
+        self.output.write_str("-->")
+    }
+
+    fn write_doctype(&mut self, name: &str) -> Result<(), O::Error> {
+        self.output.write_str("<!DOCTYPE ")?;
+        self.output.write_str(name)?;
+        self.output.write_str(">")
+    }
+
+    fn write_processing_instruction(&mut self, target: &str, data: &str) -> Result<(), O::Error> {
+        self.output.write_str("<?")?;
+        self.output.write_str(target)?;
+        self.output.write_str(" ")?;
+        self.output.write_str(data)?;
+        self.output.write_str(">")
+    }
+}
+
+/// <http://www.isthe.com/chongo/tech/comp/fnv/>
+struct Fnv1aHasher(u64);
+
+impl Output for Fnv1aHasher {
+    type Error = Infallible;
+
+    fn write_str(&mut self, text: &str) -> Result<(), Infallible> {
+        for &byte in text.as_bytes() {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+/// Adapts a `fmt::Write` text stream, such as a `String` or `fmt::Formatter`,
+/// to be used where serialization methods take an `io::Write` byte stream.
+///
+/// Bytes written must be UTF-8, as is the output of serialization methods.
+/// A character may be split across writes.
+/// An error from the text stream is returned as an `io::Error`.
+///
+/// `NodeRef` implements `fmt::Display`, which writes to a text stream without this conversion.
+pub struct FmtWriter<W: fmt::Write> {
+    writer: W,
+    /// Start of a character split across writes.
+    pending: Vec<u8>,
+}
+
+impl<W: fmt::Write> FmtWriter<W> {
+    /// Create a new adapter.
+    pub fn new(writer: W) -> Self {
+        FmtWriter {
+            writer,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Return the underlying text stream.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.writer
+            .write_str(text)
+            .map_err(|_| Error::new(ErrorKind::Other, "formatter error"))
+    }
+}
+
+impl<W: fmt::Write> Write for FmtWriter<W> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len();
+        if let Some(&first) = self.pending.first() {
+            // Complete the character with as many bytes as its first byte announces.
+            let char_len = match first {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                _ => 4,
+            };
+            let missing = (char_len - self.pending.len()).min(buf.len());
+            self.pending.extend_from_slice(&buf[..missing]);
+            buf = &buf[missing..];
+            if self.pending.len() < char_len {
+                return Ok(len);
+            }
+            let pending = std::mem::take(&mut self.pending);
+            match str::from_utf8(&pending) {
+                Ok(text) => self.write_str(text)?,
+                Err(error) => return Err(Error::new(ErrorKind::InvalidData, error)),
+            }
+        }
+        match str::from_utf8(buf) {
+            Ok(text) => self.write_str(text)?,
+            Err(error) if error.error_len().is_some() => {
+                return Err(Error::new(ErrorKind::InvalidData, error))
+            }
+            Err(error) => {
+                let (valid, incomplete) = buf.split_at(error.valid_up_to());
+                self.write_str(str::from_utf8(valid).unwrap())?;
+                self.pending.extend_from_slice(incomplete)
+            }
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "incomplete UTF-8 character",
+            ))
+        }
+    }
+}
+
+/// Serializes this node and its descendants in HTML syntax,
+/// streaming directly into the formatter.
+impl fmt::Display for NodeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_with_options(f, &SerializeOptions::default())
     }
 }
 
 impl NodeRef {
     /// Serialize this node and its descendants in HTML syntax to the given stream.
     #[inline]
-    pub fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
-        serialize(
-            writer,
-            self,
-            SerializeOpts {
-                traversal_scope: IncludeNode,
-                ..Default::default()
-            },
-        )
+    pub fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.serialize_with_options(writer, &SerializeOptions::default())
+    }
+
+    /// Serialize this node and its descendants in HTML syntax to the given stream,
+    /// calling `filter` on each node as it is reached.
+    ///
+    /// For an element, the filter is also given its attributes,
+    /// which it can change through `Cow::to_mut` to rewrite the serialized attributes.
+    /// They are only copied if changed, and are borrowed while the filter runs.
+    /// For other nodes, the attributes are empty and changes to them are ignored.
+    /// The return value determines whether the node and its descendants are serialized.
+    /// The tree itself is not modified.
+    pub fn serialize_filtered<W, F>(&self, writer: &mut W, mut filter: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&NodeRef, &mut Cow<Attributes>) -> FilterAction,
+    {
+        let options = SerializeOptions::default();
+        let mut output = IoOutput(writer);
+        let mut serializer = OptionsSerializer::new(&mut output, &options, None);
+        write_filtered(&mut serializer, self, &mut filter)
+    }
+
+    /// Serialize this node and/or its descendants in HTML syntax to the given stream,
+    /// with the given options.
+    pub fn serialize_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &SerializeOptions,
+    ) -> io::Result<()> {
+        self.write_with_options(&mut IoOutput(writer), options)
+    }
+
+    fn write_with_options<O: Output>(
+        &self,
+        output: &mut O,
+        options: &SerializeOptions,
+    ) -> Result<(), O::Error> {
+        if !options.children_only {
+            let parents = match self.as_element() {
+                Some(element) if options.create_missing_parent => missing_parents(element),
+                _ => Vec::new(),
+            };
+            let no_attributes = Attributes {
+                map: Default::default(),
+            };
+            let mut serializer = OptionsSerializer::new(output, options, None);
+            for name in &parents {
+                serializer.start_elem(name, &no_attributes)?
+            }
+            serializer.write_node(self)?;
+            for name in parents.iter().rev() {
+                serializer.end_elem(name)?
+            }
+            return Ok(());
+        }
+        let context = self.as_element().map(|element| &element.name);
+        let mut serializer = OptionsSerializer::new(output, options, context);
+        for child in self.children() {
+            serializer.write_node(&child)?
+        }
+        Ok(())
+    }
+
+    /// Return the canonical serialization of this node and its descendants.
+    ///
+    /// See `SerializeOptions::canonical`.
+    pub fn to_canonical_string(&self) -> String {
+        let mut text = String::new();
+        into_ok(self.write_with_options(&mut text, &SerializeOptions::canonical()));
+        text
+    }
+
+    /// Return a hash of the canonical serialization of this node and its descendants,
+    /// for change detection.
+    ///
+    /// This is the 64-bit FNV-1a hash, which does not depend on the platform or process.
+    pub fn canonical_hash(&self) -> u64 {
+        let mut hasher = Fnv1aHasher(0xcbf2_9ce4_8422_2325);
+        into_ok(self.write_with_options(&mut hasher, &SerializeOptions::canonical()));
+        hasher.0
+    }
+
+    /// Serialize this node and its descendants in HTML syntax to the given stream,
+    /// reindenting block-level content.
+    ///
+    /// Children of block-level elements that only contain other block-level elements,
+    /// comments or whitespace are put on their own lines,
+    /// with `indent` repeated once per nesting level.
+    /// Everything else, including inline formatting and the contents of `<pre>`, `<textarea>`,
+    /// `<script>` and `<style>` elements, is written as-is so that rendering is unchanged.
+    pub fn serialize_pretty<W: Write>(&self, writer: &mut W, indent: &str) -> io::Result<()> {
+        self.write_pretty(&mut IoOutput(writer), indent)
+    }
+
+    /// Return the result of `serialize_pretty` as a string.
+    pub fn to_pretty_string(&self, indent: &str) -> String {
+        let mut text = String::new();
+        into_ok(self.write_pretty(&mut text, indent));
+        text
+    }
+
+    fn write_pretty<O: Output>(&self, output: &mut O, indent: &str) -> Result<(), O::Error> {
+        let options = SerializeOptions::default();
+        PrettyPrinter {
+            serializer: &mut OptionsSerializer::new(output, &options, None),
+            indent,
+        }
+        .write_node(self)
     }
 
     /// Serialize this node and its descendants in HTML syntax to a new file at the given path.
     #[inline]
-    pub fn serialize_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    pub fn serialize_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
         let mut file = File::create(&path)?;
         self.serialize(&mut file)
     }
@@ -0,0 +1,731 @@
+//! CSS stylesheets and the cascade, without layout.
+//!
+//! Style rules whose selectors are supported are parsed into `StyleRule`s.
+//! Other rules, including at-rules such as `@media`, are kept verbatim as `Rule::Other`
+//! and do not take part in the cascade.
+//! Shorthand properties are not expanded.
+//!
+//! ```
+//! use kuchiki::traits::*;
+//! use kuchiki::style::Cascade;
+//!
+//! let document = kuchiki::parse_html().one(
+//!     "<style>p { display: none } .shown { display: block }</style><p class=shown>Hi</p>",
+//! );
+//! let cascade = Cascade::from_document(&document);
+//! let p = document.select_first("p").unwrap();
+//! assert_eq!(cascade.computed_value(&p, "display").as_deref(), Some("block"));
+//! ```
+
+use cssparser::{
+    parse_important, AtRuleParser, AtRuleType, CowRcStr, DeclarationListParser, DeclarationParser,
+    ParseError, Parser, ParserInput, QualifiedRuleParser, RuleListParser, SourceLocation, Token,
+};
+use indexmap::IndexMap;
+use std::fmt;
+
+use crate::iter::NodeIterator;
+use crate::node_data_ref::NodeDataRef;
+use crate::select::{Selector, Selectors, Specificity};
+use crate::tree::{ElementData, NodeRef};
+
+/// A CSS declaration, such as `color: red !important`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    /// The property name, in ASCII lower case except for custom properties.
+    pub name: String,
+    /// The value, as written, without `!important`.
+    pub value: String,
+    /// Whether the declaration is `!important`.
+    pub important: bool,
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)?;
+        if self.important {
+            f.write_str(" !important")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a list of declarations, such as the contents of a `style` attribute.
+///
+/// Invalid declarations are skipped.
+pub fn parse_declarations(css: &str) -> Vec<Declaration> {
+    let mut input = ParserInput::new(css);
+    parse_declaration_list(&mut Parser::new(&mut input))
+}
+
+/// Serialize a list of declarations, for example for a `style` attribute.
+pub fn serialize_declarations(declarations: &[Declaration]) -> String {
+    declarations
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A parsed stylesheet.
+#[derive(Debug, Clone, Default)]
+pub struct Stylesheet {
+    /// The rules, in source order.
+    pub rules: Vec<Rule>,
+}
+
+/// A rule of a stylesheet.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// A style rule with supported selectors.
+    Style(StyleRule),
+    /// Any other rule, such as an at-rule or a rule with unsupported selectors, as written.
+    Other(String),
+}
+
+/// A style rule, such as `p.note { color: red }`.
+#[derive(Debug, Clone)]
+pub struct StyleRule {
+    /// The selectors of the rule.
+    pub selectors: Selectors,
+    /// The declarations of the rule, in source order.
+    pub declarations: Vec<Declaration>,
+}
+
+impl Stylesheet {
+    /// Parse a stylesheet.
+    pub fn parse(css: &str) -> Stylesheet {
+        let mut input = ParserInput::new(css);
+        let mut input = Parser::new(&mut input);
+        let mut parser = RuleListParser::new_for_stylesheet(&mut input, RuleParser);
+        let mut rules = Vec::new();
+        loop {
+            let start = parser.input.position();
+            // `RuleListParser` skips `@charset` rules, which are kept like other at-rules.
+            if parser.input.try_parse(parse_charset).is_ok() {
+                let source = parser.input.slice_from(start).trim();
+                rules.push(Rule::Other(source.to_owned()));
+                continue;
+            }
+            match parser.next() {
+                Some(Ok(Rule::Style(rule))) => rules.push(Rule::Style(rule)),
+                // The source of other rules is only known once they are consumed.
+                Some(Ok(Rule::Other(_))) | Some(Err(_)) => {
+                    let source = parser.input.slice_from(start).trim();
+                    rules.push(Rule::Other(source.to_owned()))
+                }
+                None => return Stylesheet { rules },
+            }
+        }
+    }
+
+    /// Parse and concatenate the `<style>` elements of a document, in document order.
+    ///
+    /// Elements with a `type` other than `text/css` are ignored.
+    pub fn from_document(document: &NodeRef) -> Stylesheet {
+        let mut rules = Vec::new();
+        for style in document.inclusive_descendants().elements() {
+            if is_css_style_element(&style) {
+                rules.extend(Stylesheet::parse(&style.text_contents()).rules)
+            }
+        }
+        Stylesheet { rules }
+    }
+}
+
+/// Consume a `@charset` rule, up to its semicolon.
+fn parse_charset<'i, 't>(input: &mut Parser<'i, 't>) -> Result<(), ParseError<'i, ()>> {
+    match input.next()? {
+        Token::AtKeyword(name) if name.eq_ignore_ascii_case("charset") => {}
+        _ => return Err(input.new_custom_error(())),
+    }
+    loop {
+        match input.next() {
+            Ok(Token::Semicolon) | Err(_) => return Ok(()),
+            Ok(_) => {}
+        }
+    }
+}
+
+fn is_css_style_element(element: &NodeDataRef<ElementData>) -> bool {
+    element.name.ns == ns!(html)
+        && element.name.local == local_name!("style")
+        && element
+            .attributes
+            .borrow()
+            .get(local_name!("type"))
+            .map_or(true, |t| t.is_empty() || t.eq_ignore_ascii_case("text/css"))
+}
+
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Style(rule) => rule.fmt(f),
+            Rule::Other(source) => f.write_str(source),
+        }
+    }
+}
+
+impl fmt::Display for StyleRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {{ {} }}",
+            self.selectors,
+            serialize_declarations(&self.declarations)
+        )
+    }
+}
+
+struct RuleParser;
+
+impl<'i> QualifiedRuleParser<'i> for RuleParser {
+    type Prelude = Option<Selectors>;
+    type QualifiedRule = Rule;
+    type Error = ();
+
+    fn parse_prelude<'t>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Option<Selectors>, ParseError<'i, ()>> {
+        let start = input.position();
+        while input.next().is_ok() {}
+        Ok(Selectors::compile(input.slice_from(start).trim()).ok())
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        selectors: Option<Selectors>,
+        _location: SourceLocation,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Rule, ParseError<'i, ()>> {
+        Ok(match selectors {
+            Some(selectors) => Rule::Style(StyleRule {
+                selectors,
+                declarations: parse_declaration_list(input),
+            }),
+            None => Rule::Other(String::new()),
+        })
+    }
+}
+
+/// At-rules with a block are consumed whole, to be kept verbatim by `Stylesheet::parse`.
+/// Those without a block, such as `@import`, are rejected and kept verbatim too.
+impl<'i> AtRuleParser<'i> for RuleParser {
+    type PreludeNoBlock = ();
+    type PreludeBlock = ();
+    type AtRule = Rule;
+    type Error = ();
+
+    fn parse_prelude<'t>(
+        &mut self,
+        _name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<AtRuleType<(), ()>, ParseError<'i, ()>> {
+        while input.next().is_ok() {}
+        Ok(AtRuleType::WithBlock(()))
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        _prelude: (),
+        _location: SourceLocation,
+        _input: &mut Parser<'i, 't>,
+    ) -> Result<Rule, ParseError<'i, ()>> {
+        Ok(Rule::Other(String::new()))
+    }
+}
+
+fn parse_declaration_list(input: &mut Parser) -> Vec<Declaration> {
+    DeclarationListParser::new(input, DeclarationsParser)
+        .filter_map(Result::ok)
+        .collect()
+}
+
+struct DeclarationsParser;
+
+impl<'i> DeclarationParser<'i> for DeclarationsParser {
+    type Declaration = Declaration;
+    type Error = ();
+
+    fn parse_value<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Declaration, ParseError<'i, ()>> {
+        let start = input.position();
+        let mut end = start;
+        let mut important = false;
+        loop {
+            let at_important = input.try_parse(|input| {
+                parse_important(input)?;
+                input.expect_exhausted()
+            });
+            if at_important.is_ok() {
+                important = true;
+                break;
+            }
+            if input.next_including_whitespace_and_comments().is_err() {
+                break;
+            }
+            end = input.position();
+        }
+        let value = input.slice(start..end).trim();
+        if value.is_empty() {
+            return Err(input.new_custom_error(()));
+        }
+        let name = if name.starts_with("--") {
+            name.to_string()
+        } else {
+            name.to_ascii_lowercase()
+        };
+        Ok(Declaration {
+            name,
+            value: value.to_owned(),
+            important,
+        })
+    }
+}
+
+impl<'i> AtRuleParser<'i> for DeclarationsParser {
+    type PreludeNoBlock = ();
+    type PreludeBlock = ();
+    type AtRule = Declaration;
+    type Error = ();
+}
+
+/// Properties inherited by default, from the CSS specifications.
+static INHERITED_PROPERTIES: &[&str] = &[
+    "border-collapse",
+    "border-spacing",
+    "caption-side",
+    "color",
+    "cursor",
+    "direction",
+    "empty-cells",
+    "font",
+    "font-family",
+    "font-feature-settings",
+    "font-kerning",
+    "font-size",
+    "font-size-adjust",
+    "font-stretch",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "hyphens",
+    "letter-spacing",
+    "line-height",
+    "list-style",
+    "list-style-image",
+    "list-style-position",
+    "list-style-type",
+    "orphans",
+    "overflow-wrap",
+    "quotes",
+    "tab-size",
+    "text-align",
+    "text-align-last",
+    "text-indent",
+    "text-justify",
+    "text-shadow",
+    "text-transform",
+    "visibility",
+    "white-space",
+    "widows",
+    "word-break",
+    "word-spacing",
+    "word-wrap",
+    "writing-mode",
+];
+
+/// Returns whether a property is inherited by default. Custom properties are.
+pub fn is_inherited(property: &str) -> bool {
+    property.starts_with("--") || INHERITED_PROPERTIES.contains(&property)
+}
+
+/// Property values for an element, keyed by property name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    properties: IndexMap<String, String>,
+}
+
+impl Style {
+    /// Return the value of a property, if any.
+    #[inline]
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.properties.get(property).map(|v| &**v)
+    }
+
+    /// Iterate over property names and values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties.iter().map(|(k, v)| (&**k, &**v))
+    }
+
+    /// The number of properties with a value.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Whether no property has a value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+}
+
+/// Computes the style of elements from a stylesheet and their `style` attributes.
+///
+/// Only author styles are considered: there is no user agent stylesheet,
+/// so properties that are not declared have no value.
+#[derive(Debug, Clone, Default)]
+pub struct Cascade {
+    /// The author stylesheet.
+    pub stylesheet: Stylesheet,
+}
+
+impl Cascade {
+    /// Create a cascade for the given stylesheet.
+    #[inline]
+    pub fn new(stylesheet: Stylesheet) -> Cascade {
+        Cascade { stylesheet }
+    }
+
+    /// Create a cascade for the `<style>` elements of a document.
+    #[inline]
+    pub fn from_document(document: &NodeRef) -> Cascade {
+        Cascade::new(Stylesheet::from_document(document))
+    }
+
+    /// Return the cascaded values of the properties declared for an element,
+    /// without inheritance.
+    ///
+    /// `!important` declarations win over normal ones,
+    /// then `style` attributes over stylesheets,
+    /// then higher specificity, then later declarations.
+    pub fn cascaded_style(&self, element: &NodeDataRef<ElementData>) -> Style {
+        let mut style = Style::default();
+        for declaration in self.cascaded_declarations(element) {
+            style.properties.insert(declaration.name, declaration.value);
+        }
+        style
+    }
+
+    /// The winning declaration for each property declared for an element.
+    fn cascaded_declarations(&self, element: &NodeDataRef<ElementData>) -> Vec<Declaration> {
+        let inline = element
+            .attributes
+            .borrow()
+            .get(local_name!("style"))
+            .map(parse_declarations)
+            .unwrap_or_default();
+        let mut declarations = Vec::new();
+        let mut order = 0;
+        for rule in &self.stylesheet.rules {
+            if let Rule::Style(rule) = rule {
+                if let Some(selector) = rule.selectors.matching_selector(element) {
+                    let specificity = selector.specificity();
+                    for declaration in &rule.declarations {
+                        let key = (declaration.important, false, specificity, order);
+                        declarations.push((key, declaration));
+                        order += 1;
+                    }
+                }
+            }
+        }
+        for declaration in &inline {
+            let key = (
+                declaration.important,
+                true,
+                Specificity::new(0, 0, 0),
+                order,
+            );
+            declarations.push((key, declaration));
+            order += 1;
+        }
+        declarations.sort_by_key(|&(key, _)| key);
+
+        let mut winners = IndexMap::new();
+        for (_, declaration) in declarations {
+            winners.insert(&*declaration.name, declaration);
+        }
+        winners.into_iter().map(|(_, d)| d.clone()).collect()
+    }
+
+    /// Return the values of properties for an element,
+    /// inheriting from ancestors for inherited properties
+    /// and resolving the `inherit`, `initial` and `unset` keywords.
+    ///
+    /// Values are otherwise as declared: lengths, colors, etc. are not computed.
+    pub fn computed_style(&self, element: &NodeDataRef<ElementData>) -> Style {
+        let ancestors = element
+            .as_node()
+            .inclusive_ancestors()
+            .elements()
+            .collect::<Vec<_>>();
+        let mut parent = Style::default();
+        for element in ancestors.iter().rev() {
+            let mut style = Style::default();
+            for (name, value) in parent.iter() {
+                if is_inherited(name) {
+                    style.properties.insert(name.to_owned(), value.to_owned());
+                }
+            }
+            for (name, value) in self.cascaded_style(element).properties {
+                let inherit = if value.eq_ignore_ascii_case("inherit") {
+                    true
+                } else if value.eq_ignore_ascii_case("unset") {
+                    is_inherited(&name)
+                } else if value.eq_ignore_ascii_case("initial") {
+                    false
+                } else {
+                    style.properties.insert(name, value);
+                    continue;
+                };
+                match parent.get(&name).filter(|_| inherit) {
+                    Some(value) => style.properties.insert(name, value.to_owned()),
+                    None => style.properties.shift_remove(&name),
+                };
+            }
+            parent = style;
+        }
+        parent
+    }
+
+    /// Return the computed value of one property for an element.
+    #[inline]
+    pub fn computed_value(
+        &self,
+        element: &NodeDataRef<ElementData>,
+        property: &str,
+    ) -> Option<String> {
+        self.computed_style(element)
+            .get(property)
+            .map(str::to_owned)
+    }
+}
+
+/// Options for `inline_styles`.
+#[derive(Debug, Clone, Default)]
+pub struct InlineOpts {
+    /// Remove the rules that were inlined from `<style>` elements,
+    /// and `<style>` elements left without rules.
+    ///
+    /// Other rules, such as `@media` blocks and rules with selectors like `:hover`, are kept.
+    pub remove_inlined_rules: bool,
+}
+
+/// Apply the rules of a document’s `<style>` elements to the `style` attributes
+/// of the elements they match, as is usually needed for HTML email.
+///
+/// Existing `style` attributes are merged following the cascade.
+/// The winning declarations keep their `!important`, if any,
+/// so that they still take precedence over `!important` rules left in stylesheets.
+/// Elements in `<head>` are left alone.
+pub fn inline_styles(document: &NodeRef, opts: &InlineOpts) {
+    let style_elements = document
+        .inclusive_descendants()
+        .elements()
+        .filter(is_css_style_element)
+        .collect::<Vec<_>>();
+    let stylesheets = style_elements
+        .iter()
+        .map(|style| Stylesheet::parse(&style.text_contents()))
+        .collect::<Vec<_>>();
+    let cascade = Cascade::new(Stylesheet {
+        rules: stylesheets
+            .iter()
+            .flat_map(|stylesheet| stylesheet.rules.iter().cloned())
+            .collect(),
+    });
+
+    // Compute everything before changing attributes that selectors could depend on.
+    let mut styles = Vec::new();
+    for element in document.inclusive_descendants().elements() {
+        let in_head = element
+            .as_node()
+            .inclusive_ancestors()
+            .elements()
+            .any(|e| e.name.ns == ns!(html) && e.name.local == local_name!("head"));
+        if in_head {
+            continue;
+        }
+        let declarations = cascade.cascaded_declarations(&element);
+        if !declarations.is_empty() {
+            styles.push((element, serialize_declarations(&declarations)));
+        }
+    }
+    for (element, style) in styles {
+        element
+            .attributes
+            .borrow_mut()
+            .insert(local_name!("style"), style);
+    }
+
+    if opts.remove_inlined_rules {
+        for (element, stylesheet) in style_elements.into_iter().zip(stylesheets) {
+            let rules = stylesheet
+                .rules
+                .into_iter()
+                .filter_map(|rule| match rule {
+                    Rule::Style(mut rule) => {
+                        rule.selectors.0.retain(Selector::is_dynamic);
+                        if rule.selectors.0.is_empty() {
+                            None
+                        } else {
+                            Some(Rule::Style(rule))
+                        }
+                    }
+                    other => Some(other),
+                })
+                .collect::<Vec<_>>();
+            if rules.is_empty() {
+                element.as_node().detach();
+            } else {
+                let text = Stylesheet { rules }.to_string();
+                let node = element.as_node();
+                for child in node.children() {
+                    child.detach();
+                }
+                node.append(NodeRef::new_text(text));
+            }
+        }
+    }
+}
+
+impl ElementData {
+    /// Return an editable view of the declarations of this element’s `style` attribute.
+    ///
+    /// When a property is declared more than once, the declaration that wins the cascade is kept.
+    /// Changes are written back to the attribute, normalized,
+    /// and the attribute is removed when no declaration remains.
+    pub fn style(&self) -> InlineStyle<'_> {
+        let mut style = InlineStyle {
+            element: self,
+            declarations: Vec::new(),
+        };
+        let attributes = self.attributes.borrow();
+        for declaration in parse_declarations(attributes.get(local_name!("style")).unwrap_or("")) {
+            match style.position(&declaration.name) {
+                Some(i) if style.declarations[i].important && !declaration.important => {}
+                Some(i) => {
+                    style.declarations.remove(i);
+                    style.declarations.push(declaration)
+                }
+                None => style.declarations.push(declaration),
+            }
+        }
+        style
+    }
+}
+
+/// The declarations of a `style` attribute, as returned by `ElementData::style`.
+#[derive(Debug)]
+pub struct InlineStyle<'a> {
+    element: &'a ElementData,
+    declarations: Vec<Declaration>,
+}
+
+#[allow(clippy::result_unit_err)] // Invalid values, like invalid selectors in `Selectors::compile`
+impl<'a> InlineStyle<'a> {
+    /// The declarations, in order.
+    #[inline]
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    /// The number of declarations.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.declarations.len()
+    }
+
+    /// Whether there is no declaration.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
+
+    fn position(&self, property: &str) -> Option<usize> {
+        if property.starts_with("--") {
+            self.declarations.iter().position(|d| d.name == property)
+        } else {
+            self.declarations
+                .iter()
+                .position(|d| d.name.eq_ignore_ascii_case(property))
+        }
+    }
+
+    /// Return the value of a property, if declared.
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.position(property)
+            .map(|i| &*self.declarations[i].value)
+    }
+
+    /// Return whether a property is declared with `!important`.
+    pub fn is_important(&self, property: &str) -> bool {
+        self.position(property)
+            .is_some_and(|i| self.declarations[i].important)
+    }
+
+    /// Set a property to a value, without `!important`.
+    ///
+    /// This fails if the value is not a single valid declaration value.
+    #[inline]
+    pub fn set(&mut self, property: &str, value: &str) -> Result<(), ()> {
+        self.set_with_priority(property, value, false)
+    }
+
+    /// Set a property to a value, with or without `!important`.
+    ///
+    /// An existing declaration is replaced in place; a new one is added at the end.
+    /// This fails if the value is not a single valid declaration value.
+    pub fn set_with_priority(
+        &mut self,
+        property: &str,
+        value: &str,
+        important: bool,
+    ) -> Result<(), ()> {
+        let mut parsed = parse_declarations(&format!("{}: {}", property, value));
+        if parsed.len() != 1 || parsed[0].important || parsed[0].value != value.trim() {
+            return Err(());
+        }
+        let mut declaration = parsed.remove(0);
+        declaration.important = important;
+        match self.position(property) {
+            Some(i) => self.declarations[i] = declaration,
+            None => self.declarations.push(declaration),
+        }
+        self.write_back();
+        Ok(())
+    }
+
+    /// Remove a property, returning its declaration if it was declared.
+    pub fn remove(&mut self, property: &str) -> Option<Declaration> {
+        let declaration = self.declarations.remove(self.position(property)?);
+        self.write_back();
+        Some(declaration)
+    }
+
+    fn write_back(&self) {
+        let mut attributes = self.element.attributes.borrow_mut();
+        if self.declarations.is_empty() {
+            attributes.remove(local_name!("style"));
+        } else {
+            attributes.insert(
+                local_name!("style"),
+                serialize_declarations(&self.declarations),
+            );
+        }
+    }
+}
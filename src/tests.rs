@@ -7,6 +7,15 @@ use tempfile::TempDir;
 use crate::parser::{parse_html, parse_fragment};
 use crate::select::*;
 use crate::traits::*;
+use crate::style::{parse_declarations, serialize_declarations, Cascade, Rule, Stylesheet};
+use crate::tree::{ElementData, NodeRef};
+use crate::iter::NodeEdge;
+use crate::NodeDataRef;
+use crate::{AttributeError, Date, DateOrTime, Dimension, ImageCandidate, ImageDescriptor, Time};
+use crate::{AttributeOrder, FilterAction, FmtWriter, QuoteStyle, SerializeOptions};
+use crate::PlainTextOpts;
+use std::borrow::Cow;
+use crate::xpath::{Value, XPath, XPathError, XPathNode};
 
 #[test]
 fn text_nodes() {
@@ -69,7 +78,7 @@ fn parse_and_serialize_fragment() {
 #[test]
 fn parse_file() {
     let mut path = Path::new(env!("CARGO_MANIFEST_DIR")).to_path_buf();
-    path.push("test_data".to_string());
+    path.push("test_data");
     path.push("foo.html");
 
     let html = r"<!DOCTYPE html><html><head>
@@ -170,6 +179,150 @@ fn to_string() {
     );
 }
 
+const DISPLAY_HTML: &str = "<p title=\"été\">naïve ☃</p>";
+
+fn display_paragraph() -> NodeRef {
+    let document = parse_fragment(
+        QualName::new(None, ns!(html), local_name!("body")),
+        Vec::new(),
+    )
+    .one(DISPLAY_HTML);
+    document.select_first("p").unwrap().as_node().clone()
+}
+
+/// A text stream that always fails.
+struct FailingFmt;
+
+impl std::fmt::Write for FailingFmt {
+    fn write_str(&mut self, _: &str) -> std::fmt::Result {
+        Err(std::fmt::Error)
+    }
+}
+
+/// A byte stream that always fails.
+struct FailingIo;
+
+impl std::io::Write for FailingIo {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "failing"))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn display() {
+    let paragraph = display_paragraph();
+    assert_eq!(format!("{}", paragraph), DISPLAY_HTML);
+    let mut u8_vec = Vec::new();
+    paragraph.serialize(&mut u8_vec).unwrap();
+    assert_eq!(String::from_utf8(u8_vec).unwrap(), DISPLAY_HTML);
+}
+
+#[test]
+fn display_into_fmt_write() {
+    use std::fmt::Write as _;
+
+    let mut text = String::new();
+    write!(text, "[{}]", display_paragraph()).unwrap();
+    assert_eq!(text, format!("[{}]", DISPLAY_HTML));
+}
+
+/// Return an element with `depth` levels of elements of the same name nested in it,
+/// and the innermost one.
+fn nested_elements(name: html5ever::LocalName, depth: usize) -> (NodeRef, NodeRef) {
+    let new_element = || NodeRef::new_element(QualName::new(None, ns!(html), name.clone()), None);
+    let root = new_element();
+    let mut deepest = root.clone();
+    for _ in 0..depth {
+        let element = new_element();
+        deepest.append(element.clone());
+        deepest = element;
+    }
+    (root, deepest)
+}
+
+#[test]
+fn display_deep_tree() {
+    let (root, _) = nested_elements(local_name!("div"), 10_000);
+    let html = root.to_string();
+    assert!(html.starts_with("<div><div>"));
+    assert_eq!(html.len(), 10_001 * "<div></div>".len());
+}
+
+#[test]
+fn display_writer_errors() {
+    use std::fmt::Write as _;
+
+    assert!(write!(FailingFmt, "{}", display_paragraph()).is_err());
+}
+
+#[test]
+fn serialize_writer_errors() {
+    let paragraph = display_paragraph();
+    assert!(paragraph.serialize(&mut FailingIo).is_err());
+    assert!(paragraph.serialize_minified(&mut FailingIo).is_err());
+    assert!(paragraph.serialize_pretty(&mut FailingIo, "  ").is_err());
+    assert!(paragraph
+        .serialize_with_options(&mut FailingIo, &SerializeOptions::canonical())
+        .is_err());
+}
+
+#[test]
+fn fmt_writer() {
+    let mut writer = FmtWriter::new(String::new());
+    display_paragraph().serialize(&mut writer).unwrap();
+    assert_eq!(writer.into_inner(), DISPLAY_HTML);
+}
+
+#[test]
+fn fmt_writer_split_characters() {
+    use std::io::Write as _;
+
+    let mut writer = FmtWriter::new(String::new());
+    for byte in DISPLAY_HTML.bytes() {
+        writer.write_all(&[byte]).unwrap();
+    }
+    writer.flush().unwrap();
+    assert_eq!(writer.into_inner(), DISPLAY_HTML);
+
+    let mut writer = FmtWriter::new(String::new());
+    let bytes = "a☃b𝄞".as_bytes();
+    writer.write_all(&bytes[..2]).unwrap();
+    writer.write_all(&bytes[2..6]).unwrap();
+    writer.write_all(&bytes[6..]).unwrap();
+    assert_eq!(writer.into_inner(), "a☃b𝄞");
+}
+
+#[test]
+fn fmt_writer_incomplete_character() {
+    use std::io::Write as _;
+
+    let mut writer = FmtWriter::new(String::new());
+    writer.write_all(&"☃".as_bytes()[..2]).unwrap();
+    assert!(writer.flush().is_err());
+}
+
+#[test]
+fn fmt_writer_invalid_bytes() {
+    use std::io::Write as _;
+
+    let mut writer = FmtWriter::new(String::new());
+    assert!(writer.write_all(b"\xFF").is_err());
+    let mut writer = FmtWriter::new(String::new());
+    writer.write_all(&"☃".as_bytes()[..2]).unwrap();
+    assert!(writer.write_all(b"\xFF").is_err());
+}
+
+#[test]
+fn fmt_writer_errors() {
+    assert!(display_paragraph()
+        .serialize(&mut FmtWriter::new(FailingFmt))
+        .is_err());
+}
+
 #[test]
 fn specificity() {
     let selectors = Selectors::compile(".example, :first-child, div").unwrap();
@@ -183,3 +336,2613 @@ fn specificity() {
     assert!(specificities[0] > specificities[2]);
     assert!(specificities[1] > specificities[2]);
 }
+
+#[test]
+fn selector_cache_evicts_least_recently_used() {
+    let cache = SelectorCache::new(2);
+    assert!(cache.is_empty());
+    cache.compile("p").unwrap();
+    cache.compile("a").unwrap();
+    assert_eq!(cache.len(), 2);
+
+    // "p" was used more recently than "a", so "a" is evicted.
+    cache.compile("p").unwrap();
+    cache.compile("div").unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(
+        format!("{:?}", cache),
+        r#"SelectorCache { capacity: 2, entries: ["p", "div"] }"#
+    );
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn selector_cache_hit_returns_cached_selectors() {
+    let cache = SelectorCache::new(2);
+    let first = cache.compile("p.foo").unwrap();
+    let second = cache.compile("p.foo").unwrap();
+    assert_eq!(first.to_string(), second.to_string());
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn selector_cache_does_not_cache_errors() {
+    let cache = SelectorCache::new(2);
+    assert!(cache.compile("p:").is_err());
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn selector_cache_with_zero_capacity() {
+    let cache = SelectorCache::new(0);
+    cache.compile("p").unwrap();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn select_compiled() {
+    let html = r"
+<title>Test case</title>
+<p class=foo>Foo
+<p>Bar
+<p class=foo>Baz
+";
+
+    let document = parse_html().one(html);
+    let selectors = Selectors::compile("p.foo").unwrap();
+    let matching = document.select_compiled(&selectors).collect::<Vec<_>>();
+    assert_eq!(matching.len(), 2);
+    assert_eq!(
+        matching,
+        document.select("p.foo").unwrap().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        document.select_first_compiled(&selectors).unwrap(),
+        matching[0]
+    );
+    assert!(document
+        .select_first_compiled(&Selectors::compile("p.bar").unwrap())
+        .is_none());
+}
+
+fn select_ids(document: &NodeRef, selectors: &str) -> Vec<String> {
+    document
+        .select(selectors)
+        .unwrap()
+        .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn has_descendant() {
+    let html = r"
+<article id=a><p><img></p></article>
+<article id=b><img></article>
+<article id=c><p>No image</p></article>
+";
+    let document = parse_html().one(html);
+    assert_eq!(select_ids(&document, "article:has(img)"), ["a", "b"]);
+    assert_eq!(select_ids(&document, "article:has(> img)"), ["b"]);
+    assert_eq!(select_ids(&document, "article:has(> p img)"), ["a"]);
+    assert_eq!(
+        select_ids(&document, "article:has(> p, > img)"),
+        ["a", "b", "c"]
+    );
+    // The anchor itself is not a candidate, and neither are its ancestors.
+    assert_eq!(
+        select_ids(&document, "article:has(body img)"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn has_sibling() {
+    let html = r"
+<h2 id=d></h2><p>After d</p>
+<h2 id=e></h2><h2 id=f></h2><div></div><p>After f</p>
+<h2 id=g></h2><div></div>
+";
+    let document = parse_html().one(html);
+    assert_eq!(select_ids(&document, "h2:has(+ p)"), ["d"]);
+    assert_eq!(select_ids(&document, "h2:has(~ p)"), ["d", "e", "f"]);
+    assert_eq!(select_ids(&document, "h2:has(~ h2 + div)"), ["d", "e", "f"]);
+}
+
+#[test]
+fn has_negated() {
+    let html = r"
+<article id=a><p><img></p></article>
+<article id=b><p>No image</p></article>
+";
+    let document = parse_html().one(html);
+    assert_eq!(select_ids(&document, "article:not(:has(img))"), ["b"]);
+    assert_eq!(
+        select_ids(&document, "article:has(p:not(:has(img)))"),
+        ["b"]
+    );
+}
+
+#[test]
+fn has_nested_anchors() {
+    // Matching an outer element remembers results for inner ones,
+    // which must be the same as when they are matched first.
+    let html = r"
+<div id=a><div id=b><span></span></div><div id=c><p></p></div></div>
+<div id=d><div id=e><p></p></div></div>
+";
+    let document = parse_html().one(html);
+    assert_eq!(select_ids(&document, "div:has(span)"), ["a", "b"]);
+    assert_eq!(select_ids(&document, "div:has(p)"), ["a", "c", "d", "e"]);
+    let filtered = |selectors: &str| {
+        let selectors = Selectors::compile(selectors).unwrap();
+        selectors
+            .filter(document.descendants().elements().rev())
+            .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(filtered("div:has(span)"), ["b", "a"]);
+}
+
+#[test]
+fn has_deep_tree() {
+    let depth = 2000;
+    let html = format!("{}<span></span>", "<div>".repeat(depth));
+    let document = parse_html().one(html);
+    assert_eq!(document.select("div:has(span)").unwrap().count(), depth);
+    assert_eq!(document.select("div:has(> span)").unwrap().count(), 1);
+    assert_eq!(document.select("div:has(.missing)").unwrap().count(), 0);
+}
+
+#[test]
+fn has_specificity() {
+    let selectors =
+        Selectors::compile("div:has(p), div:has(> #a, .b), div:not(:has(p.c)), :has(:has(#a))")
+            .unwrap();
+    let specificities = selectors
+        .0
+        .iter()
+        .map(|s| s.specificity().components())
+        .collect::<Vec<_>>();
+    assert_eq!(specificities, [(0, 0, 2), (1, 0, 1), (0, 1, 2), (1, 0, 0)]);
+}
+
+#[test]
+fn has_serialization_and_errors() {
+    let selectors = Selectors::compile("article:has(> p, img)").unwrap();
+    assert_eq!(selectors.to_string(), "article:has(> p, img)");
+    assert!(Selectors::compile("article:has()").is_err());
+    assert!(Selectors::compile("article:has(>)").is_err());
+}
+
+const TEXT_HTML: &str = r"
+<table>
+<tr><th>Name</th><td>Widget</td>
+<tr><th>Price</th><td>12 EUR</td>
+<tr><th><b>Price</b> (excl. tax)</th><td>10 EUR</td>
+</table>";
+
+fn text_selector_opts() -> SelectorOpts {
+    SelectorOpts {
+        text_pseudo_classes: true,
+        ..SelectorOpts::default()
+    }
+}
+
+fn select_texts(selectors: &str) -> Vec<String> {
+    let document = parse_html().one(TEXT_HTML);
+    let selectors = Selectors::compile_with_options(selectors, &text_selector_opts()).unwrap();
+    document
+        .select_compiled(&selectors)
+        .map(|e| e.text_contents())
+        .collect()
+}
+
+#[test]
+fn contains_pseudo_classes() {
+    assert_eq!(
+        select_texts("th:contains(Price)"),
+        ["Price", "Price (excl. tax)"]
+    );
+    assert_eq!(select_texts("th:contains(\"price\")"), Vec::<String>::new());
+    assert_eq!(
+        select_texts("th:icontains(\"price\")"),
+        ["Price", "Price (excl. tax)"]
+    );
+}
+
+#[test]
+fn own_text_pseudo_class() {
+    assert_eq!(select_texts("th:own-text(\"Price\")"), ["Price"]);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn matches_text_pseudo_class() {
+    assert_eq!(
+        select_texts("td:matches-text(/^\\d+ EUR$/)"),
+        ["12 EUR", "10 EUR"]
+    );
+    assert_eq!(select_texts("th:matches-text(/^price$/i)"), ["Price"]);
+    assert_eq!(
+        select_texts("td:matches-text(\"^1[02]\")"),
+        ["12 EUR", "10 EUR"]
+    );
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn text_pseudo_classes_serialization() {
+    let selectors = Selectors::compile_with_options(
+        "th:icontains(Price):matches-text(/x/i)",
+        &text_selector_opts(),
+    )
+    .unwrap();
+    assert_eq!(
+        selectors.to_string(),
+        "th:icontains(\"price\"):matches-text(/x/i)"
+    );
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn text_pseudo_classes_invalid_regex() {
+    let opts = text_selector_opts();
+    assert!(Selectors::compile_with_options("td:matches-text(/x/q)", &opts).is_err());
+    assert!(Selectors::compile_with_options("td:matches-text(/(/)", &opts).is_err());
+}
+
+#[cfg(not(feature = "regex"))]
+#[test]
+fn matches_text_requires_regex_feature() {
+    let opts = text_selector_opts();
+    assert!(Selectors::compile_with_options("td:matches-text(/x/)", &opts).is_err());
+}
+
+#[test]
+fn text_pseudo_classes_require_opt_in() {
+    assert!(Selectors::compile("th:contains(Price)").is_err());
+}
+
+const CUSTOM_HTML: &str = r#"
+<div id=a><img width=1 height=1 src=pixel.gif></div>
+<div id=b data-state=open><img width=100 height=50 src=photo.jpg></div>
+<div id=c data-state=closed></div>
+"#;
+
+/// The registry from the `PseudoClassRegistry` documentation.
+fn custom_registry() -> PseudoClassRegistry {
+    let mut registry = PseudoClassRegistry::new();
+    registry.register("has-tracking-pixel", |element| {
+        element
+            .as_node()
+            .select("img[width='1'][height='1']")
+            .unwrap()
+            .next()
+            .is_some()
+    });
+    registry.register_with_argument("data-state", |element, state| {
+        element.attributes.borrow().get("data-state") == Some(state)
+    });
+    registry
+}
+
+fn custom_selector_opts() -> SelectorOpts {
+    SelectorOpts {
+        custom_pseudo_classes: custom_registry(),
+        ..SelectorOpts::default()
+    }
+}
+
+fn select_custom_ids(selectors: &str) -> Vec<String> {
+    let document = parse_html().one(CUSTOM_HTML);
+    let selectors = Selectors::compile_with_options(selectors, &custom_selector_opts()).unwrap();
+    document
+        .select_compiled(&selectors)
+        .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn pseudo_class_registry_contents() {
+    let registry = custom_registry();
+    assert!(registry.contains("Has-Tracking-Pixel"));
+    assert!(!registry.contains("data"));
+    assert_eq!(
+        format!("{:?}", registry),
+        r#"{"data-state", "has-tracking-pixel"}"#
+    );
+}
+
+#[test]
+fn custom_pseudo_class() {
+    assert_eq!(select_custom_ids("div:has-tracking-pixel"), ["a"]);
+    assert_eq!(
+        select_custom_ids("div:not(:HAS-TRACKING-PIXEL)"),
+        ["b", "c"]
+    );
+}
+
+#[test]
+fn custom_pseudo_class_with_argument() {
+    assert_eq!(select_custom_ids("div:data-state(open)"), ["b"]);
+    assert_eq!(select_custom_ids("div:data-state( \"closed\" )"), ["c"]);
+}
+
+#[test]
+fn custom_pseudo_classes_serialization() {
+    let selectors = Selectors::compile_with_options(
+        "div:has-tracking-pixel:data-state(\"a b\")",
+        &custom_selector_opts(),
+    )
+    .unwrap();
+    assert_eq!(
+        selectors.to_string(),
+        "div:has-tracking-pixel:data-state(\"a b\")"
+    );
+}
+
+#[test]
+fn custom_pseudo_classes_argument_mismatch() {
+    let opts = custom_selector_opts();
+    assert!(Selectors::compile_with_options("div:has-tracking-pixel(x)", &opts).is_err());
+    assert!(Selectors::compile_with_options("div:data-state", &opts).is_err());
+}
+
+#[test]
+fn custom_pseudo_classes_do_not_override_built_ins() {
+    let mut registry = PseudoClassRegistry::new();
+    registry.register("checked", |_| true);
+    let opts = SelectorOpts {
+        custom_pseudo_classes: registry,
+        ..SelectorOpts::default()
+    };
+    let document = parse_html().one("<input><input type=checkbox checked>");
+    let selectors = Selectors::compile_with_options("input:checked", &opts).unwrap();
+    assert_eq!(document.select_compiled(&selectors).count(), 1);
+}
+
+#[test]
+fn custom_pseudo_classes_require_registry() {
+    assert!(Selectors::compile("div:has-tracking-pixel").is_err());
+}
+
+const FORM_HTML: &str = r#"
+<form>
+<input id=text required placeholder=Name>
+<input id=filled placeholder=Name value=Joe readonly>
+<input id=check type=checkbox checked>
+<input id=hidden type=hidden required>
+<input id=radio1 type=radio name=r><input id=radio2 type=radio name=r>
+<fieldset id=fs disabled>
+<legend><input id=in-legend></legend>
+<input id=in-fieldset>
+</fieldset>
+<select id=select><option id=o1 disabled>1<option id=o2>2<option id=o3>3</select>
+<select id=multi multiple><option id=o4>4<option id=o5 selected>5</select>
+<textarea id=area placeholder=Comment></textarea>
+<button id=submit1>Go</button><button id=submit2 type=submit>Go</button>
+<progress id=progress></progress>
+</form>
+<div id=editable contenteditable><p id=editable-p>Text</p></div>
+"#;
+
+fn form_ids(selectors: &str) -> Vec<String> {
+    select_ids(&parse_html().one(FORM_HTML), selectors)
+}
+
+#[test]
+fn disabled_and_enabled_pseudo_classes() {
+    assert_eq!(form_ids(":disabled"), ["fs", "in-fieldset", "o1"]);
+    assert_eq!(
+        form_ids("input:enabled"),
+        form_ids("input:not(#in-fieldset)")
+    );
+}
+
+#[test]
+fn checked_and_default_pseudo_classes() {
+    assert_eq!(form_ids(":checked"), ["check", "o2", "o5"]);
+    assert_eq!(form_ids(":default"), ["check", "o5", "submit1"]);
+}
+
+#[test]
+fn indeterminate_pseudo_class() {
+    assert_eq!(form_ids(":indeterminate"), ["radio1", "radio2", "progress"]);
+}
+
+#[test]
+fn required_and_optional_pseudo_classes() {
+    assert_eq!(form_ids(":required"), ["text"]);
+    assert_eq!(form_ids("input:optional"), form_ids("input:not(#text)"));
+}
+
+#[test]
+fn placeholder_shown_pseudo_class() {
+    assert_eq!(form_ids(":placeholder-shown"), ["text", "area"]);
+}
+
+#[test]
+fn read_write_and_read_only_pseudo_classes() {
+    assert_eq!(
+        form_ids(":read-write"),
+        ["text", "in-legend", "area", "editable", "editable-p"]
+    );
+    assert_eq!(
+        form_ids("input:read-only"),
+        form_ids("input:not(#text):not(#in-legend)")
+    );
+}
+
+const LANGUAGE_HTML: &str = r#"
+<div id=fr lang=fr-CA><p id=fr-p>Bonjour</p><p id=en lang=EN>Hello</p></div>
+<div id=rtl dir=rtl><p id=rtl-p>…</p><p id=ltr dir=ltr>…</p></div>
+<p id=auto dir=auto>« שלום » hello</p>
+<svg><text id=svg-text xml:lang=de>Hallo</text></svg>
+<a name=anchor id=named></a>
+<my-element id=custom></my-element><button id=customized is=my-button></button>
+"#;
+
+fn language_ids_with(selectors: &str, opts: &SelectorOpts) -> Vec<String> {
+    let document = parse_html().one(LANGUAGE_HTML);
+    let selectors = Selectors::compile_with_options(selectors, opts).unwrap();
+    document
+        .select_compiled(&selectors)
+        .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+        .collect()
+}
+
+fn language_ids(selectors: &str) -> Vec<String> {
+    language_ids_with(selectors, &SelectorOpts::default())
+}
+
+#[test]
+fn lang_pseudo_class() {
+    assert_eq!(language_ids(":lang(fr)"), ["fr", "fr-p"]);
+    assert_eq!(language_ids(":lang(\"fr-ca\")"), ["fr", "fr-p"]);
+    assert_eq!(language_ids(":lang(en, de)"), ["en", "svg-text"]);
+    assert_eq!(language_ids(":lang(f)"), Vec::<String>::new());
+}
+
+#[test]
+fn dir_pseudo_class() {
+    assert_eq!(language_ids("p:dir(rtl)"), ["rtl-p", "auto"]);
+    assert_eq!(language_ids("p:dir(ltr)"), ["fr-p", "en", "ltr"]);
+}
+
+#[test]
+fn defined_pseudo_class() {
+    assert_eq!(language_ids("[id]:not(:defined)"), ["custom", "customized"]);
+}
+
+#[test]
+fn target_pseudo_class() {
+    assert_eq!(language_ids(":target"), Vec::<String>::new());
+    let opts = SelectorOpts {
+        target_fragment: Some("#en".to_owned()),
+        ..SelectorOpts::default()
+    };
+    assert_eq!(language_ids_with(":target", &opts), ["en"]);
+    let opts = SelectorOpts {
+        target_fragment: Some("anchor".to_owned()),
+        ..SelectorOpts::default()
+    };
+    assert_eq!(language_ids_with(":target", &opts), ["named"]);
+}
+
+#[test]
+fn lang_and_dir_serialization() {
+    let selectors = Selectors::compile(":lang(fr, en-US):dir(rtl)").unwrap();
+    assert_eq!(selectors.to_string(), ":lang(\"fr\", \"en-US\"):dir(rtl)");
+}
+
+#[test]
+fn dir_pseudo_class_invalid_direction() {
+    assert!(Selectors::compile(":dir(up)").is_err());
+}
+
+const SCOPED_HTML: &str = r"
+<ul id=outer>
+<li id=a><ul id=inner><li id=b></li></ul></li>
+<li id=c></li>
+</ul>
+<p id=d></p>";
+
+fn scoped_ids(node: &NodeRef, selectors: &str) -> Vec<String> {
+    node.select(selectors)
+        .unwrap()
+        .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn scoped_select_relative_selectors() {
+    let document = parse_html().one(SCOPED_HTML);
+    let outer = document.select_first("#outer").unwrap().as_node().clone();
+    let inner = document.select_first("#inner").unwrap().as_node().clone();
+    assert_eq!(scoped_ids(&outer, "li"), ["a", "b", "c"]);
+    assert_eq!(scoped_ids(&outer, "> li"), ["a", "c"]);
+    assert_eq!(scoped_ids(&outer, ":scope > li"), ["a", "c"]);
+    assert_eq!(scoped_ids(&inner, "> li"), ["b"]);
+    assert_eq!(
+        outer
+            .select_first("> li")
+            .unwrap()
+            .attributes
+            .borrow()
+            .get("id"),
+        Some("a")
+    );
+}
+
+#[test]
+fn scoped_select_excludes_scope_element() {
+    let document = parse_html().one(SCOPED_HTML);
+    let outer = document.select_first("#outer").unwrap().as_node().clone();
+    assert_eq!(scoped_ids(&outer, ":scope"), Vec::<String>::new());
+    assert_eq!(scoped_ids(&outer, "ul"), ["inner"]);
+    assert!(outer.select_first("#outer").is_err());
+    // Siblings of the scope element are not descendants.
+    assert_eq!(scoped_ids(&outer, "+ p, ~ p"), Vec::<String>::new());
+}
+
+#[test]
+fn scoped_select_on_document() {
+    let document = parse_html().one(SCOPED_HTML);
+    // On a document, `:scope` is the root element.
+    let root = document.select_first(":scope").unwrap();
+    assert_eq!(&*root.name.local, "html");
+    assert_eq!(scoped_ids(&document, ":scope > body > ul"), ["outer"]);
+}
+
+#[test]
+fn scoped_select_compiled() {
+    let document = parse_html().one(SCOPED_HTML);
+    let outer = document.select_first("#outer").unwrap().as_node().clone();
+    let selectors = Selectors::compile_scoped("> li, :scope ~ p").unwrap();
+    assert_eq!(selectors.to_string(), ":scope > li, :scope ~ p");
+    let matching = outer
+        .select_compiled(&selectors)
+        .map(|e| e.attributes.borrow().get("id").unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(matching, ["a", "c"]);
+    let li = document.select_first("#c").unwrap();
+    let ul = document.select_first("#outer").unwrap();
+    assert!(selectors.matches_with_scope(&li, Some(&ul)));
+    assert!(!selectors.matches_with_scope(&li, None));
+}
+
+#[test]
+fn relative_selector_without_compound_is_an_error() {
+    assert!(Selectors::compile_scoped(">").is_err());
+    assert!(Selectors::compile_scoped("> li, ~").is_err());
+}
+
+#[test]
+fn relative_selectors_require_a_scope() {
+    assert!(Selectors::compile("> li").is_err());
+    assert!(Selectors::compile("li, + p").is_err());
+    let document = parse_html().one(SCOPED_HTML);
+    let li = document.select_first("li").unwrap();
+    assert!(li.as_node().matches("> ul").is_err());
+    assert!(document.descendants().select("> li").is_err());
+}
+
+#[test]
+fn selector_cache_keeps_relative_selectors_scoped() {
+    let cache = SelectorCache::new(4);
+    assert!(cache.compile_scoped("> li").is_ok());
+    assert!(cache.compile("> li").is_err());
+    assert!(cache.compile("li").is_ok());
+    assert!(cache.compile_scoped("li").is_ok());
+    assert_eq!(cache.len(), 2);
+}
+
+const CLOSEST_HTML: &str = r"
+<section class=outer><div class=inner><p id=p>Text <b>bold</b></p></div></section>";
+
+#[test]
+fn matches() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let p = document.select_first("#p").unwrap();
+    assert_eq!(p.matches("div > p"), Ok(true));
+    assert_eq!(p.matches("section > p"), Ok(false));
+    assert_eq!(p.matches(":scope"), Ok(true));
+    assert_eq!(p.as_node().matches(&"p".to_owned()), Ok(true));
+    let selectors = Selectors::compile("section").unwrap();
+    assert_eq!(p.matches(&selectors), Ok(false));
+}
+
+#[test]
+fn matches_non_element() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let bold_text = document
+        .select_first("b")
+        .unwrap()
+        .as_node()
+        .first_child()
+        .unwrap();
+    assert_eq!(bold_text.matches("*"), Ok(false));
+}
+
+#[test]
+fn matches_invalid_selector() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let p = document.select_first("#p").unwrap();
+    assert!(p.matches("p[").is_err());
+    assert!(p.closest("p[").is_err());
+}
+
+#[test]
+fn closest() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let p = document.select_first("#p").unwrap();
+    let closest = p.closest("div, section").unwrap().unwrap();
+    assert_eq!(closest.attributes.borrow().get("class"), Some("inner"));
+    assert!(p.closest("article").unwrap().is_none());
+}
+
+#[test]
+fn closest_includes_the_element() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let p = document.select_first("#p").unwrap();
+    assert_eq!(p.closest("p").unwrap().unwrap(), p);
+}
+
+#[test]
+fn closest_from_non_element() {
+    let document = parse_html().one(CLOSEST_HTML);
+    let bold_text = document
+        .select_first("b")
+        .unwrap()
+        .as_node()
+        .first_child()
+        .unwrap();
+    let selectors = Selectors::compile("section").unwrap();
+    let closest = bold_text.closest(&selectors).unwrap().unwrap();
+    assert_eq!(closest.attributes.borrow().get("class"), Some("outer"));
+}
+
+const XPATH_HTML: &str = r#"
+<html lang=en><body>
+<ul id=list><li class=a>One</li><li class="a b">Two</li><li lang=fr>Trois</li></ul>
+<p data-n=3>Text <!-- note --> <b>bold</b></p>
+</body></html>"#;
+
+fn xpath_texts(expression: &str) -> Vec<String> {
+    parse_html()
+        .one(XPATH_HTML)
+        .xpath(expression)
+        .unwrap()
+        .into_node_set()
+        .unwrap()
+        .iter()
+        .map(XPathNode::string_value)
+        .collect()
+}
+
+fn xpath_value(expression: &str) -> Value {
+    parse_html().one(XPATH_HTML).xpath(expression).unwrap()
+}
+
+#[test]
+fn xpath_module_example() {
+    let document = parse_html().one("<ul><li>one</li><li class=x>two</li></ul>");
+    let value = document.xpath("string(//li[@class = 'x'])").unwrap();
+    assert_eq!(value.string(), "two");
+    assert_eq!(document.xpath("count(//li)").unwrap().number(), 2.);
+}
+
+#[test]
+fn xpath_paths_and_predicates() {
+    assert_eq!(xpath_texts("//li"), ["One", "Two", "Trois"]);
+    assert_eq!(xpath_texts("//li[2]"), ["Two"]);
+    assert_eq!(xpath_texts("//LI[contains(@class, 'b')]"), ["Two"]);
+    assert_eq!(xpath_texts("id('list')/li[position() > 1][1]"), ["Two"]);
+}
+
+#[test]
+fn xpath_axes() {
+    assert_eq!(
+        xpath_texts("//li[last()]/preceding-sibling::li[1]"),
+        ["Two"]
+    );
+    assert_eq!(xpath_texts("//b/ancestor::*[@id or @data-n]/@*"), ["3"]);
+    assert_eq!(xpath_texts("//p/comment()"), [" note "]);
+    assert_eq!(
+        xpath_texts("(//li)[last()]/following::*"),
+        ["Text  bold", "bold"]
+    );
+    assert_eq!(xpath_texts("//b/preceding::text()[1]"), [" "]);
+    assert_eq!(xpath_texts("//li[lang('fr')]/@lang"), ["fr"]);
+}
+
+#[test]
+fn xpath_document_order() {
+    assert_eq!(xpath_texts("//b | //li[1]"), ["One", "bold"]);
+    assert_eq!(
+        xpath_texts("//b | //li[3] | //li[1] | //li[3]"),
+        ["One", "Trois", "bold"]
+    );
+    assert_eq!(xpath_texts("//li/../li[1]"), ["One"]);
+    assert_eq!(xpath_texts("//li/ancestor::*/@*"), ["en", "list"]);
+}
+
+#[test]
+fn xpath_numbers_and_comparisons() {
+    assert_eq!(xpath_value("count(//li[@class])"), Value::Number(2.));
+    assert_eq!(xpath_value("//p/@data-n * 2 + 1"), Value::Number(7.));
+    assert_eq!(xpath_value("10 - 2 - 3"), Value::Number(5.));
+    assert_eq!(xpath_value("12 div 2 div 3"), Value::Number(2.));
+    assert_eq!(xpath_value("--'3'"), Value::Number(3.));
+    assert_eq!(xpath_value("//li = 'Two'"), Value::Boolean(true));
+    assert_eq!(xpath_value("//li != 'Two'"), Value::Boolean(true));
+    assert_eq!(xpath_value("1 < 2 < 3 = 1"), Value::Boolean(true));
+    assert_eq!(xpath_value("not(//table)"), Value::Boolean(true));
+    assert_eq!(xpath_value("1 div 0").string(), "Infinity");
+}
+
+#[test]
+fn xpath_round() {
+    let round = |n: &str| xpath_value(&format!("round({})", n)).number();
+    assert_eq!(round("2.5"), 3.);
+    assert_eq!(round("-2.5"), -2.);
+    assert_eq!(round("0.49999999999999994"), 0.);
+    assert_eq!(round("-0.5"), 0.);
+    assert!(round("-0.5").is_sign_negative());
+    assert!(round("-0.2").is_sign_negative());
+    assert!(round("0.2").is_sign_positive());
+    assert_eq!(round("4503599627370497"), 4503599627370497.);
+    assert!(round("0 div 0").is_nan());
+}
+
+#[test]
+fn xpath_string_functions() {
+    assert_eq!(
+        xpath_value("concat(name(//li[1]), ':', normalize-space(' a  b '))"),
+        Value::String("li:a b".to_owned())
+    );
+    assert_eq!(xpath_value("substring('12345', 1.5, 2.6)").string(), "234");
+    assert_eq!(xpath_value("translate('abc', 'abc', 'AB')").string(), "AB");
+}
+
+#[test]
+fn xpath_context_node() {
+    let document = parse_html().one(XPATH_HTML);
+    let li = document.select_first("li").unwrap();
+    assert_eq!(
+        li.as_node().xpath("string(../@id)").unwrap().string(),
+        "list"
+    );
+}
+
+#[test]
+fn xpath_variables() {
+    let document = parse_html().one(XPATH_HTML);
+    let expression = XPath::compile("//li[@class = $class]").unwrap();
+    let mut variables = std::collections::HashMap::new();
+    variables.insert("class".to_owned(), Value::String("a b".to_owned()));
+    let nodes = expression
+        .evaluate_with_variables(&document, &variables)
+        .unwrap()
+        .into_node_set()
+        .unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].as_node().unwrap().text_contents(), "Two");
+}
+
+#[test]
+fn xpath_long_operator_chains() {
+    let expression = vec!["1"; 100_000].join(" + ");
+    assert_eq!(xpath_value(&expression), Value::Number(100_000.));
+    let expression = format!("{}1", "-".repeat(100_001));
+    assert_eq!(xpath_value(&expression), Value::Number(-1.));
+}
+
+#[test]
+fn xpath_syntax_errors() {
+    assert!(matches!(
+        XPath::compile("//p[").unwrap_err(),
+        XPathError::Syntax { position: 4, .. }
+    ));
+    assert!(matches!(
+        XPath::compile("//p p"),
+        Err(XPathError::Syntax { .. })
+    ));
+}
+
+#[test]
+fn xpath_nesting_limit() {
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(XPath::compile(&nested(50)).is_ok());
+    // Evaluation recurses as deeply as parsing.
+    let document = parse_html().one("<p>");
+    let calls = format!("{}1{}", "not(".repeat(99), ")".repeat(99));
+    assert_eq!(document.xpath(&calls).unwrap(), Value::Boolean(false));
+    let predicates = format!("//*{}", "[*".repeat(99) + &"]".repeat(99));
+    assert!(document.xpath(&predicates).is_ok());
+    assert!(matches!(
+        XPath::compile(&nested(100_000)),
+        Err(XPathError::Syntax { .. })
+    ));
+    let predicates = format!("{}1{}", "//p[".repeat(100_000), "]".repeat(100_000));
+    assert!(matches!(
+        XPath::compile(&predicates),
+        Err(XPathError::Syntax { .. })
+    ));
+}
+
+#[test]
+fn xpath_compile_errors() {
+    assert_eq!(
+        XPath::compile("foo()").unwrap_err(),
+        XPathError::UnknownFunction("foo".to_owned())
+    );
+    assert_eq!(
+        XPath::compile("count()").unwrap_err(),
+        XPathError::ArgumentCount("count".to_owned())
+    );
+    assert_eq!(
+        XPath::compile("//x:p").unwrap_err(),
+        XPathError::UnknownPrefix("x".to_owned())
+    );
+}
+
+#[test]
+fn xpath_evaluation_errors() {
+    let document = parse_html().one("<p>");
+    assert_eq!(
+        document.xpath("$v").unwrap_err(),
+        XPathError::UnknownVariable("v".to_owned())
+    );
+    assert_eq!(
+        document.xpath("'a'/b").unwrap_err(),
+        XPathError::NotANodeSet
+    );
+    assert_eq!(document.xpath("//p div 2").unwrap().string(), "NaN");
+}
+
+#[test]
+fn specificity_components() {
+    let selectors = Selectors::compile("#main, p.a.b, body p, div > *, p:not(.x)").unwrap();
+    let specificities = selectors
+        .0
+        .iter()
+        .map(|s| s.specificity().components())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        specificities,
+        [(1, 0, 0), (0, 2, 1), (0, 0, 2), (0, 0, 1), (0, 1, 1)]
+    );
+    assert_eq!(Specificity::new(0, 2, 1), selectors.0[1].specificity());
+}
+
+#[test]
+fn specificity_components_saturate() {
+    assert_eq!(Specificity::new(2000, 0, 0).components(), (1023, 0, 0));
+    assert!(Specificity::new(1, 0, 0) > Specificity::new(0, 1023, 1023));
+}
+
+const MATCHING_SELECTOR_HTML: &str = "<div><p class='a b'>Text</p><p>Other</p></div>";
+
+#[test]
+fn matching_selector_is_most_specific() {
+    let selectors = Selectors::compile("#main, p.a.b, body p, div > *, p:not(.x)").unwrap();
+    let document = parse_html().one(MATCHING_SELECTOR_HTML);
+    let paragraphs = document.select("p").unwrap().collect::<Vec<_>>();
+    let selector = selectors.matching_selector(&paragraphs[0]).unwrap();
+    assert_eq!(selector.specificity().components(), (0, 2, 1));
+    // `p:not(.x)` is more specific than `body p` and `div > *`.
+    let selector = selectors.matching_selector(&paragraphs[1]).unwrap();
+    assert_eq!(selector.specificity().components(), (0, 1, 1));
+}
+
+#[test]
+fn matching_selector_prefers_last_among_equals() {
+    let document = parse_html().one(MATCHING_SELECTOR_HTML);
+    let p = document.select("p").unwrap().nth(1).unwrap();
+    let selectors = Selectors::compile("div p, body p, span").unwrap();
+    let selector = selectors.matching_selector(&p).unwrap();
+    assert!(std::ptr::eq(selector, &selectors.0[1]));
+}
+
+#[test]
+fn matching_selector_without_match() {
+    let document = parse_html().one(MATCHING_SELECTOR_HTML);
+    let div = document.select_first("div").unwrap();
+    assert!(Selectors::compile("p")
+        .unwrap()
+        .matching_selector(&div)
+        .is_none());
+}
+
+const STYLESHEET_CSS: &str = r#"
+@charset "utf-8";
+p, .note { color: red; margin: 0 auto !important; invalid }
+@media print { p { display: none } }
+@import url(x.css);
+a::before { content: "x" }
+div { --Custom: 1px; COLOR: Blue !IMPORTANT }
+"#;
+
+#[test]
+fn stylesheet_parsing() {
+    let stylesheet = Stylesheet::parse(STYLESHEET_CSS);
+    let rules = stylesheet
+        .rules
+        .iter()
+        .map(|rule| rule.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rules,
+        [
+            "@charset \"utf-8\";",
+            "p, .note { color: red; margin: 0 auto !important }",
+            "@media print { p { display: none } }",
+            "@import url(x.css);",
+            "a::before { content: \"x\" }",
+            "div { --Custom: 1px; color: Blue !important }",
+        ]
+    );
+}
+
+#[test]
+fn stylesheet_drops_relative_selectors() {
+    let stylesheet = Stylesheet::parse("> p { color: red } p { color: blue }");
+    // Rules with an invalid prelude are kept verbatim, and never apply.
+    assert!(matches!(&stylesheet.rules[0], Rule::Other(source) if source == "> p { color: red }"));
+    assert!(matches!(stylesheet.rules[1], Rule::Style(_)));
+    let document = parse_html().one("<style>> body { color: red }</style><p>Text</p>");
+    let cascade = Cascade::from_document(&document);
+    let body = document.select_first("body").unwrap();
+    assert_eq!(cascade.computed_style(&body).get("color"), None);
+}
+
+#[test]
+fn stylesheet_keeps_at_rules_as_is() {
+    let stylesheet = Stylesheet::parse(STYLESHEET_CSS);
+    assert!(matches!(stylesheet.rules[0], Rule::Other(_)));
+    assert!(matches!(stylesheet.rules[2], Rule::Other(_)));
+}
+
+#[test]
+fn declaration_parsing() {
+    let declarations = parse_declarations("color:red;; background: url(a;b.png) ; x: ");
+    assert_eq!(declarations.len(), 2);
+    assert_eq!(declarations[1].value, "url(a;b.png)");
+    assert_eq!(
+        serialize_declarations(&declarations),
+        "color: red; background: url(a;b.png)"
+    );
+}
+
+const CASCADE_HTML: &str = r#"
+<style>
+  body { color: black; display: block; font-size: 12px }
+  p { color: green !important; display: none }
+  #main p.x { color: blue; display: block }
+  p.x { display: inline }
+</style>
+<style type=text/less>p { display: table }</style>
+<body><div id=main><p class=x style="display: flex; font-size: inherit">A <b style="color: unset; display: initial">B</b></p></div></body>"#;
+
+#[test]
+fn cascade_ignores_non_css_style_elements() {
+    let document = parse_html().one(CASCADE_HTML);
+    let cascade = Cascade::from_document(&document);
+    assert_eq!(cascade.stylesheet.rules.len(), 4);
+}
+
+#[test]
+fn cascaded_style() {
+    let document = parse_html().one(CASCADE_HTML);
+    let cascade = Cascade::from_document(&document);
+    let p = document.select_first("p").unwrap();
+    let cascaded = cascade.cascaded_style(&p);
+    // `!important` beats specificity, and the style attribute beats normal rules.
+    assert_eq!(cascaded.get("color"), Some("green"));
+    assert_eq!(cascaded.get("display"), Some("flex"));
+    assert_eq!(cascaded.get("font-size"), Some("inherit"));
+}
+
+#[test]
+fn computed_style_inheritance() {
+    let document = parse_html().one(CASCADE_HTML);
+    let cascade = Cascade::from_document(&document);
+    let p = document.select_first("p").unwrap();
+    let computed = cascade.computed_style(&p);
+    assert_eq!(computed.get("font-size"), Some("12px"));
+    // `unset` inherits an inherited property, `initial` resets a property.
+    let b = document.select_first("b").unwrap();
+    let computed = cascade.computed_style(&b);
+    assert_eq!(computed.get("color"), Some("green"));
+    assert_eq!(computed.get("display"), None);
+    assert_eq!(computed.get("font-size"), Some("12px"));
+}
+
+#[test]
+fn computed_value() {
+    let document = parse_html().one(CASCADE_HTML);
+    let cascade = Cascade::from_document(&document);
+    let div = document.select_first("div").unwrap();
+    assert_eq!(cascade.computed_value(&div, "display"), None);
+    assert_eq!(
+        cascade.computed_value(&div, "color").as_deref(),
+        Some("black")
+    );
+}
+
+#[test]
+fn style_module_example() {
+    let document = parse_html()
+        .one("<style>p { display: none } .shown { display: block }</style><p class=shown>Hi</p>");
+    let cascade = Cascade::from_document(&document);
+    let p = document.select_first("p").unwrap();
+    assert_eq!(
+        cascade.computed_value(&p, "display").as_deref(),
+        Some("block")
+    );
+}
+
+const INLINE_HTML: &str = r#"<html><head><title>Mail</title>
+<style>
+  @charset "utf-8";
+  p { color: red; margin: 0 }
+  .big { font-size: 20px !important }
+  a:hover, a { text-decoration: none }
+  @media (max-width: 600px) { p { margin: 4px } }
+</style>
+<style>#intro { color: blue }</style>
+</head><body><p id=intro class=big style="font-size: 10px; padding: 1px">Hi <a href=x>there</a></p><p>Bye</p></body></html>"#;
+
+#[test]
+fn inline_styles() {
+    let document = parse_html().one(INLINE_HTML);
+    crate::style::inline_styles(&document, &Default::default());
+    let styles = document
+        .select("body *")
+        .unwrap()
+        .map(|e| e.attributes.borrow().get("style").unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        styles,
+        [
+            "color: blue; margin: 0; font-size: 20px !important; padding: 1px",
+            "text-decoration: none",
+            "color: red; margin: 0",
+        ]
+    );
+    assert_eq!(document.select("style").unwrap().count(), 2);
+}
+
+#[test]
+fn inline_styles_skip_head() {
+    let document = parse_html().one(INLINE_HTML);
+    crate::style::inline_styles(&document, &Default::default());
+    assert!(document
+        .select_first("title")
+        .unwrap()
+        .attributes
+        .borrow()
+        .get("style")
+        .is_none());
+}
+
+#[test]
+fn inline_styles_remove_inlined_rules() {
+    let document = parse_html().one(INLINE_HTML);
+    let opts = crate::style::InlineOpts {
+        remove_inlined_rules: true,
+    };
+    crate::style::inline_styles(&document, &opts);
+    let remaining = document
+        .select("style")
+        .unwrap()
+        .map(|style| style.text_contents())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        remaining,
+        ["@charset \"utf-8\";\na:hover { text-decoration: none }\n@media (max-width: 600px) { p { margin: 4px } }\n"]
+    );
+}
+
+const INLINE_STYLE_HTML: &str =
+    r#"<p style="COLOR: red !important; margin:0; color: blue; --x: A">Text</p>"#;
+
+#[test]
+fn inline_style_reading() {
+    let document = parse_html().one(INLINE_STYLE_HTML);
+    let p = document.select_first("p").unwrap();
+    let style = p.style();
+    assert_eq!(style.len(), 3);
+    // The important declaration wins over the later one.
+    assert_eq!(style.get("color"), Some("red"));
+    assert!(style.is_important("Color"));
+    // Custom property names are case-sensitive.
+    assert_eq!(style.get("--x"), Some("A"));
+    assert_eq!(style.get("--X"), None);
+}
+
+#[test]
+fn inline_style_set() {
+    let document = parse_html().one(INLINE_STYLE_HTML);
+    let p = document.select_first("p").unwrap();
+    let mut style = p.style();
+    style.set("margin", "1px 2px").unwrap();
+    style.set_with_priority("padding", "3px", true).unwrap();
+    style.set("background", "url(a;b.png)").unwrap();
+    style.remove("background");
+    assert_eq!(
+        p.attributes.borrow().get("style"),
+        Some("color: red !important; margin: 1px 2px; --x: A; padding: 3px !important")
+    );
+}
+
+#[test]
+fn inline_style_set_invalid_value() {
+    let document = parse_html().one(INLINE_STYLE_HTML);
+    let p = document.select_first("p").unwrap();
+    let mut style = p.style();
+    assert!(style.set("padding", "1px; color: green").is_err());
+    assert!(style.set("padding", "1px !important").is_err());
+    assert!(style.set("padding", "1px; junk").is_err());
+    assert!(style.set("padding", "").is_err());
+    assert_eq!(style.get("padding"), None);
+}
+
+#[test]
+fn inline_style_remove() {
+    let document = parse_html().one(INLINE_STYLE_HTML);
+    let p = document.select_first("p").unwrap();
+    let mut style = p.style();
+    assert_eq!(style.remove("margin").unwrap().value, "0");
+    assert!(style.remove("margin").is_none());
+    style.remove("color");
+    style.remove("--x");
+    assert!(style.is_empty());
+    assert!(!p.attributes.borrow().contains("style"));
+}
+
+const CLASS_LIST_HTML: &str = "<p class=' a\tb a '>Text</p><div></div>";
+
+#[test]
+fn class_list_tokens() {
+    let document = parse_html().one(CLASS_LIST_HTML);
+    let p = document.select_first("p").unwrap();
+    let classes = p.class_list();
+    assert_eq!(classes.tokens(), ["a", "b"]);
+    assert_eq!(classes.len(), 2);
+    assert!(classes.contains("b"));
+    assert!(!classes.contains("B"));
+}
+
+#[test]
+fn class_list_add_and_toggle() {
+    let document = parse_html().one(CLASS_LIST_HTML);
+    let p = document.select_first("p").unwrap();
+    let classes = p.class_list();
+    classes.add("c").unwrap();
+    assert_eq!(p.attributes.borrow().get("class"), Some("a b c"));
+    assert_eq!(classes.toggle("a"), Ok(false));
+    assert_eq!(classes.toggle("a"), Ok(true));
+    assert_eq!(p.attributes.borrow().get("class"), Some("b c a"));
+}
+
+#[test]
+fn class_list_replace() {
+    let document = parse_html().one(CLASS_LIST_HTML);
+    let p = document.select_first("p").unwrap();
+    let classes = p.class_list();
+    assert_eq!(classes.replace("b", "d"), Ok(true));
+    assert_eq!(classes.replace("x", "y"), Ok(false));
+    // Replacing with a token already present removes the old one.
+    assert_eq!(classes.replace("d", "a"), Ok(true));
+    assert_eq!(p.attributes.borrow().get("class"), Some("a"));
+}
+
+#[test]
+fn class_list_invalid_tokens() {
+    let document = parse_html().one(CLASS_LIST_HTML);
+    let p = document.select_first("p").unwrap();
+    let classes = p.class_list();
+    assert!(classes.add("two words").is_err());
+    assert!(classes.remove("").is_err());
+    assert!(classes.replace("a", "").is_err());
+    assert!(classes.toggle("a\n").is_err());
+    assert_eq!(p.attributes.borrow().get("class"), Some(" a\tb a "));
+}
+
+#[test]
+fn class_list_does_not_create_empty_attribute() {
+    let document = parse_html().one(CLASS_LIST_HTML);
+    let div = document.select_first("div").unwrap();
+    div.class_list().remove("x").unwrap();
+    assert!(!div.attributes.borrow().contains("class"));
+    assert!(div.class_list().is_empty());
+}
+
+const DATASET_HTML: &str = "<p data-foo-bar=1 data-x- data-=e id=p data-a-B=2>Text</p>";
+
+#[test]
+fn dataset_entries() {
+    let document = parse_html().one(DATASET_HTML);
+    let p = document.select_first("p").unwrap();
+    assert_eq!(
+        p.dataset().entries(),
+        [
+            ("fooBar".to_owned(), "1".to_owned()),
+            ("x-".to_owned(), "".to_owned()),
+            ("".to_owned(), "e".to_owned()),
+            ("aB".to_owned(), "2".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn dataset_get_set_remove() {
+    let document = parse_html().one(DATASET_HTML);
+    let p = document.select_first("p").unwrap();
+    let dataset = p.dataset();
+    assert_eq!(dataset.get("fooBar"), Ok(Some("1".to_owned())));
+    assert_eq!(dataset.set("someValue", "v"), Ok(None));
+    assert_eq!(p.attributes.borrow().get("data-some-value"), Some("v"));
+    assert_eq!(dataset.contains("someValue"), Ok(true));
+    assert_eq!(dataset.remove("fooBar"), Ok(Some("1".to_owned())));
+    assert_eq!(dataset.contains("fooBar"), Ok(false));
+}
+
+#[test]
+fn dataset_invalid_keys() {
+    let document = parse_html().one(DATASET_HTML);
+    let p = document.select_first("p").unwrap();
+    let dataset = p.dataset();
+    assert_eq!(dataset.get("foo-bar"), Err(()));
+    assert_eq!(dataset.set("a-b", "v"), Err(()));
+    assert_eq!(dataset.contains("a-b"), Err(()));
+    assert_eq!(dataset.remove("a-b"), Err(()));
+    assert_eq!(p.attributes.borrow().get("data-a-b"), Some("2"));
+}
+
+const NAMESPACED_HTML: &str = r##"<p lang=en><svg xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#a"/>Text</svg>"##;
+
+#[test]
+fn namespaced_attribute_lookup() {
+    let document = parse_html().one(NAMESPACED_HTML);
+    let svg_use = document.select_first("use").unwrap();
+    let attributes = svg_use.attributes.borrow();
+    assert_eq!(attributes.get("href"), None);
+    assert_eq!(attributes.get_ns(ns!(xlink), "href"), Some("#a"));
+    assert!(attributes.contains_ns(ns!(xlink), "href"));
+}
+
+#[test]
+fn namespaced_attribute_insert_and_remove() {
+    let document = parse_html().one(NAMESPACED_HTML);
+    let svg_use = document.select_first("use").unwrap();
+    let mut attributes = svg_use.attributes.borrow_mut();
+    attributes.insert_ns(
+        ns!(xml),
+        Some(namespace_prefix!("xml")),
+        "lang",
+        "fr".to_owned(),
+    );
+    attributes.insert("width", "10".to_owned());
+    let names = attributes
+        .iter_qualified()
+        .map(|(name, value)| {
+            let prefix = name
+                .prefix
+                .as_ref()
+                .map_or(String::new(), |p| format!("{}:", p));
+            format!("{}{}={}", prefix, name.local, value)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["xlink:href=#a", "xml:lang=fr", "width=10"]);
+    assert_eq!(
+        attributes.remove_ns(ns!(xml), "lang").unwrap().prefix,
+        Some(namespace_prefix!("xml"))
+    );
+    assert!(!attributes.contains_ns(ns!(xml), "lang"));
+    assert!(attributes.remove_ns(ns!(xml), "lang").is_none());
+}
+
+#[test]
+fn lookup_namespace_uri() {
+    let document = parse_html().one(NAMESPACED_HTML);
+    let svg_use = document.select_first("use").unwrap();
+    let text = svg_use.as_node().next_sibling().unwrap();
+    assert_eq!(text.lookup_namespace_uri(None), Some(ns!(svg)));
+    assert_eq!(text.lookup_namespace_uri(Some("xlink")), Some(ns!(xlink)));
+    assert_eq!(text.lookup_namespace_uri(Some("xml")), Some(ns!(xml)));
+    assert_eq!(text.lookup_namespace_uri(Some("foo")), None);
+    let p = document.select_first("p").unwrap();
+    assert_eq!(p.as_node().lookup_namespace_uri(None), Some(ns!(html)));
+    assert_eq!(p.as_node().lookup_namespace_uri(Some("xlink")), None);
+    assert_eq!(document.lookup_namespace_uri(None), Some(ns!(html)));
+}
+
+#[test]
+fn lookup_prefix() {
+    let document = parse_html().one(NAMESPACED_HTML);
+    let svg_use = document.select_first("use").unwrap();
+    let text = svg_use.as_node().next_sibling().unwrap();
+    assert_eq!(
+        text.lookup_prefix(&ns!(xlink)),
+        Some(namespace_prefix!("xlink"))
+    );
+    assert_eq!(text.lookup_prefix(&ns!(svg)), None);
+}
+
+const TYPED_ATTRIBUTES_HTML: &str = r#"<img width=" 50%" height="+12.5px" tabindex="-3x" colspan=-1 data-f="1.5e2abc"
+    rel="noopener  help noopener" hidden
+    srcset="a.png, b,c.png 2x, d.png 100w, e.png 1x 2x, f.png 10h, g.png 20w 10h,">"#;
+
+fn typed_attributes() -> NodeDataRef<ElementData> {
+    parse_html()
+        .one(TYPED_ATTRIBUTES_HTML)
+        .select_first("img")
+        .unwrap()
+}
+
+#[test]
+fn bool_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert!(attributes.get_bool("hidden"));
+    assert!(!attributes.get_bool("disabled"));
+}
+
+#[test]
+fn integer_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert_eq!(attributes.get_integer("tabindex"), Ok(-3));
+    assert!(attributes.get_integer("rel").is_err());
+    assert_eq!(
+        attributes.get_non_negative_integer("colspan"),
+        Err(AttributeError::Invalid {
+            value: "-1".to_owned(),
+            expected: "a non-negative integer"
+        })
+    );
+}
+
+#[test]
+fn missing_typed_attribute() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert_eq!(
+        attributes.get_integer("rowspan"),
+        Err(AttributeError::Missing)
+    );
+    assert_eq!(
+        attributes.get_datetime("datetime"),
+        Err(AttributeError::Missing)
+    );
+}
+
+#[test]
+fn float_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert_eq!(attributes.get_float("data-f"), Ok(150.));
+    assert!(attributes.get_float("rel").is_err());
+}
+
+#[test]
+fn dimension_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert_eq!(
+        attributes.get_dimension("width"),
+        Ok(Dimension::Percentage(50.))
+    );
+    assert_eq!(
+        attributes.get_dimension("height"),
+        Ok(Dimension::Length(12.5))
+    );
+    assert!(attributes.get_dimension("tabindex").is_err());
+}
+
+#[test]
+fn token_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    assert_eq!(attributes.get_tokens("rel").unwrap(), ["noopener", "help"]);
+}
+
+#[test]
+fn srcset_attributes() {
+    let img = typed_attributes();
+    let attributes = img.attributes.borrow();
+    let candidate = |url: &str, descriptor| ImageCandidate {
+        url: url.to_owned(),
+        descriptor,
+    };
+    // Candidates with invalid descriptors are dropped.
+    assert_eq!(
+        attributes.get_srcset("srcset").unwrap(),
+        [
+            candidate("a.png", None),
+            candidate("b,c.png", Some(ImageDescriptor::Density(2.))),
+            candidate("d.png", Some(ImageDescriptor::Width(100))),
+            candidate("g.png", Some(ImageDescriptor::Width(20))),
+        ]
+    );
+}
+
+fn datetime(value: &str) -> Result<DateOrTime, AttributeError> {
+    let document = parse_html().one("<ins></ins>");
+    let ins = document.select_first("ins").unwrap();
+    let mut attributes = ins.attributes.borrow_mut();
+    attributes.insert("datetime", value.to_owned());
+    attributes.get_datetime("datetime")
+}
+
+const LEAP_DAY: Date = Date {
+    year: 2024,
+    month: 2,
+    day: 29,
+};
+
+fn time(hour: u8, minute: u8, second: f64) -> Time {
+    Time {
+        hour,
+        minute,
+        second,
+    }
+}
+
+#[test]
+fn date_attributes() {
+    assert_eq!(datetime("2024-02-29"), Ok(DateOrTime::Date(LEAP_DAY)));
+}
+
+#[test]
+fn time_attributes() {
+    assert_eq!(datetime("13:05"), Ok(DateOrTime::Time(time(13, 5, 0.))));
+    assert_eq!(
+        datetime("23:59:59.25"),
+        Ok(DateOrTime::Time(time(23, 59, 59.25)))
+    );
+}
+
+#[test]
+fn global_datetime_attributes() {
+    assert_eq!(
+        datetime("2024-02-29T13:05Z"),
+        Ok(DateOrTime::Global {
+            date: LEAP_DAY,
+            time: time(13, 5, 0.),
+            offset: 0
+        })
+    );
+    assert_eq!(
+        datetime("2024-02-29 13:05:30-0330"),
+        Ok(DateOrTime::Global {
+            date: LEAP_DAY,
+            time: time(13, 5, 30.),
+            offset: -210
+        })
+    );
+}
+
+#[test]
+fn datetime_attributes_with_long_years() {
+    assert_eq!(
+        datetime("12345-01-01T00:00+01:00"),
+        Ok(DateOrTime::Global {
+            date: Date {
+                year: 12345,
+                month: 1,
+                day: 1
+            },
+            time: time(0, 0, 0.),
+            offset: 60
+        })
+    );
+}
+
+#[test]
+fn invalid_datetime_attributes() {
+    for value in &[
+        "",
+        "2023-02-29",
+        "2024-13-01",
+        "2024-1-01",
+        "0000-01-01",
+        "999-01-01",
+        " 2024-01-01",
+        "2024-01-01x",
+        "2024-01-01T13:05",
+        "2024-01-01T24:00Z",
+        "2024-01-01T13:05+24:00",
+        "2024-01-01T13:05+0160",
+        "13:60",
+        "13:05:60",
+        "13:05:5.5",
+        "13:05:05.5.5",
+        "12:30:.5",
+        "12:30:5.",
+        "12:30:5",
+        "12:30:05.",
+        "12:30:055",
+        "13:05Z",
+        "2024-W01",
+        "P1D",
+    ] {
+        assert_eq!(
+            datetime(value),
+            Err(AttributeError::Invalid {
+                value: (*value).to_owned(),
+                expected: "a date or time string"
+            }),
+            "{:?}",
+            value
+        );
+    }
+}
+
+const PRETTY_HTML: &str =
+    "<!DOCTYPE html><html><head><title>T</title></head><body> <div><p>Some <b>bold</b> \
+                text</p>\n<pre>  keep\n  this</pre><!-- c --><ul><li>a</li><li>b</li></ul></div>\
+                <script>if (a < b) {}</script></body></html>";
+
+#[test]
+fn serialize_pretty() {
+    let document = parse_html().one(PRETTY_HTML);
+    let expected = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>T</title>
+  </head>
+  <body>
+    <div>
+      <p>Some <b>bold</b> text</p>
+      <pre>  keep
+  this</pre>
+      <!-- c -->
+      <ul>
+        <li>a</li>
+        <li>b</li>
+      </ul>
+    </div>
+    <script>if (a < b) {}</script>
+  </body>
+</html>"#;
+    assert_eq!(document.to_pretty_string("  "), expected);
+}
+
+#[test]
+fn serialize_pretty_keeps_inline_content_on_one_line() {
+    let document = parse_html().one(PRETTY_HTML);
+    let p = document.select_first("p").unwrap().as_node().clone();
+    assert_eq!(p.to_pretty_string("\t"), "<p>Some <b>bold</b> text</p>");
+}
+
+#[test]
+fn serialize_pretty_subtree() {
+    let document = parse_html().one(PRETTY_HTML);
+    let ul = document.select_first("ul").unwrap().as_node().clone();
+    assert_eq!(
+        ul.to_pretty_string("\t"),
+        "<ul>\n\t<li>a</li>\n\t<li>b</li>\n</ul>"
+    );
+}
+
+#[test]
+fn serialize_pretty_deep_tree() {
+    let (root, deepest) = nested_elements(local_name!("span"), 100_000);
+    deepest.append(NodeRef::new_text("deep"));
+    let html = root.to_pretty_string("  ");
+    assert!(html.starts_with("<span><span>"));
+    assert!(html.contains("<span>deep</span>"));
+}
+
+#[test]
+fn serialize_pretty_deep_block_tree() {
+    let (root, _) = nested_elements(local_name!("div"), 1000);
+    let html = root.to_pretty_string(" ");
+    assert!(html.starts_with("<div>\n <div>\n  <div>"));
+    assert!(html.ends_with("</div>\n </div>\n</div>"));
+}
+
+const MINIFY_HTML: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>  A   title </title>
+    <!-- a comment -->
+    <script> if (a < b) {} </script>
+  </head>
+  <body>
+    <!--[if IE]><p>IE</p><![endif]-->
+    <p class="a b" id=x title='say "hi"'>Some   <b>bold</b> &amp; <i>italic</i>
+      text</p>
+    <ul>
+      <li>One</li>
+      <li>Two</li>
+    </ul>
+    <input type="checkbox" checked="checked" value="">
+    <pre>
+
+  kept  </pre>
+    <table>
+      <tr><td>1</td><td>2</td></tr>
+    </table>
+  </body>
+</html>
+"#;
+
+const MINIFIED_HTML: &str =
+    "<!DOCTYPE html><title> A title </title><script> if (a < b) {} </script>\
+     <body><!--[if IE]><p>IE</p><![endif]-->\
+     <p class=\"a b\" id=x title='say \"hi\"'>Some <b>bold</b> &amp; <i>italic</i> text\
+     <ul><li>One<li>Two</ul><input type=checkbox checked value>\
+     <pre>\n\n  kept  </pre><table><tr><td>1<td>2</table>";
+
+/// The elements, attributes and non-whitespace text of a tree, with whitespace collapsed,
+/// to compare trees regardless of the changes made by minification.
+fn tree_structure(node: &NodeRef) -> String {
+    let mut structure = String::new();
+    for edge in node.traverse() {
+        match edge {
+            NodeEdge::Start(node) => {
+                if let Some(element) = node.as_element() {
+                    structure.push_str(&format!("<{}", element.name.local));
+                    for (name, attribute) in &element.attributes.borrow().map {
+                        // Boolean attributes lose a value equal to their name.
+                        let value = if attribute.value.eq_ignore_ascii_case(&name.local) {
+                            ""
+                        } else {
+                            &attribute.value
+                        };
+                        structure.push_str(&format!(" {}={:?}", name.local, value));
+                    }
+                    structure.push('>');
+                } else if let Some(text) = node.as_text() {
+                    let text = text.borrow();
+                    let words = text.split_ascii_whitespace().collect::<Vec<_>>();
+                    if !words.is_empty() {
+                        structure.push_str(&words.join(" "));
+                    }
+                }
+            }
+            NodeEdge::End(node) => {
+                if let Some(element) = node.as_element() {
+                    structure.push_str(&format!("</{}>", element.name.local));
+                }
+            }
+        }
+    }
+    structure
+}
+
+fn assert_minified_reparses_to_same_tree(html: &str) {
+    let document = parse_html().one(html);
+    let reparsed = parse_html().one(document.to_minified_string());
+    assert_eq!(
+        tree_structure(&reparsed),
+        tree_structure(&document),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn serialize_minified() {
+    let document = parse_html().one(MINIFY_HTML);
+    assert_eq!(document.to_minified_string(), MINIFIED_HTML);
+}
+
+#[test]
+fn minified_output_is_stable() {
+    let reparsed = parse_html().one(MINIFIED_HTML);
+    assert_eq!(reparsed.to_minified_string(), MINIFIED_HTML);
+    assert_eq!(
+        reparsed.select_first("pre").unwrap().text_contents(),
+        "\n  kept  "
+    );
+}
+
+#[test]
+fn minified_reparses_to_same_tree() {
+    assert_minified_reparses_to_same_tree(MINIFY_HTML);
+}
+
+#[test]
+fn minified_omitted_tags_reparse_to_same_tree() {
+    for html in &[
+        "<p>One<div>Two</div><p>Three<ul><li>a<li>b</ul><p>Four",
+        "<a href=x><p>In a link</p></a><video><p>In a video</p></video><p>Last",
+        "<dl><dt>Term<dd>Definition<dt>Other<dd>More</dl>",
+        "<select><optgroup label=g><option>1<option>2</optgroup><option>3</select>",
+        "<table><caption>C</caption><colgroup><col><col></colgroup><colgroup><col></colgroup>\
+         <thead><tr><th>H</thead><tbody><tr><td>1<td>2</tbody><tbody><tr><td>3</tbody>\
+         <tfoot><tr><td>F</tfoot></table>",
+        "<ruby>漢<rp>(</rp><rt>kan</rt><rp>)</rp></ruby>",
+        "<html lang=en><head><meta charset=utf-8></head><body class=b><p>Text</p></body></html>",
+        "<body><!-- first --><p>Text",
+        "<head></head><body><script>1</script><p>Text",
+        "<table><tr><td>1</table>",
+    ] {
+        assert_minified_reparses_to_same_tree(html);
+    }
+}
+
+#[test]
+fn minified_keeps_body_start_tag_before_head_elements() {
+    for first in &[
+        "<base href=x>",
+        "<basefont>",
+        "<bgsound>",
+        "<link rel=x>",
+        "<meta name=x>",
+        "<noframes>x</noframes>",
+        "<script>1</script>",
+        "<style>p {}</style>",
+        "<template>x</template>",
+        "<title>T</title>",
+    ] {
+        let html = format!("<head></head><body>{}<p>Text", first);
+        assert_minified_reparses_to_same_tree(&html);
+        let document = parse_html().one(html);
+        assert!(
+            document.to_minified_string().contains("<body>"),
+            "{}",
+            first
+        );
+    }
+}
+
+#[test]
+fn minified_keeps_paragraph_end_tag_before_table_in_quirks_mode() {
+    // Without a doctype, the document is in quirks mode.
+    let quirks = "<p>One</p><table><tr><td>1</table>";
+    assert_minified_reparses_to_same_tree(quirks);
+    let document = parse_html().one(quirks);
+    assert_eq!(
+        document.as_document().unwrap().quirks_mode(),
+        QuirksMode::Quirks
+    );
+    assert!(document.to_minified_string().contains("</p>"));
+
+    let no_quirks = format!("<!DOCTYPE html>{}", quirks);
+    assert_minified_reparses_to_same_tree(&no_quirks);
+    let document = parse_html().one(no_quirks);
+    assert!(!document.to_minified_string().contains("</p>"));
+}
+
+#[test]
+fn minified_deep_tree() {
+    let (root, deepest) = nested_elements(local_name!("div"), 100_000);
+    deepest.append(NodeRef::new_text("deep"));
+    let html = root.to_minified_string();
+    assert!(html.starts_with("<div><div>"));
+    assert_eq!(html.len(), 100_001 * "<div></div>".len() + "deep".len());
+}
+
+#[test]
+fn minified_comments() {
+    let html = "<p>a<!-- dropped -->b</p><!--[if IE]><p>IE</p><![endif]-->\
+                <!--[if !IE]><!--><p>Not IE</p><!--<![endif]-->\
+                <![if !IE]><p>Also not IE</p><![endif]><!--[IF lt IE 9]>old<![ENDIF]-->";
+    let document = parse_html().one(html);
+    assert_eq!(
+        document.to_minified_string(),
+        "<p>ab</p><!--[if IE]><p>IE</p><![endif]-->\
+         <!--[if !IE]><!--><p>Not IE</p><!--<![endif]-->\
+         <!--[if !IE]--><p>Also not IE</p><!--[endif]--><!--[IF lt IE 9]>old<![ENDIF]-->"
+    );
+}
+
+#[test]
+fn minified_raw_text() {
+    let document = parse_html().one("<noscript><p>a < b</p></noscript><style>a > b {}</style>");
+    assert_eq!(
+        document.to_minified_string(),
+        "<noscript><p>a < b</p></noscript><style>a > b {}</style>"
+    );
+}
+
+const OPTIONS_HTML: &str = r#"<div title="it's &quot;é&quot;" class=a id=b>Café <script>let s = "é" < 1;</script><noscript><b></noscript><br></div>"#;
+
+fn serialize_div(options: &SerializeOptions) -> String {
+    let document = parse_html().one(OPTIONS_HTML);
+    let div = document.select_first("div").unwrap();
+    let mut u8_vec = Vec::new();
+    div.as_node()
+        .serialize_with_options(&mut u8_vec, options)
+        .unwrap();
+    String::from_utf8(u8_vec).unwrap()
+}
+
+#[test]
+fn serialize_options_example() {
+    let options = SerializeOptions::new()
+        .ascii_only(true)
+        .attribute_order(AttributeOrder::Sorted);
+    assert_eq!(
+        serialize_div(&options),
+        "<div class=\"a\" id=\"b\" title=\"it's &quot;&#xE9;&quot;\">Caf&#xE9; \
+         <script>let s = \"é\" < 1;</script><noscript><b></noscript><br></div>"
+    );
+}
+
+#[test]
+fn serialize_with_default_options() {
+    let document = parse_html().one(OPTIONS_HTML);
+    let div = document.select_first("div").unwrap();
+    assert_eq!(
+        serialize_div(&SerializeOptions::new()),
+        div.as_node().to_string()
+    );
+}
+
+#[test]
+fn serialize_ascii_only() {
+    assert_eq!(
+        serialize_div(&SerializeOptions::new().ascii_only(true)),
+        "<div title=\"it's &quot;&#xE9;&quot;\" class=\"a\" id=\"b\">Caf&#xE9; \
+         <script>let s = \"é\" < 1;</script><noscript><b></noscript><br></div>"
+    );
+}
+
+#[test]
+fn serialize_escapes_runs() {
+    let text = NodeRef::new_text("a\u{A0}b & <c> é€𝄞 \"d\"");
+    let mut u8_vec = Vec::new();
+    text.serialize_with_options(&mut u8_vec, &SerializeOptions::new().ascii_only(true))
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(u8_vec).unwrap(),
+        "a&nbsp;b &amp; &lt;c&gt; &#xE9;&#x20AC;&#x1D11E; \"d\""
+    );
+}
+
+#[test]
+fn serialize_sorted_attributes() {
+    assert!(
+        serialize_div(&SerializeOptions::new().attribute_order(AttributeOrder::Sorted))
+            .starts_with("<div class=\"a\" id=\"b\" title=\"it's &quot;é&quot;\">")
+    );
+}
+
+#[test]
+fn serialize_single_quotes() {
+    assert!(
+        serialize_div(&SerializeOptions::new().quote_style(QuoteStyle::Single))
+            .starts_with("<div title='it&#39;s \"é\"' class='a' id='b'>")
+    );
+}
+
+#[test]
+fn serialize_children_only() {
+    assert_eq!(
+        serialize_div(&SerializeOptions::new().children_only(true)),
+        "Café <script>let s = \"é\" < 1;</script><noscript><b></noscript><br>"
+    );
+}
+
+fn serialize_first(html: &str, selector: &str, options: &SerializeOptions) -> String {
+    let document = parse_html().one(html);
+    let element = document.select_first(selector).unwrap();
+    let mut u8_vec = Vec::new();
+    element
+        .as_node()
+        .serialize_with_options(&mut u8_vec, options)
+        .unwrap();
+    String::from_utf8(u8_vec).unwrap()
+}
+
+#[test]
+fn serialize_create_missing_parent() {
+    let html = "<table class=t><tr><td>a</td></tr></table><ul><li>b</li></ul><p>c</p>";
+    let options = SerializeOptions::new().create_missing_parent(true);
+    assert_eq!(
+        serialize_first(html, "td", &options),
+        "<table><tbody><tr><td>a</td></tr></tbody></table>"
+    );
+    assert_eq!(serialize_first(html, "li", &options), "<ul><li>b</li></ul>");
+    assert_eq!(serialize_first(html, "p", &options), "<p>c</p>");
+    assert_eq!(
+        serialize_first(html, "td", &SerializeOptions::new()),
+        "<td>a</td>"
+    );
+    assert_eq!(
+        serialize_first(html, "tr", &options.children_only(true)),
+        "<td>a</td>"
+    );
+}
+
+#[test]
+fn serialize_without_scripting() {
+    assert!(
+        serialize_div(&SerializeOptions::new().scripting_enabled(false))
+            .ends_with("<noscript>&lt;b&gt;</noscript><br></div>")
+    );
+}
+
+#[test]
+fn serialize_raw_text_children() {
+    let document = parse_html().one(OPTIONS_HTML);
+    let script = document.select_first("script").unwrap();
+    let mut u8_vec = Vec::new();
+    script
+        .as_node()
+        .serialize_with_options(&mut u8_vec, &SerializeOptions::new().children_only(true))
+        .unwrap();
+    assert_eq!(String::from_utf8(u8_vec).unwrap(), "let s = \"é\" < 1;");
+}
+
+const CANONICAL: &str = "<html><head></head><body><div class=\"a\" id=\"x\">\
+                         <p>Some text <b>bold</b></p><pre> keep  this </pre></div></body></html>";
+
+#[test]
+fn canonical_serialization() {
+    let document = parse_html().one(
+        "<div id=x class='a'>\n  <p>Some   text\n   <b>bold</b> </p>\n  <pre> keep  this </pre></div>",
+    );
+    assert_eq!(document.to_canonical_string(), CANONICAL);
+}
+
+#[test]
+fn canonical_serialization_ignores_formatting() {
+    let document = parse_html()
+        .one("<div class=a id=\"x\"><p> Some text <b>bold</b></p><pre> keep  this </pre>\n</div>");
+    assert_eq!(document.to_canonical_string(), CANONICAL);
+}
+
+#[test]
+fn canonical_hash_of_equivalent_trees() {
+    let a = parse_html().one("<div id=x class='a'>\n  <p>Some   text</p></div>");
+    let b = parse_html().one("<div class=a id=\"x\"><p> Some text </p>\n</div>");
+    assert_eq!(a.canonical_hash(), b.canonical_hash());
+}
+
+#[test]
+fn canonical_hash_of_different_trees() {
+    let a = parse_html().one("<div><p>Some text <b>bold</b></p></div>");
+    let b = parse_html().one("<div><p>Some text<b>bold</b></p></div>");
+    assert_ne!(a.canonical_hash(), b.canonical_hash());
+}
+
+#[test]
+fn canonical_hash_of_empty_output() {
+    assert_eq!(
+        NodeRef::new_text("").canonical_hash(),
+        0xcbf2_9ce4_8422_2325
+    );
+}
+
+#[test]
+fn canonical_namespace_prefixes() {
+    let element = NodeRef::new_element(QualName::new(None, "urn:x".into(), "root".into()), None);
+    {
+        let mut attributes = element.as_element().unwrap().attributes.borrow_mut();
+        attributes.insert_ns("urn:y", Some("y".into()), "b", "2".to_owned());
+        attributes.insert_ns("urn:x", Some("x".into()), "a", "1".to_owned());
+    }
+    assert_eq!(
+        element.to_canonical_string(),
+        "<ns1:root ns1:a=\"1\" ns2:b=\"2\"></ns1:root>"
+    );
+}
+
+const FILTER_HTML: &str = r#"<div><!-- note --><script src="https://tracker.example/t.js"></script><font color=red>Hi <a href="/x?token=secret">there</a></font></div>"#;
+
+fn filter_div<F>(filter: F) -> String
+where
+    F: FnMut(&NodeRef, &mut Cow<crate::Attributes>) -> FilterAction,
+{
+    let document = parse_html().one(FILTER_HTML);
+    let div = document.select_first("div").unwrap();
+    let mut u8_vec = Vec::new();
+    div.as_node()
+        .serialize_filtered(&mut u8_vec, filter)
+        .unwrap();
+    String::from_utf8(u8_vec).unwrap()
+}
+
+#[test]
+fn serialize_filtered_keep() {
+    let document = parse_html().one(FILTER_HTML);
+    let div = document.select_first("div").unwrap();
+    assert_eq!(
+        filter_div(|_, _| FilterAction::Keep),
+        div.as_node().to_string()
+    );
+}
+
+#[test]
+fn serialize_filtered_skip() {
+    let filtered = filter_div(|node, _| {
+        if node.as_comment().is_some() || node.matches("script[src*=tracker]") == Ok(true) {
+            FilterAction::Skip
+        } else {
+            FilterAction::Keep
+        }
+    });
+    assert_eq!(
+        filtered,
+        r#"<div><font color="red">Hi <a href="/x?token=secret">there</a></font></div>"#
+    );
+}
+
+#[test]
+fn serialize_filtered_unwrap() {
+    let filtered = filter_div(|node, _| {
+        if node.matches("font, script") == Ok(true) || node.as_comment().is_some() {
+            FilterAction::Unwrap
+        } else {
+            FilterAction::Keep
+        }
+    });
+    assert_eq!(
+        filtered,
+        r#"<div><!-- note -->Hi <a href="/x?token=secret">there</a></div>"#
+    );
+}
+
+#[test]
+fn serialize_filtered_rewrites_attributes() {
+    let document = parse_html().one(FILTER_HTML);
+    let div = document.select_first("div").unwrap();
+    let before = div.as_node().to_string();
+    let mut u8_vec = Vec::new();
+    div.as_node()
+        .serialize_filtered(&mut u8_vec, |_, attributes| {
+            if let Some(href) = attributes
+                .get("href")
+                .filter(|href| href.contains("secret"))
+            {
+                let href = href.replace("secret", "REDACTED");
+                attributes.to_mut().insert("href", href);
+            }
+            FilterAction::Keep
+        })
+        .unwrap();
+    assert!(String::from_utf8(u8_vec)
+        .unwrap()
+        .contains(r#"<a href="/x?token=REDACTED">"#));
+    assert_eq!(div.as_node().to_string(), before);
+}
+
+#[test]
+fn serialize_filtered_borrows_unchanged_attributes() {
+    let mut copies = 0;
+    filter_div(|node, attributes| {
+        if node.as_element().is_some() && matches!(attributes, Cow::Owned(_)) {
+            copies += 1
+        }
+        FilterAction::Keep
+    });
+    assert_eq!(copies, 0);
+}
+
+#[test]
+fn serialize_filtered_ignores_attributes_of_other_nodes() {
+    let filtered = filter_div(|node, attributes| {
+        if node.as_text().is_some() {
+            attributes.to_mut().insert("id", "ignored".to_owned());
+        }
+        FilterAction::Keep
+    });
+    assert!(!filtered.contains("ignored"));
+}
+
+#[test]
+fn serialize_filtered_deep_tree() {
+    let (root, deepest) = nested_elements(local_name!("div"), 100_000);
+    deepest.append(NodeRef::new_element(
+        QualName::new(None, ns!(html), local_name!("script")),
+        None,
+    ));
+    deepest.append(NodeRef::new_text("deep"));
+    let mut depth = 0;
+    let mut u8_vec = Vec::new();
+    root.serialize_filtered(&mut u8_vec, |node, _| match node.as_element() {
+        Some(element) if element.name.local == local_name!("script") => FilterAction::Skip,
+        Some(_) => {
+            depth += 1;
+            if depth % 2 == 0 {
+                FilterAction::Unwrap
+            } else {
+                FilterAction::Keep
+            }
+        }
+        None => FilterAction::Keep,
+    })
+    .unwrap();
+    let html = String::from_utf8(u8_vec).unwrap();
+    assert!(html.starts_with("<div><div>"));
+    assert_eq!(html.len(), 50_001 * "<div></div>".len() + "deep".len());
+}
+
+fn plain_text(html: &str) -> String {
+    parse_html().one(html).to_plain_text()
+}
+
+#[test]
+fn plain_text_headings_and_paragraphs() {
+    assert_eq!(
+        plain_text("<h2>A  heading</h2><p>Some <b>bold</b>\n text.<br>New line.</p><p>Next</p>"),
+        "## A heading\n\nSome bold text.\nNew line.\n\nNext\n"
+    );
+}
+
+#[test]
+fn plain_text_link_footnotes() {
+    assert_eq!(
+        plain_text(
+            r##"<p><a href="https://example.com/">link</a> and <a href="#top">local</a></p>"##
+        ),
+        "link[1] and local\n\n[1] https://example.com/\n"
+    );
+}
+
+#[test]
+fn plain_text_skips_elements_that_are_not_rendered() {
+    assert_eq!(
+        plain_text(
+            "<html><head><title>Title</title><style>p {}</style></head><body>\
+             <p>Shown</p><script>ignored()</script><template>t</template></body></html>"
+        ),
+        "Shown\n"
+    );
+}
+
+#[test]
+fn plain_text_skips_hidden_elements() {
+    let html = "<p>Shown</p><p hidden>Hidden</p><p style='display: none'>None</p>\
+                <ul><li>A<li hidden>B</ul>";
+    assert_eq!(plain_text(html), "Shown\n\n* A\n");
+    assert!(!parse_html().one(html).inner_text().contains("Hidden"));
+}
+
+#[test]
+fn plain_text_lists() {
+    assert_eq!(
+        plain_text("<ul><li>One</li><li>Two<ol start=3><li>Three</li><li>Four</li></ol></li></ul>"),
+        "* One\n* Two\n\n  3. Three\n  4. Four\n"
+    );
+}
+
+#[test]
+fn plain_text_list_numbers_saturate() {
+    assert_eq!(
+        plain_text("<ol start=9223372036854775807><li>A</li><li>B</li></ol>"),
+        "9223372036854775807. A\n9223372036854775807. B\n"
+    );
+}
+
+#[test]
+fn plain_text_preformatted() {
+    assert_eq!(
+        plain_text("<p>Before</p><pre>  keep\n    this</pre>"),
+        "Before\n\n  keep\n    this\n"
+    );
+}
+
+#[test]
+fn plain_text_preformatted_skips_elements_that_are_not_rendered() {
+    assert_eq!(
+        plain_text(
+            "<pre>a<script>ignored()</script> b<span hidden>c</span><style>p {}</style></pre>"
+        ),
+        "a b\n"
+    );
+}
+
+#[test]
+fn plain_text_tables() {
+    let html = "<table>\
+                <tr><th>Name</th><th>Value</th></tr>\
+                <tr><td>a</td><td>1</td></tr>\
+                <tr><td>longer</td><td>22</td></tr>\
+                </table>";
+    assert_eq!(
+        plain_text(html),
+        "Name   | Value\n-------+------\na      | 1\nlonger | 22\n"
+    );
+}
+
+#[test]
+fn plain_text_wrapping() {
+    let document = parse_html().one(
+        "<blockquote>Quoted text that is long enough to wrap.</blockquote>\
+         <p>Some <b>bold</b> text with a <a href=/x>link</a>.<br>New line.</p>",
+    );
+    let opts = PlainTextOpts {
+        wrap_width: Some(20),
+        link_footnotes: false,
+    };
+    let blockquote = document.select_first("blockquote").unwrap();
+    assert_eq!(
+        blockquote.as_node().to_plain_text_with_options(&opts),
+        "> Quoted text that\n> is long enough to\n> wrap.\n"
+    );
+    let p = document.select_first("p").unwrap();
+    assert_eq!(
+        p.as_node().to_plain_text_with_options(&opts),
+        "Some bold text with\na link.\nNew line.\n"
+    );
+}
+
+#[test]
+fn plain_text_without_wrapping() {
+    let text = "word ".repeat(30);
+    let opts = PlainTextOpts {
+        wrap_width: None,
+        link_footnotes: true,
+    };
+    let document = parse_html().one(format!("<p>{}</p>", text).as_str());
+    assert_eq!(
+        document.to_plain_text_with_options(&opts),
+        format!("{}\n", text.trim_end())
+    );
+}
+
+#[test]
+fn plain_text_of_text_node() {
+    let text = NodeRef::new_text("hello  world");
+    assert_eq!(text.to_plain_text(), "hello world\n");
+    let opts = PlainTextOpts {
+        wrap_width: Some(5),
+        link_footnotes: true,
+    };
+    assert_eq!(text.to_plain_text_with_options(&opts), "hello\nworld\n");
+}
+
+#[test]
+fn plain_text_deep_tree() {
+    for name in &[local_name!("div"), local_name!("span")] {
+        let (root, deepest) = nested_elements(name.clone(), 100_000);
+        deepest.append(NodeRef::new_text("deep"));
+        assert_eq!(root.to_plain_text(), "deep\n");
+    }
+}
+
+fn inner_text(html: &str) -> String {
+    parse_html().one(html).inner_text()
+}
+
+#[test]
+fn inner_text_collapses_whitespace() {
+    assert_eq!(
+        inner_text("<div>  Some   <b>bold</b>\n   text </div>"),
+        "Some bold text"
+    );
+}
+
+#[test]
+fn inner_text_skips_elements_that_are_not_rendered() {
+    assert_eq!(
+        inner_text(
+            "<html><head><title>Title</title></head><body>\
+             <p>Shown</p><script>ignored()</script><template>ignored</template></body></html>"
+        ),
+        "Shown"
+    );
+}
+
+#[test]
+fn inner_text_skips_hidden_elements() {
+    assert_eq!(
+        inner_text(
+            r#"<div>a <span hidden>hidden</span><span style="color: red; display: NONE">none</span>b</div>"#
+        ),
+        "a b"
+    );
+}
+
+#[test]
+fn inner_text_paragraphs_and_line_breaks() {
+    assert_eq!(
+        inner_text("<div>Text</div><p>First<br> paragraph </p><p>Second</p>"),
+        "Text\n\nFirst\nparagraph\n\nSecond"
+    );
+}
+
+#[test]
+fn inner_text_preformatted() {
+    let document = parse_html().one("<p>Before</p><pre>  keep\n  <b>this</b></pre>");
+    assert_eq!(document.inner_text(), "Before\n\n  keep\n  this");
+    let b = document.select_first("b").unwrap();
+    assert_eq!(b.as_node().inner_text(), "this");
+    let pre = document.select_first("pre").unwrap();
+    assert_eq!(pre.as_node().inner_text(), "  keep\n  this");
+}
+
+#[test]
+fn inner_text_tables() {
+    assert_eq!(
+        inner_text(
+            "<table><thead><tr><th>a</th><th>b</th></tr></thead>\
+             <tbody><tr><td>1</td><td>2</td></tr></tbody></table>"
+        ),
+        "a\tb\n1\t2"
+    );
+}
+
+#[test]
+fn inner_text_of_text_node() {
+    let text = NodeRef::new_text("  a  b ");
+    assert_eq!(text.inner_text(), "a b");
+}
+
+#[test]
+fn inner_text_deep_tree() {
+    let (root, deepest) = nested_elements(local_name!("div"), 100_000);
+    deepest.append(NodeRef::new_text(" deep "));
+    let (pre, deepest) = nested_elements(local_name!("pre"), 100_000);
+    deepest.append(NodeRef::new_text(" kept "));
+    root.append(pre);
+    assert_eq!(root.inner_text(), "deep\n kept ");
+}
+
+fn markdown(html: &str) -> String {
+    crate::markdown::to_markdown(&parse_html().one(html))
+}
+
+#[test]
+fn markdown_module_example() {
+    let document = parse_html().one("<h1>Title</h1><p>Some <em>text</em>.</p>");
+    assert_eq!(
+        crate::markdown::to_markdown(&document),
+        "# Title\n\nSome *text*.\n"
+    );
+}
+
+#[test]
+fn markdown_inline_formatting() {
+    assert_eq!(
+        markdown(
+            "<h1>Title <small>v1</small></h1>\
+             <p>Some <em>emphasis</em>, <strong> strong </strong>, <del>gone</del>, \
+             <code>a `tick`</code>, H<sub>2</sub>O</p>"
+        ),
+        "# Title v1\n\nSome *emphasis*, **strong** , ~~gone~~, `` a `tick` ``, H<sub>2</sub>O\n"
+    );
+}
+
+#[test]
+fn markdown_links_and_images() {
+    assert_eq!(
+        markdown(
+            r#"<p><a href="https://example.com/a b" title="The &quot;site&quot;">link</a>
+               and <img src="i.png" alt="an [image]"></p>"#
+        ),
+        "[link](<https://example.com/a b> \"The \\\"site\\\"\") and ![an \\[image\\]](i.png)\n"
+    );
+}
+
+#[test]
+fn markdown_escapes_text() {
+    assert_eq!(
+        markdown("<p>a<br>2. Not a list item, *not emphasis*.</p>"),
+        "a\\\n2\\. Not a list item, \\*not emphasis\\*.\n"
+    );
+}
+
+#[test]
+fn markdown_lists() {
+    assert_eq!(
+        markdown(r#"<ul><li>One</li><li>Two<ol start="3"><li>Three</li></ol></li></ul>"#),
+        "- One\n- Two\n  3. Three\n"
+    );
+}
+
+#[test]
+fn markdown_list_numbers_are_clamped() {
+    assert_eq!(
+        markdown(r#"<ol start="999999998"><li>A</li><li>B</li><li>C</li></ol>"#),
+        "999999998. A\n999999999. B\n999999999. C\n"
+    );
+    assert_eq!(
+        markdown(r#"<ol start="99999999999"><li>A</li></ol>"#),
+        "999999999. A\n"
+    );
+}
+
+#[test]
+fn markdown_blockquotes() {
+    assert_eq!(
+        markdown("<blockquote><p>Quote</p><p>More</p></blockquote>"),
+        "> Quote\n>\n> More\n"
+    );
+}
+
+#[test]
+fn markdown_code_blocks() {
+    assert_eq!(
+        markdown("<pre><code class=\"language-rust\">fn main() {\n    println!(\"```\");\n}\n</code></pre>"),
+        "````rust\nfn main() {\n    println!(\"```\");\n}\n````\n"
+    );
+}
+
+#[test]
+fn markdown_code_block_language_without_backticks() {
+    assert_eq!(
+        markdown("<pre class=\"language-r`s`\">x</pre>"),
+        "```rs\nx\n```\n"
+    );
+}
+
+#[test]
+fn markdown_tables() {
+    assert_eq!(
+        markdown(
+            "<table><thead><tr><th>A</th><th>B|C</th></tr></thead>\
+             <tbody><tr><td>1</td><td><b>2</b></td></tr></tbody></table>"
+        ),
+        "| A | B\\|C |\n| --- | --- |\n| 1 | **2** |\n"
+    );
+}
+
+#[test]
+fn markdown_raw_html() {
+    assert_eq!(
+        markdown(
+            "<dl><dt>Term</dt><dd>Definition</dd></dl>\
+             <table><tr><td colspan=\"2\">merged</td></tr></table>"
+        ),
+        "<dl><dt>Term</dt><dd>Definition</dd></dl>\n\n\
+         <table><tbody><tr><td colspan=\"2\">merged</td></tr></tbody></table>\n"
+    );
+}
+
+#[test]
+fn markdown_skips_elements_that_are_not_rendered() {
+    assert_eq!(
+        markdown("<p>a<script>b</script><iframe>c</iframe> d</p><template>e</template>"),
+        "a d\n"
+    );
+}
+
+#[test]
+fn markdown_heading_closing_sequence_is_escaped() {
+    assert_eq!(markdown("<h1>Issue #</h1>"), "# Issue \\#\n");
+    assert_eq!(markdown("<h2>## </h2>"), "## \\##\n");
+    assert_eq!(markdown("<h3>C#</h3>"), "### C#\n");
+}
+
+#[test]
+fn markdown_deep_tree() {
+    for name in &[local_name!("div"), local_name!("span")] {
+        let (root, deepest) = nested_elements(name.clone(), 100_000);
+        deepest.append(NodeRef::new_text("deep"));
+        let markdown = crate::markdown::to_markdown(&root);
+        assert_eq!(markdown, "deep\n");
+    }
+    let markdown = markdown(&"<ul><li>x".repeat(1000));
+    assert_eq!(markdown.lines().count(), 1000);
+    assert!(markdown.ends_with(&format!("\n{}- x\n", "  ".repeat(999))));
+}
+
+#[cfg(feature = "serde")]
+const SERDE_HTML: &str = r##"<!DOCTYPE html><html><head></head><body>
+<p id="a" class="b c">Hello <!-- note --><b>world</b></p>
+<template><li>item</li></template>
+<svg xmlns:xlink="http://www.w3.org/1999/xlink"><a xlink:href="#x">link</a></svg>
+</body></html>"##;
+
+#[cfg(feature = "serde")]
+fn serde_round_trip(node: &NodeRef) -> NodeRef {
+    let json = serde_json::to_string(node).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_document() {
+    let document = parse_html().one(SERDE_HTML);
+    let copy = serde_round_trip(&document);
+    assert_eq!(copy.to_string(), document.to_string());
+    assert_eq!(
+        copy.as_document().unwrap().quirks_mode(),
+        QuirksMode::NoQuirks
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_template_contents() {
+    let copy = serde_round_trip(&parse_html().one(SERDE_HTML));
+    let template = copy.select_first("template").unwrap();
+    let contents = template.template_contents.as_ref().unwrap();
+    assert_eq!(contents.text_contents(), "item");
+    assert!(template.as_node().first_child().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_attributes() {
+    let copy = serde_round_trip(&parse_html().one(SERDE_HTML));
+    let link = copy.select_first("svg a").unwrap();
+    let attributes = link.attributes.borrow();
+    let (name, attribute) = attributes.map.iter().next().unwrap();
+    assert_eq!(name.ns, ns!(xlink));
+    assert_eq!(attribute.prefix, Some(namespace_prefix!("xlink")));
+
+    let paragraph = copy.select_first("p").unwrap();
+    let names: Vec<_> = paragraph
+        .attributes
+        .borrow()
+        .map
+        .keys()
+        .map(|name| name.local.to_string())
+        .collect();
+    assert_eq!(names, ["id", "class"]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_deep_tree() {
+    let (root, deepest) = nested_elements(local_name!("div"), 5000);
+    deepest.append(NodeRef::new_text("deep"));
+
+    let copy = serde_round_trip(&root);
+    assert_eq!(copy.descendants().count(), 5001);
+    let text = copy.descendants().text_nodes().next().unwrap();
+    assert_eq!(*text.borrow(), "deep");
+    assert_eq!(text.as_node().ancestors().count(), 5001);
+}
+
+#[cfg(feature = "serde")]
+fn serde_error(json: &str) -> String {
+    serde_json::from_str::<NodeRef>(json)
+        .map(|_| ())
+        .unwrap_err()
+        .to_string()
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_missing_root() {
+    assert_eq!(serde_error(r#"{"nodes":[]}"#), "no root node");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_several_roots() {
+    let json = r#"{"nodes":[
+        {"parent":null,"template_contents":false,"data":"DocumentFragment"},
+        {"parent":null,"template_contents":false,"data":"DocumentFragment"}
+    ]}"#;
+    assert_eq!(serde_error(json), "more than one root node");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_parent_after_child() {
+    let json = r#"{"nodes":[
+        {"parent":1,"template_contents":false,"data":"DocumentFragment"},
+        {"parent":null,"template_contents":false,"data":"DocumentFragment"}
+    ]}"#;
+    assert_eq!(serde_error(json), "parent not before its child");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_children_of_text() {
+    let json = r#"{"nodes":[
+        {"parent":null,"template_contents":false,"data":{"Text":{"text":"a"}}},
+        {"parent":0,"template_contents":false,"data":{"Text":{"text":"b"}}}
+    ]}"#;
+    assert_eq!(serde_error(json), "parent that cannot have these children");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_drops_contents_of_other_elements() {
+    let json = r#"{"nodes":[
+        {"parent":null,"template_contents":false,"data":{"Element":{
+            "namespace":"http://www.w3.org/1999/xhtml","prefix":null,"local_name":"div",
+            "attributes":[]}}},
+        {"parent":0,"template_contents":true,"data":{"Text":{"text":"dropped"}}},
+        {"parent":0,"template_contents":false,"data":{"Text":{"text":"kept"}}}
+    ]}"#;
+    let div: NodeRef = serde_json::from_str(json).unwrap();
+    assert_eq!(div.to_string(), "<div>kept</div>");
+}
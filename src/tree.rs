@@ -1,5 +1,5 @@
 use html5ever::tree_builder::QuirksMode;
-use html5ever::QualName;
+use html5ever::{Namespace, Prefix, QualName};
 use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::ops::Deref;
@@ -99,7 +99,7 @@ impl Deref for NodeRef {
     type Target = Node;
     #[inline]
     fn deref(&self) -> &Node {
-        &*self.0
+        &self.0
     }
 }
 
@@ -281,6 +281,78 @@ impl NodeRef {
         }
         s
     }
+
+    /// The element whose namespace declarations are in scope for this node.
+    fn namespace_scope_element(&self) -> Option<NodeRef> {
+        match self.data {
+            NodeData::Element(_) => Some(self.clone()),
+            NodeData::Document(_) => self.children().find(|child| child.as_element().is_some()),
+            NodeData::Doctype(_) | NodeData::DocumentFragment => None,
+            _ => self.parent().filter(|parent| parent.as_element().is_some()),
+        }
+    }
+
+    /// Return the namespace bound to a prefix (or the default namespace for `None`)
+    /// at this node, from element names and `xmlns` attributes of its ancestors.
+    ///
+    /// <https://dom.spec.whatwg.org/#locate-a-namespace>
+    pub fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<Namespace> {
+        match prefix {
+            Some("xml") => return Some(ns!(xml)),
+            Some("xmlns") => return Some(ns!(xmlns)),
+            _ => {}
+        }
+        let scope = self.namespace_scope_element()?;
+        for element in scope.inclusive_ancestors().elements() {
+            if element.name.ns != ns!() && element.name.prefix.as_deref() == prefix {
+                return Some(element.name.ns.clone());
+            }
+            let attributes = element.attributes.borrow();
+            let declaration = attributes.map.iter().find(|(name, attr)| {
+                name.ns == ns!(xmlns)
+                    && match prefix {
+                        Some(prefix) => {
+                            attr.prefix.as_deref() == Some("xmlns") && &*name.local == prefix
+                        }
+                        None => attr.prefix.is_none() && name.local == local_name!("xmlns"),
+                    }
+            });
+            if let Some((_, attr)) = declaration {
+                return if attr.value.is_empty() {
+                    None
+                } else {
+                    Some(Namespace::from(&*attr.value))
+                };
+            }
+        }
+        None
+    }
+
+    /// Return a prefix bound to a namespace at this node,
+    /// from element names and `xmlns:*` attributes of its ancestors.
+    ///
+    /// <https://dom.spec.whatwg.org/#locate-a-namespace-prefix>
+    pub fn lookup_prefix(&self, namespace: &Namespace) -> Option<Prefix> {
+        if *namespace == ns!() {
+            return None;
+        }
+        let scope = self.namespace_scope_element()?;
+        for element in scope.inclusive_ancestors().elements() {
+            if element.name.ns == *namespace && element.name.prefix.is_some() {
+                return element.name.prefix.clone();
+            }
+            let attributes = element.attributes.borrow();
+            let declaration = attributes.map.iter().find(|(name, attr)| {
+                name.ns == ns!(xmlns)
+                    && attr.prefix.as_deref() == Some("xmlns")
+                    && *attr.value == **namespace
+            });
+            if let Some((name, _)) = declaration {
+                return Some(Prefix::from(&*name.local));
+            }
+        }
+        None
+    }
 }
 
 impl Node {
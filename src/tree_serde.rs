@@ -0,0 +1,262 @@
+//! `Serialize` and `Deserialize` for node trees, with the `serde` feature.
+
+use html5ever::tree_builder::QuirksMode;
+use html5ever::QualName;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::Cell;
+
+use crate::attributes::{Attribute, ExpandedName};
+use crate::tree::{DocumentData, NodeData, NodeRef};
+
+/// A copy of a node and its descendants, as serialized:
+/// the nodes in tree order, each with the index of its parent,
+/// so that deep trees are not nested in the serialized data.
+#[derive(Serialize, Deserialize)]
+struct SerializedTree {
+    nodes: Vec<SerializedNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedNode {
+    /// The index in `nodes` of the parent, which comes first, or `None` for the root.
+    parent: Option<usize>,
+    /// Whether the node is in the contents of its parent `<template>` element
+    /// rather than a child.
+    template_contents: bool,
+    data: SerializedData,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SerializedData {
+    Document {
+        quirks_mode: SerializedQuirksMode,
+    },
+    DocumentFragment,
+    Doctype {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
+    Element {
+        namespace: String,
+        prefix: Option<String>,
+        local_name: String,
+        attributes: Vec<SerializedAttribute>,
+    },
+    Text {
+        text: String,
+    },
+    Comment {
+        text: String,
+    },
+    ProcessingInstruction {
+        target: String,
+        data: String,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedAttribute {
+    namespace: String,
+    prefix: Option<String>,
+    local_name: String,
+    value: String,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SerializedQuirksMode {
+    Quirks,
+    LimitedQuirks,
+    NoQuirks,
+}
+
+impl<'a> From<&'a NodeRef> for SerializedTree {
+    fn from(root: &'a NodeRef) -> SerializedTree {
+        let mut nodes = Vec::new();
+        // Nodes to serialize, with their parent index and whether they are template contents.
+        // Children are pushed in reverse order to be popped in tree order.
+        let mut stack = vec![(root.clone(), None, false)];
+        while let Some((node, parent, template_contents)) = stack.pop() {
+            let index = nodes.len();
+            nodes.push(SerializedNode {
+                parent,
+                template_contents,
+                data: SerializedData::from(&node),
+            });
+            if let Some(fragment) = node
+                .as_element()
+                .and_then(|element| element.template_contents.as_ref())
+            {
+                stack.extend(
+                    fragment
+                        .children()
+                        .rev()
+                        .map(|child| (child, Some(index), true)),
+                )
+            }
+            stack.extend(
+                node.children()
+                    .rev()
+                    .map(|child| (child, Some(index), false)),
+            )
+        }
+        SerializedTree { nodes }
+    }
+}
+
+impl<'a> From<&'a NodeRef> for SerializedData {
+    fn from(node: &'a NodeRef) -> SerializedData {
+        match node.data() {
+            NodeData::Document(document) => SerializedData::Document {
+                quirks_mode: match document.quirks_mode() {
+                    QuirksMode::Quirks => SerializedQuirksMode::Quirks,
+                    QuirksMode::LimitedQuirks => SerializedQuirksMode::LimitedQuirks,
+                    QuirksMode::NoQuirks => SerializedQuirksMode::NoQuirks,
+                },
+            },
+            NodeData::DocumentFragment => SerializedData::DocumentFragment,
+            NodeData::Doctype(doctype) => SerializedData::Doctype {
+                name: doctype.name.clone(),
+                public_id: doctype.public_id.clone(),
+                system_id: doctype.system_id.clone(),
+            },
+            NodeData::Element(element) => SerializedData::Element {
+                namespace: element.name.ns.to_string(),
+                prefix: element
+                    .name
+                    .prefix
+                    .as_ref()
+                    .map(|prefix| prefix.to_string()),
+                local_name: element.name.local.to_string(),
+                attributes: element
+                    .attributes
+                    .borrow()
+                    .map
+                    .iter()
+                    .map(|(name, attribute)| SerializedAttribute {
+                        namespace: name.ns.to_string(),
+                        prefix: attribute.prefix.as_ref().map(|prefix| prefix.to_string()),
+                        local_name: name.local.to_string(),
+                        value: attribute.value.clone(),
+                    })
+                    .collect(),
+            },
+            NodeData::Text(text) => SerializedData::Text {
+                text: text.borrow().clone(),
+            },
+            NodeData::Comment(text) => SerializedData::Comment {
+                text: text.borrow().clone(),
+            },
+            NodeData::ProcessingInstruction(contents) => {
+                let contents = contents.borrow();
+                SerializedData::ProcessingInstruction {
+                    target: contents.0.clone(),
+                    data: contents.1.clone(),
+                }
+            }
+        }
+    }
+}
+
+impl From<SerializedData> for NodeRef {
+    fn from(data: SerializedData) -> NodeRef {
+        match data {
+            SerializedData::Document { quirks_mode } => {
+                NodeRef::new(NodeData::Document(DocumentData {
+                    _quirks_mode: Cell::new(match quirks_mode {
+                        SerializedQuirksMode::Quirks => QuirksMode::Quirks,
+                        SerializedQuirksMode::LimitedQuirks => QuirksMode::LimitedQuirks,
+                        SerializedQuirksMode::NoQuirks => QuirksMode::NoQuirks,
+                    }),
+                }))
+            }
+            SerializedData::DocumentFragment => NodeRef::new(NodeData::DocumentFragment),
+            SerializedData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => NodeRef::new_doctype(name, public_id, system_id),
+            SerializedData::Element {
+                namespace,
+                prefix,
+                local_name,
+                attributes,
+            } => {
+                let name =
+                    QualName::new(prefix.map(Into::into), namespace.into(), local_name.into());
+                let attributes = attributes.into_iter().map(|attribute| {
+                    (
+                        ExpandedName::new(attribute.namespace, attribute.local_name),
+                        Attribute {
+                            prefix: attribute.prefix.map(Into::into),
+                            value: attribute.value,
+                        },
+                    )
+                });
+                NodeRef::new_element(name, attributes)
+            }
+            SerializedData::Text { text } => NodeRef::new_text(text),
+            SerializedData::Comment { text } => NodeRef::new_comment(text),
+            SerializedData::ProcessingInstruction { target, data } => {
+                NodeRef::new_processing_instruction(target, data)
+            }
+        }
+    }
+}
+
+impl SerializedTree {
+    /// Build the tree, or return an error message if the nodes do not form one.
+    fn into_node(self) -> Result<NodeRef, &'static str> {
+        let mut nodes: Vec<NodeRef> = Vec::with_capacity(self.nodes.len());
+        for (index, node) in self.nodes.into_iter().enumerate() {
+            let new_node = NodeRef::from(node.data);
+            match node.parent {
+                None if index == 0 => {}
+                None => return Err("more than one root node"),
+                Some(parent) => {
+                    let parent = nodes.get(parent).ok_or("parent not before its child")?;
+                    // `new_element` creates the contents fragment of HTML `<template>` elements.
+                    // Contents given for any other element are dropped.
+                    let parent = match (parent.data(), node.template_contents) {
+                        (NodeData::Element(element), true) => element.template_contents.clone(),
+                        (
+                            NodeData::Document(_)
+                            | NodeData::DocumentFragment
+                            | NodeData::Element(_),
+                            false,
+                        ) => Some(parent.clone()),
+                        _ => return Err("parent that cannot have these children"),
+                    };
+                    if let Some(parent) = parent {
+                        parent.append(new_node.clone())
+                    }
+                }
+            }
+            nodes.push(new_node)
+        }
+        nodes.into_iter().next().ok_or("no root node")
+    }
+}
+
+/// Serializes a node and its descendants: node kinds, names and namespaces,
+/// attributes in order, children, and the contents of `<template>` elements.
+///
+/// Nodes are listed in tree order with the index of their parent,
+/// so trees of any depth can be serialized and deserialized.
+impl Serialize for NodeRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedTree::from(self).serialize(serializer)
+    }
+}
+
+/// Deserializes a new tree, as serialized by `Serialize`.
+///
+/// Nodes with a parent that does not come before them or cannot have children are an error.
+impl<'de> Deserialize<'de> for NodeRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NodeRef, D::Error> {
+        SerializedTree::deserialize(deserializer)?
+            .into_node()
+            .map_err(D::Error::custom)
+    }
+}
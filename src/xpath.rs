@@ -0,0 +1,1602 @@
+//! XPath 1.0 expressions.
+//!
+//! Expressions are evaluated against the tree with a `NodeRef` as the context node.
+//! Attributes are exposed as nodes of the XPath data model through `XPathNode::Attribute`.
+//! Namespace nodes are not supported: the `namespace` axis is always empty.
+//!
+//! ```
+//! use kuchiki::traits::*;
+//!
+//! let document = kuchiki::parse_html().one("<ul><li>one</li><li class=x>two</li></ul>");
+//! let value = document.xpath("string(//li[@class = 'x'])").unwrap();
+//! assert_eq!(value.string(), "two");
+//! assert_eq!(document.xpath("count(//li)").unwrap().number(), 2.);
+//! ```
+
+use html5ever::{LocalName, Namespace};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::rc::Rc;
+
+use crate::attributes::ExpandedName;
+use crate::element_state;
+use crate::tree::{Node, NodeData, NodeRef};
+
+impl NodeRef {
+    /// Compile and evaluate an XPath 1.0 expression with this node as the context node.
+    #[inline]
+    pub fn xpath(&self, expression: &str) -> Result<Value, XPathError> {
+        XPath::compile(expression)?.evaluate(self)
+    }
+}
+
+/// A compiled XPath 1.0 expression.
+#[derive(Debug, Clone)]
+pub struct XPath {
+    expr: Expr,
+    source: String,
+}
+
+impl XPath {
+    /// Compile an expression.
+    ///
+    /// The prefixes `xml`, `html`, `svg`, `mathml` and `xlink` are bound to their usual namespaces.
+    /// Unprefixed element names match elements in the HTML namespace (ignoring ASCII case)
+    /// or in no namespace.
+    pub fn compile(expression: &str) -> Result<XPath, XPathError> {
+        XPath::compile_with_namespaces(expression, &HashMap::new())
+    }
+
+    /// Compile an expression, with additional (or overriding) namespace prefix bindings.
+    pub fn compile_with_namespaces(
+        expression: &str,
+        namespaces: &HashMap<String, Namespace>,
+    ) -> Result<XPath, XPathError> {
+        let mut bindings: HashMap<String, Namespace> = vec![
+            ("xml", ns!(xml)),
+            ("html", ns!(html)),
+            ("svg", ns!(svg)),
+            ("mathml", ns!(mathml)),
+            ("xlink", ns!(xlink)),
+        ]
+        .into_iter()
+        .map(|(prefix, ns)| (prefix.to_owned(), ns))
+        .collect();
+        bindings.extend(namespaces.iter().map(|(p, ns)| (p.clone(), ns.clone())));
+
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+            end: expression.len(),
+            namespaces: &bindings,
+            depth: 0,
+        };
+        let expr = parser.parse_expr()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("unexpected token"));
+        }
+        Ok(XPath {
+            expr,
+            source: expression.to_owned(),
+        })
+    }
+
+    /// Evaluate the expression with the given node as the context node.
+    #[inline]
+    pub fn evaluate(&self, context: &NodeRef) -> Result<Value, XPathError> {
+        self.evaluate_with_variables(context, &HashMap::new())
+    }
+
+    /// Evaluate the expression with the given node as the context node,
+    /// resolving `$name` references from `variables`.
+    pub fn evaluate_with_variables(
+        &self,
+        context: &NodeRef,
+        variables: &HashMap<String, Value>,
+    ) -> Result<Value, XPathError> {
+        let node = XPathNode::Node(context.clone());
+        let environment = Environment {
+            variables,
+            document_order: DocumentOrder::default(),
+        };
+        let context = Context {
+            node: &node,
+            position: 1,
+            size: 1,
+            environment: &environment,
+        };
+        evaluate(&self.expr, &context)
+    }
+}
+
+impl fmt::Display for XPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// An error compiling or evaluating an XPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XPathError {
+    /// The expression is not valid XPath 1.0 syntax.
+    Syntax {
+        /// Byte offset in the expression where the error was found.
+        position: usize,
+        /// What went wrong.
+        message: String,
+    },
+    /// The expression calls a function that is not in the core function library.
+    UnknownFunction(String),
+    /// A function was called with the wrong number of arguments.
+    ArgumentCount(String),
+    /// A name test uses a prefix with no namespace binding.
+    UnknownPrefix(String),
+    /// A `$name` reference has no value.
+    UnknownVariable(String),
+    /// A node-set was required but another type of value was found.
+    NotANodeSet,
+}
+
+impl fmt::Display for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XPathError::Syntax { position, message } => {
+                write!(f, "XPath syntax error at {}: {}", position, message)
+            }
+            XPathError::UnknownFunction(name) => write!(f, "unknown XPath function {}()", name),
+            XPathError::ArgumentCount(name) => {
+                write!(f, "wrong number of arguments to {}()", name)
+            }
+            XPathError::UnknownPrefix(prefix) => write!(f, "unbound namespace prefix {}", prefix),
+            XPathError::UnknownVariable(name) => write!(f, "unbound variable ${}", name),
+            XPathError::NotANodeSet => f.write_str("expected a node-set"),
+        }
+    }
+}
+
+impl Error for XPathError {}
+
+/// The result of evaluating an XPath expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Nodes without duplicates, in document order.
+    NodeSet(Vec<XPathNode>),
+    /// A boolean.
+    Boolean(bool),
+    /// A number.
+    Number(f64),
+    /// A string.
+    String(String),
+}
+
+impl Value {
+    /// Convert to a boolean, like the `boolean()` function.
+    pub fn boolean(&self) -> bool {
+        match self {
+            Value::NodeSet(nodes) => !nodes.is_empty(),
+            Value::Boolean(b) => *b,
+            Value::Number(n) => *n != 0. && !n.is_nan(),
+            Value::String(s) => !s.is_empty(),
+        }
+    }
+
+    /// Convert to a number, like the `number()` function.
+    pub fn number(&self) -> f64 {
+        match self {
+            Value::Boolean(b) => {
+                if *b {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Value::Number(n) => *n,
+            Value::NodeSet(_) | Value::String(_) => string_to_number(&self.string()),
+        }
+    }
+
+    /// Convert to a string, like the `string()` function.
+    pub fn string(&self) -> String {
+        match self {
+            Value::NodeSet(nodes) => nodes.first().map_or_else(String::new, |n| n.string_value()),
+            Value::Boolean(b) => b.to_string(),
+            Value::Number(n) => number_to_string(*n),
+            Value::String(s) => s.clone(),
+        }
+    }
+
+    /// Return the nodes if this is a node-set.
+    pub fn into_node_set(self) -> Option<Vec<XPathNode>> {
+        match self {
+            Value::NodeSet(nodes) => Some(nodes),
+            _ => None,
+        }
+    }
+}
+
+/// A node of the XPath data model.
+#[derive(Debug, Clone, PartialEq)]
+pub enum XPathNode {
+    /// A node of the tree.
+    Node(NodeRef),
+    /// An attribute of an element.
+    Attribute {
+        /// The element carrying the attribute.
+        element: NodeRef,
+        /// The name of the attribute.
+        name: ExpandedName,
+    },
+}
+
+impl XPathNode {
+    /// Return the tree node, if this is not an attribute.
+    pub fn as_node(&self) -> Option<&NodeRef> {
+        match self {
+            XPathNode::Node(node) => Some(node),
+            XPathNode::Attribute { .. } => None,
+        }
+    }
+
+    /// Return the string-value of the node: the text content of elements and documents,
+    /// or the value of an attribute.
+    pub fn string_value(&self) -> String {
+        match self {
+            XPathNode::Node(node) => match node.data() {
+                NodeData::Element(_) | NodeData::Document(_) | NodeData::DocumentFragment => {
+                    node.text_contents()
+                }
+                NodeData::Text(text) | NodeData::Comment(text) => text.borrow().clone(),
+                NodeData::ProcessingInstruction(contents) => contents.borrow().1.clone(),
+                NodeData::Doctype(_) => String::new(),
+            },
+            XPathNode::Attribute { element, name } => element
+                .as_element()
+                .and_then(|e| e.attributes.borrow().map.get(name).map(|a| a.value.clone()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The tree node itself, or the element of an attribute.
+    fn tree_node(&self) -> &NodeRef {
+        match self {
+            XPathNode::Node(node) => node,
+            XPathNode::Attribute { element, .. } => element,
+        }
+    }
+
+    fn local_name(&self) -> String {
+        match self {
+            XPathNode::Node(node) => match node.data() {
+                NodeData::Element(element) => element.name.local.to_string(),
+                NodeData::ProcessingInstruction(contents) => contents.borrow().0.clone(),
+                _ => String::new(),
+            },
+            XPathNode::Attribute { name, .. } => name.local.to_string(),
+        }
+    }
+
+    fn namespace_uri(&self) -> String {
+        match self {
+            XPathNode::Node(node) => node
+                .as_element()
+                .map_or_else(String::new, |e| e.name.ns.to_string()),
+            XPathNode::Attribute { name, .. } => name.ns.to_string(),
+        }
+    }
+
+    fn qualified_name(&self) -> String {
+        let prefix = match self {
+            XPathNode::Node(node) => node.as_element().and_then(|e| e.name.prefix.clone()),
+            XPathNode::Attribute { element, name } => element
+                .as_element()
+                .and_then(|e| e.attributes.borrow().map.get(name)?.prefix.clone()),
+        };
+        match prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name()),
+            None => self.local_name(),
+        }
+    }
+}
+
+fn is_xml_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// <https://www.w3.org/TR/xpath-10/#function-number>
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_xml_whitespace);
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let valid = digits.bytes().any(|b| b.is_ascii_digit())
+        && digits.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+        && digits.bytes().filter(|&b| b == b'.').count() <= 1;
+    if valid {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+/// <https://www.w3.org/TR/xpath-10/#function-string>
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_owned()
+    } else if n.is_infinite() {
+        if n > 0. { "Infinity" } else { "-Infinity" }.to_owned()
+    } else if n == 0. {
+        "0".to_owned()
+    } else {
+        n.to_string()
+    }
+}
+
+/// <https://www.w3.org/TR/xpath-10/#function-round>
+///
+/// Halves round towards positive infinity, and results that round to zero keep the sign.
+fn round(n: f64) -> f64 {
+    if !n.is_finite() || n == 0. {
+        return n;
+    }
+    // Unlike `n + 0.5`, the fractional part is exact:
+    // 0.49999999999999994 + 0.5 would round up to 1.
+    let floor = n.floor();
+    let rounded = if n - floor >= 0.5 { floor + 1. } else { floor };
+    if rounded == 0. && n < 0. {
+        -0.
+    } else {
+        rounded
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Multiply,
+    And,
+    Or,
+    Mod,
+    Div,
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    /// Prefix and local name, `None` for `*`.
+    NameTest(Option<String>, Option<String>),
+    NodeType(String),
+    FunctionName(String),
+    AxisName(String),
+}
+
+impl Token {
+    fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Token::And
+                | Token::Or
+                | Token::Mod
+                | Token::Div
+                | Token::Multiply
+                | Token::Slash
+                | Token::DoubleSlash
+                | Token::Pipe
+                | Token::Plus
+                | Token::Minus
+                | Token::Equal
+                | Token::NotEqual
+                | Token::Less
+                | Token::LessEqual
+                | Token::Greater
+                | Token::GreaterEqual
+        )
+    }
+}
+
+fn read_ncname(s: &str) -> Option<&str> {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_alphabetic() || c == '_' => {}
+        _ => return None,
+    }
+    let end = chars
+        .find(|&(_, c)| !(c.is_alphanumeric() || matches!(c, '.' | '-' | '_')))
+        .map_or(s.len(), |(i, _)| i);
+    Some(&s[..end])
+}
+
+fn syntax_error(position: usize, message: &str) -> XPathError {
+    XPathError::Syntax {
+        position,
+        message: message.to_owned(),
+    }
+}
+
+/// <https://www.w3.org/TR/xpath-10/#exprlex>
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, XPathError> {
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut position = 0;
+    while let Some(c) = source[position..].chars().next() {
+        if is_xml_whitespace(c) {
+            position += 1;
+            continue;
+        }
+        let start = position;
+        let rest = &source[position..];
+        // `*` and names are operators unless they can start a name test here.
+        let name_test_allowed = tokens.last().map_or(true, |(token, _)| {
+            token.is_operator()
+                || matches!(
+                    token,
+                    Token::At
+                        | Token::ColonColon
+                        | Token::LeftParen
+                        | Token::LeftBracket
+                        | Token::Comma
+                )
+        });
+        let (token, len) = match c {
+            '(' => (Token::LeftParen, 1),
+            ')' => (Token::RightParen, 1),
+            '[' => (Token::LeftBracket, 1),
+            ']' => (Token::RightBracket, 1),
+            '@' => (Token::At, 1),
+            ',' => (Token::Comma, 1),
+            '|' => (Token::Pipe, 1),
+            '+' => (Token::Plus, 1),
+            '-' => (Token::Minus, 1),
+            '=' => (Token::Equal, 1),
+            _ if rest.starts_with("::") => (Token::ColonColon, 2),
+            _ if rest.starts_with("!=") => (Token::NotEqual, 2),
+            _ if rest.starts_with("<=") => (Token::LessEqual, 2),
+            _ if rest.starts_with(">=") => (Token::GreaterEqual, 2),
+            _ if rest.starts_with("//") => (Token::DoubleSlash, 2),
+            _ if rest.starts_with("..") => (Token::DotDot, 2),
+            '<' => (Token::Less, 1),
+            '>' => (Token::Greater, 1),
+            '/' => (Token::Slash, 1),
+            '*' if name_test_allowed => (Token::NameTest(None, None), 1),
+            '*' => (Token::Multiply, 1),
+            '"' | '\'' => match rest[1..].find(c) {
+                Some(len) => (Token::Literal(rest[1..len + 1].to_owned()), len + 2),
+                None => return Err(syntax_error(start, "unterminated string literal")),
+            },
+            '0'..='9' | '.' => {
+                let integer = rest.bytes().take_while(u8::is_ascii_digit).count();
+                let mut len = integer;
+                if rest[len..].starts_with('.') {
+                    len += 1 + rest[len + 1..]
+                        .bytes()
+                        .take_while(u8::is_ascii_digit)
+                        .count();
+                }
+                if len == 1 && c == '.' {
+                    (Token::Dot, len)
+                } else {
+                    (Token::Number(rest[..len].parse().unwrap_or(f64::NAN)), len)
+                }
+            }
+            '$' => match read_qname(&rest[1..]) {
+                Some(name) => (Token::Variable(name.to_owned()), 1 + name.len()),
+                None => return Err(syntax_error(start, "expected a variable name")),
+            },
+            _ => {
+                let name = match read_ncname(rest) {
+                    Some(name) => name,
+                    None => return Err(syntax_error(start, "unexpected character")),
+                };
+                let mut len = name.len();
+                let mut prefix = None;
+                let mut local = Some(name.to_owned());
+                let after = &rest[len..];
+                if after.starts_with(':') && !after.starts_with("::") {
+                    prefix = Some(name.to_owned());
+                    if after[1..].starts_with('*') {
+                        local = None;
+                        len += 2;
+                    } else if let Some(name) = read_ncname(&after[1..]) {
+                        local = Some(name.to_owned());
+                        len += 1 + name.len();
+                    } else {
+                        return Err(syntax_error(start + len, "expected a local name"));
+                    }
+                }
+
+                let token = if !name_test_allowed {
+                    match (&prefix, local.as_deref()) {
+                        (None, Some("and")) => Token::And,
+                        (None, Some("or")) => Token::Or,
+                        (None, Some("mod")) => Token::Mod,
+                        (None, Some("div")) => Token::Div,
+                        _ => return Err(syntax_error(start, "expected an operator")),
+                    }
+                } else {
+                    let following = rest[len..].trim_start_matches(is_xml_whitespace);
+                    match (prefix, local) {
+                        (None, Some(local)) if following.starts_with('(') => match &*local {
+                            "comment" | "text" | "processing-instruction" | "node" => {
+                                Token::NodeType(local)
+                            }
+                            _ => Token::FunctionName(local),
+                        },
+                        (Some(prefix), Some(local)) if following.starts_with('(') => {
+                            Token::FunctionName(format!("{}:{}", prefix, local))
+                        }
+                        (None, Some(local)) if following.starts_with("::") => {
+                            Token::AxisName(local)
+                        }
+                        (prefix, local) => Token::NameTest(prefix, local),
+                    }
+                };
+                (token, len)
+            }
+        };
+        position += len;
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+fn read_qname(s: &str) -> Option<&str> {
+    let prefix = read_ncname(s)?;
+    let rest = &s[prefix.len()..];
+    if rest.starts_with(':') && !rest.starts_with("::") {
+        if let Some(local) = read_ncname(&rest[1..]) {
+            return Some(&s[..prefix.len() + 1 + local.len()]);
+        }
+    }
+    Some(prefix)
+}
+
+/// A parsed expression.
+///
+/// Chains of left-associative operators are kept flat rather than nested,
+/// so that the depth of the tree is bounded by the nesting limit of the parser.
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Compare(Box<Expr>, Vec<(Comparison, Expr)>),
+    Arithmetic(Box<Expr>, Vec<(Arithmetic, Expr)>),
+    Negate(Box<Expr>),
+    Union(Vec<Expr>),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Function(Function, Vec<Expr>),
+    /// A primary expression followed by predicates.
+    Filter(Box<Expr>, Vec<Expr>),
+    Path(PathStart, Vec<Step>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+#[derive(Debug, Clone)]
+enum PathStart {
+    Context,
+    Root,
+    Filter(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+impl Step {
+    fn descendant_or_self() -> Step {
+        Step {
+            axis: Axis::DescendantOrSelf,
+            test: NodeTest::Node,
+            predicates: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    Itself,
+}
+
+impl Axis {
+    fn from_name(name: &str) -> Option<Axis> {
+        Some(match name {
+            "ancestor" => Axis::Ancestor,
+            "ancestor-or-self" => Axis::AncestorOrSelf,
+            "attribute" => Axis::Attribute,
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "following" => Axis::Following,
+            "following-sibling" => Axis::FollowingSibling,
+            "namespace" => Axis::Namespace,
+            "parent" => Axis::Parent,
+            "preceding" => Axis::Preceding,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "self" => Axis::Itself,
+            _ => return None,
+        })
+    }
+
+    /// Reverse axes yield nodes nearest first, which is what predicate positions count.
+    fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NodeTest {
+    /// `*`
+    Any,
+    /// `prefix:*`
+    Namespace(Namespace),
+    /// A name, with the namespace of its prefix if any.
+    Name(Option<Namespace>, LocalName),
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>),
+}
+
+impl NodeTest {
+    fn matches(&self, node: &XPathNode, axis: Axis) -> bool {
+        // Name tests select nodes of the axis’ principal node type.
+        let name = match (node, axis) {
+            (XPathNode::Attribute { name, .. }, Axis::Attribute) => Some(name.clone()),
+            (XPathNode::Node(node), _) if axis != Axis::Attribute => node
+                .as_element()
+                .map(|e| ExpandedName::new(e.name.ns.clone(), e.name.local.clone())),
+            _ => None,
+        };
+        match self {
+            NodeTest::Any => name.is_some(),
+            NodeTest::Namespace(ns) => name.is_some_and(|name| name.ns == *ns),
+            NodeTest::Name(Some(ns), local) => {
+                name.is_some_and(|name| name.ns == *ns && name.local == *local)
+            }
+            NodeTest::Name(None, local) => name.is_some_and(|name| {
+                if axis == Axis::Attribute || name.ns == ns!() {
+                    name.ns == ns!() && name.local == *local
+                } else {
+                    name.ns == ns!(html) && name.local.eq_ignore_ascii_case(local)
+                }
+            }),
+            NodeTest::Node => true,
+            NodeTest::Text => node.as_node().is_some_and(|n| n.as_text().is_some()),
+            NodeTest::Comment => node.as_node().is_some_and(|n| n.as_comment().is_some()),
+            NodeTest::ProcessingInstruction(target) => match node.as_node().map(|n| n.data()) {
+                Some(NodeData::ProcessingInstruction(contents)) => {
+                    target.as_ref().map_or(true, |t| *t == contents.borrow().0)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Last,
+    Position,
+    Count,
+    Id,
+    LocalName,
+    NamespaceUri,
+    Name,
+    String,
+    Concat,
+    StartsWith,
+    Contains,
+    SubstringBefore,
+    SubstringAfter,
+    Substring,
+    StringLength,
+    NormalizeSpace,
+    Translate,
+    Boolean,
+    Not,
+    True,
+    False,
+    Lang,
+    Number,
+    Sum,
+    Floor,
+    Ceiling,
+    Round,
+}
+
+impl Function {
+    /// A core library function and its minimum and maximum number of arguments.
+    fn from_name(name: &str) -> Option<(Function, usize, usize)> {
+        Some(match name {
+            "last" => (Function::Last, 0, 0),
+            "position" => (Function::Position, 0, 0),
+            "count" => (Function::Count, 1, 1),
+            "id" => (Function::Id, 1, 1),
+            "local-name" => (Function::LocalName, 0, 1),
+            "namespace-uri" => (Function::NamespaceUri, 0, 1),
+            "name" => (Function::Name, 0, 1),
+            "string" => (Function::String, 0, 1),
+            "concat" => (Function::Concat, 2, usize::MAX),
+            "starts-with" => (Function::StartsWith, 2, 2),
+            "contains" => (Function::Contains, 2, 2),
+            "substring-before" => (Function::SubstringBefore, 2, 2),
+            "substring-after" => (Function::SubstringAfter, 2, 2),
+            "substring" => (Function::Substring, 2, 3),
+            "string-length" => (Function::StringLength, 0, 1),
+            "normalize-space" => (Function::NormalizeSpace, 0, 1),
+            "translate" => (Function::Translate, 3, 3),
+            "boolean" => (Function::Boolean, 1, 1),
+            "not" => (Function::Not, 1, 1),
+            "true" => (Function::True, 0, 0),
+            "false" => (Function::False, 0, 0),
+            "lang" => (Function::Lang, 1, 1),
+            "number" => (Function::Number, 0, 1),
+            "sum" => (Function::Sum, 1, 1),
+            "floor" => (Function::Floor, 1, 1),
+            "ceiling" => (Function::Ceiling, 1, 1),
+            "round" => (Function::Round, 1, 1),
+            _ => return None,
+        })
+    }
+}
+
+/// How deeply parenthesized expressions, predicates and function arguments can be nested.
+///
+/// Both parsing and evaluation recurse once per level.
+const MAX_DEPTH: usize = 100;
+
+/// <https://www.w3.org/TR/xpath-10/#section-Expressions>
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Length of the source, for errors at the end of input.
+    end: usize,
+    namespaces: &'a HashMap<String, Namespace>,
+    /// The number of `parse_expr` calls in progress.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token, message: &str) -> Result<(), XPathError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn error(&self, message: &str) -> XPathError {
+        let position = self
+            .tokens
+            .get(self.position)
+            .map_or(self.end, |&(_, position)| position);
+        syntax_error(position, message)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, XPathError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expression nested too deeply"));
+        }
+        self.depth += 1;
+        let result = self.parse_or();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, XPathError> {
+        let mut operands = vec![self.parse_and()?];
+        while self.eat(&Token::Or) {
+            operands.push(self.parse_and()?);
+        }
+        Ok(single_or(operands, Expr::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, XPathError> {
+        let mut operands = vec![self.parse_equality()?];
+        while self.eat(&Token::And) {
+            operands.push(self.parse_equality()?);
+        }
+        Ok(single_or(operands, Expr::And))
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr, XPathError> {
+        let left = self.parse_relational()?;
+        let mut rest = Vec::new();
+        loop {
+            let comparison = match self.peek() {
+                Some(Token::Equal) => Comparison::Equal,
+                Some(Token::NotEqual) => Comparison::NotEqual,
+                _ => return Ok(chain(left, rest, Expr::Compare)),
+            };
+            self.position += 1;
+            rest.push((comparison, self.parse_relational()?));
+        }
+    }
+
+    fn parse_relational(&mut self) -> Result<Expr, XPathError> {
+        let left = self.parse_additive()?;
+        let mut rest = Vec::new();
+        loop {
+            let comparison = match self.peek() {
+                Some(Token::Less) => Comparison::Less,
+                Some(Token::LessEqual) => Comparison::LessEqual,
+                Some(Token::Greater) => Comparison::Greater,
+                Some(Token::GreaterEqual) => Comparison::GreaterEqual,
+                _ => return Ok(chain(left, rest, Expr::Compare)),
+            };
+            self.position += 1;
+            rest.push((comparison, self.parse_additive()?));
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, XPathError> {
+        let left = self.parse_multiplicative()?;
+        let mut rest = Vec::new();
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => Arithmetic::Add,
+                Some(Token::Minus) => Arithmetic::Subtract,
+                _ => return Ok(chain(left, rest, Expr::Arithmetic)),
+            };
+            self.position += 1;
+            rest.push((operator, self.parse_multiplicative()?));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, XPathError> {
+        let left = self.parse_unary()?;
+        let mut rest = Vec::new();
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Multiply) => Arithmetic::Multiply,
+                Some(Token::Div) => Arithmetic::Divide,
+                Some(Token::Mod) => Arithmetic::Modulo,
+                _ => return Ok(chain(left, rest, Expr::Arithmetic)),
+            };
+            self.position += 1;
+            rest.push((operator, self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, XPathError> {
+        let mut negations = 0;
+        while self.eat(&Token::Minus) {
+            negations += 1;
+        }
+        let operand = self.parse_union()?;
+        // Negating twice converts to a number without changing it.
+        Ok(match negations {
+            0 => operand,
+            n if n % 2 == 1 => Expr::Negate(Box::new(operand)),
+            _ => Expr::Negate(Box::new(Expr::Negate(Box::new(operand)))),
+        })
+    }
+
+    fn parse_union(&mut self) -> Result<Expr, XPathError> {
+        let mut operands = vec![self.parse_path()?];
+        while self.eat(&Token::Pipe) {
+            operands.push(self.parse_path()?);
+        }
+        Ok(single_or(operands, Expr::Union))
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::NameTest(..))
+                | Some(Token::NodeType(_))
+                | Some(Token::AxisName(_))
+                | Some(Token::At)
+                | Some(Token::Dot)
+                | Some(Token::DotDot)
+        )
+    }
+
+    fn parse_path(&mut self) -> Result<Expr, XPathError> {
+        let mut steps = Vec::new();
+        if self.eat(&Token::Slash) {
+            if self.starts_step() {
+                steps.push(self.parse_step()?);
+                self.parse_more_steps(&mut steps)?;
+            }
+            return Ok(Expr::Path(PathStart::Root, steps));
+        }
+        if self.eat(&Token::DoubleSlash) {
+            steps.push(Step::descendant_or_self());
+            steps.push(self.parse_step()?);
+            self.parse_more_steps(&mut steps)?;
+            return Ok(Expr::Path(PathStart::Root, steps));
+        }
+        if self.starts_step() {
+            steps.push(self.parse_step()?);
+            self.parse_more_steps(&mut steps)?;
+            return Ok(Expr::Path(PathStart::Context, steps));
+        }
+
+        let primary = self.parse_primary()?;
+        let predicates = self.parse_predicates()?;
+        let filter = if predicates.is_empty() {
+            primary
+        } else {
+            Expr::Filter(Box::new(primary), predicates)
+        };
+        self.parse_more_steps(&mut steps)?;
+        if steps.is_empty() {
+            Ok(filter)
+        } else {
+            Ok(Expr::Path(PathStart::Filter(Box::new(filter)), steps))
+        }
+    }
+
+    /// Parse any number of `/step` or `//step`.
+    fn parse_more_steps(&mut self, steps: &mut Vec<Step>) -> Result<(), XPathError> {
+        loop {
+            if self.eat(&Token::DoubleSlash) {
+                steps.push(Step::descendant_or_self());
+            } else if !self.eat(&Token::Slash) {
+                return Ok(());
+            }
+            steps.push(self.parse_step()?);
+        }
+    }
+
+    fn parse_step(&mut self) -> Result<Step, XPathError> {
+        let axis = match self.peek() {
+            Some(Token::Dot) | Some(Token::DotDot) => {
+                let axis = if self.next() == Some(Token::Dot) {
+                    Axis::Itself
+                } else {
+                    Axis::Parent
+                };
+                return Ok(Step {
+                    axis,
+                    test: NodeTest::Node,
+                    predicates: Vec::new(),
+                });
+            }
+            Some(Token::At) => {
+                self.position += 1;
+                Axis::Attribute
+            }
+            Some(Token::AxisName(name)) => {
+                let axis = Axis::from_name(name).ok_or_else(|| self.error("unknown axis"))?;
+                self.position += 1;
+                self.expect(&Token::ColonColon, "expected ::")?;
+                axis
+            }
+            _ => Axis::Child,
+        };
+        let test = self.parse_node_test()?;
+        let predicates = self.parse_predicates()?;
+        Ok(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn parse_node_test(&mut self) -> Result<NodeTest, XPathError> {
+        let error = self.error("expected a node test");
+        match self.next() {
+            Some(Token::NameTest(prefix, local)) => {
+                let ns = match prefix {
+                    Some(prefix) => match self.namespaces.get(&prefix) {
+                        Some(ns) => Some(ns.clone()),
+                        None => return Err(XPathError::UnknownPrefix(prefix)),
+                    },
+                    None => None,
+                };
+                Ok(match (ns, local) {
+                    (None, None) => NodeTest::Any,
+                    (Some(ns), None) => NodeTest::Namespace(ns),
+                    (ns, Some(local)) => NodeTest::Name(ns, LocalName::from(local)),
+                })
+            }
+            Some(Token::NodeType(node_type)) => {
+                self.expect(&Token::LeftParen, "expected (")?;
+                let test = match &*node_type {
+                    "comment" => NodeTest::Comment,
+                    "text" => NodeTest::Text,
+                    "node" => NodeTest::Node,
+                    _ => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            let target = target.clone();
+                            self.position += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                };
+                self.expect(&Token::RightParen, "expected )")?;
+                Ok(test)
+            }
+            _ => Err(error),
+        }
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>, XPathError> {
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LeftBracket) {
+            predicates.push(self.parse_expr()?);
+            self.expect(&Token::RightBracket, "expected ]")?;
+        }
+        Ok(predicates)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, XPathError> {
+        let error = self.error("expected an expression");
+        match self.next() {
+            Some(Token::Variable(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Literal(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::LeftParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RightParen, "expected )")?;
+                Ok(expr)
+            }
+            Some(Token::FunctionName(name)) => {
+                self.expect(&Token::LeftParen, "expected (")?;
+                let mut arguments = Vec::new();
+                if !self.eat(&Token::RightParen) {
+                    loop {
+                        arguments.push(self.parse_expr()?);
+                        if !self.eat(&Token::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(&Token::RightParen, "expected )")?;
+                }
+                let (function, min, max) = Function::from_name(&name)
+                    .ok_or_else(|| XPathError::UnknownFunction(name.clone()))?;
+                if arguments.len() < min || arguments.len() > max {
+                    return Err(XPathError::ArgumentCount(name));
+                }
+                Ok(Expr::Function(function, arguments))
+            }
+            _ => Err(error),
+        }
+    }
+}
+
+/// The expression made of a single operand, or all operands combined with `combine`.
+fn single_or(mut operands: Vec<Expr>, combine: fn(Vec<Expr>) -> Expr) -> Expr {
+    if operands.len() == 1 {
+        operands.pop().unwrap()
+    } else {
+        combine(operands)
+    }
+}
+
+/// The left operand, or the chain of operations starting with it.
+fn chain<T>(
+    left: Expr,
+    rest: Vec<(T, Expr)>,
+    combine: fn(Box<Expr>, Vec<(T, Expr)>) -> Expr,
+) -> Expr {
+    if rest.is_empty() {
+        left
+    } else {
+        combine(Box::new(left), rest)
+    }
+}
+
+/// State shared by the whole evaluation of an expression.
+struct Environment<'a> {
+    variables: &'a HashMap<String, Value>,
+    document_order: DocumentOrder,
+}
+
+struct Context<'a> {
+    node: &'a XPathNode,
+    position: usize,
+    size: usize,
+    environment: &'a Environment<'a>,
+}
+
+fn node_set(value: Value) -> Result<Vec<XPathNode>, XPathError> {
+    value.into_node_set().ok_or(XPathError::NotANodeSet)
+}
+
+fn root_of(node: &XPathNode) -> NodeRef {
+    node.tree_node().inclusive_ancestors().last().unwrap()
+}
+
+fn evaluate(expr: &Expr, context: &Context) -> Result<Value, XPathError> {
+    Ok(match expr {
+        Expr::Or(operands) => {
+            for operand in operands {
+                if evaluate(operand, context)?.boolean() {
+                    return Ok(Value::Boolean(true));
+                }
+            }
+            Value::Boolean(false)
+        }
+        Expr::And(operands) => {
+            for operand in operands {
+                if !evaluate(operand, context)?.boolean() {
+                    return Ok(Value::Boolean(false));
+                }
+            }
+            Value::Boolean(true)
+        }
+        Expr::Compare(left, rest) => {
+            let mut left = evaluate(left, context)?;
+            for (comparison, right) in rest {
+                let right = evaluate(right, context)?;
+                left = Value::Boolean(compare(&left, *comparison, &right));
+            }
+            left
+        }
+        Expr::Arithmetic(left, rest) => {
+            let mut left = evaluate(left, context)?.number();
+            for (operator, right) in rest {
+                let right = evaluate(right, context)?.number();
+                left = match operator {
+                    Arithmetic::Add => left + right,
+                    Arithmetic::Subtract => left - right,
+                    Arithmetic::Multiply => left * right,
+                    Arithmetic::Divide => left / right,
+                    Arithmetic::Modulo => left % right,
+                };
+            }
+            Value::Number(left)
+        }
+        Expr::Negate(operand) => Value::Number(-evaluate(operand, context)?.number()),
+        Expr::Union(operands) => {
+            let mut nodes = Vec::new();
+            for operand in operands {
+                nodes.extend(node_set(evaluate(operand, context)?)?);
+            }
+            context.environment.document_order.sort(&mut nodes);
+            Value::NodeSet(nodes)
+        }
+        Expr::Literal(value) => Value::String(value.clone()),
+        Expr::Number(value) => Value::Number(*value),
+        Expr::Variable(name) => context
+            .environment
+            .variables
+            .get(name)
+            .cloned()
+            .ok_or_else(|| XPathError::UnknownVariable(name.clone()))?,
+        Expr::Function(function, arguments) => call_function(*function, arguments, context)?,
+        Expr::Filter(primary, predicates) => {
+            let nodes = node_set(evaluate(primary, context)?)?;
+            Value::NodeSet(apply_predicates(nodes, predicates, context.environment)?)
+        }
+        Expr::Path(start, steps) => {
+            let mut nodes = match start {
+                PathStart::Context => vec![context.node.clone()],
+                PathStart::Root => vec![XPathNode::Node(root_of(context.node))],
+                PathStart::Filter(filter) => node_set(evaluate(filter, context)?)?,
+            };
+            for step in steps {
+                nodes = evaluate_step(step, &nodes, context.environment)?;
+            }
+            Value::NodeSet(nodes)
+        }
+    })
+}
+
+fn evaluate_step(
+    step: &Step,
+    contexts: &[XPathNode],
+    environment: &Environment,
+) -> Result<Vec<XPathNode>, XPathError> {
+    let mut result = Vec::new();
+    for context in contexts {
+        let candidates = axis(step.axis, context)
+            .filter(|node| step.test.matches(node, step.axis))
+            .collect();
+        let mut selected = apply_predicates(candidates, &step.predicates, environment)?;
+        if step.axis.is_reverse() {
+            selected.reverse();
+        }
+        result.append(&mut selected);
+    }
+    // A single context node yields each node once and, after the reversal above,
+    // in document order.
+    if contexts.len() > 1 {
+        environment.document_order.sort(&mut result);
+    }
+    Ok(result)
+}
+
+fn apply_predicates(
+    mut nodes: Vec<XPathNode>,
+    predicates: &[Expr],
+    environment: &Environment,
+) -> Result<Vec<XPathNode>, XPathError> {
+    for predicate in predicates {
+        let size = nodes.len();
+        let mut kept = Vec::new();
+        for (index, node) in nodes.into_iter().enumerate() {
+            let context = Context {
+                node: &node,
+                position: index + 1,
+                size,
+                environment,
+            };
+            let keep = match evaluate(predicate, &context)? {
+                Value::Number(position) => position == (index + 1) as f64,
+                value => value.boolean(),
+            };
+            if keep {
+                kept.push(node);
+            }
+        }
+        nodes = kept;
+    }
+    Ok(nodes)
+}
+
+fn tree_nodes<I>(iter: I) -> Box<dyn Iterator<Item = XPathNode>>
+where
+    I: Iterator<Item = NodeRef> + 'static,
+{
+    Box::new(iter.map(XPathNode::Node))
+}
+
+/// Nodes after `node` in document order, excluding its descendants.
+fn following(node: &NodeRef) -> impl Iterator<Item = NodeRef> {
+    node.inclusive_ancestors()
+        .flat_map(|ancestor| ancestor.following_siblings())
+        .flat_map(|sibling| sibling.inclusive_descendants())
+}
+
+/// Nodes before `node` in reverse document order, excluding its ancestors.
+fn preceding(node: &NodeRef) -> impl Iterator<Item = NodeRef> {
+    node.inclusive_ancestors()
+        .flat_map(|ancestor| ancestor.preceding_siblings())
+        .flat_map(|sibling| sibling.inclusive_descendants().rev())
+}
+
+/// The nodes of an axis, in axis order (nearest first for reverse axes).
+fn axis(axis: Axis, node: &XPathNode) -> Box<dyn Iterator<Item = XPathNode>> {
+    match node {
+        XPathNode::Node(node) => match axis {
+            Axis::Ancestor => tree_nodes(node.ancestors()),
+            Axis::AncestorOrSelf => tree_nodes(node.inclusive_ancestors()),
+            Axis::Attribute => {
+                let element = node.clone();
+                let names = match node.as_element() {
+                    Some(e) => e
+                        .attributes
+                        .borrow()
+                        .map
+                        .keys()
+                        .filter(|name| name.ns != ns!(xmlns))
+                        .cloned()
+                        .collect(),
+                    None => Vec::new(),
+                };
+                Box::new(names.into_iter().map(move |name| XPathNode::Attribute {
+                    element: element.clone(),
+                    name,
+                }))
+            }
+            Axis::Child => tree_nodes(node.children()),
+            Axis::Descendant => tree_nodes(node.descendants()),
+            Axis::DescendantOrSelf => tree_nodes(node.inclusive_descendants()),
+            Axis::Following => tree_nodes(following(node)),
+            Axis::FollowingSibling => tree_nodes(node.following_siblings()),
+            Axis::Namespace => Box::new(iter::empty()),
+            Axis::Parent => tree_nodes(node.parent().into_iter()),
+            Axis::Preceding => tree_nodes(preceding(node)),
+            Axis::PrecedingSibling => tree_nodes(node.preceding_siblings()),
+            Axis::Itself => tree_nodes(iter::once(node.clone())),
+        },
+        XPathNode::Attribute { element, .. } => match axis {
+            Axis::Ancestor => tree_nodes(element.inclusive_ancestors()),
+            Axis::AncestorOrSelf => {
+                Box::new(iter::once(node.clone()).chain(tree_nodes(element.inclusive_ancestors())))
+            }
+            // An attribute comes after its element and before the element’s children.
+            Axis::Following => tree_nodes(element.descendants().chain(following(element))),
+            Axis::Parent => tree_nodes(iter::once(element.clone())),
+            Axis::Preceding => tree_nodes(preceding(element)),
+            Axis::Itself | Axis::DescendantOrSelf => Box::new(iter::once(node.clone())),
+            _ => Box::new(iter::empty()),
+        },
+    }
+}
+
+/// Positions of nodes in document order, computed at most once per tree
+/// for the evaluation of an expression.
+#[derive(Default)]
+struct DocumentOrder {
+    /// For each node, the index of its tree and its index in the tree.
+    positions: RefCell<HashMap<*const Node, (usize, usize)>>,
+    /// The roots of the trees numbered so far, which also keeps their nodes alive.
+    roots: RefCell<Vec<NodeRef>>,
+}
+
+impl DocumentOrder {
+    fn position(&self, node: &NodeRef) -> (usize, usize) {
+        if let Some(&position) = self.positions.borrow().get(&Rc::as_ptr(&node.0)) {
+            return position;
+        }
+        let root = node.inclusive_ancestors().last().unwrap();
+        let mut roots = self.roots.borrow_mut();
+        let tree = roots.len();
+        let mut positions = self.positions.borrow_mut();
+        for (index, descendant) in root.inclusive_descendants().enumerate() {
+            positions.insert(Rc::as_ptr(&descendant.0), (tree, index));
+        }
+        roots.push(root);
+        positions[&Rc::as_ptr(&node.0)]
+    }
+
+    /// Sort nodes in document order and remove duplicates.
+    fn sort(&self, nodes: &mut Vec<XPathNode>) {
+        if nodes.len() < 2 {
+            return;
+        }
+        nodes.sort_by_cached_key(|node| {
+            let (tree, index) = self.position(node.tree_node());
+            let attribute = match node {
+                XPathNode::Node(_) => 0,
+                XPathNode::Attribute { element, name } => element
+                    .as_element()
+                    .and_then(|e| e.attributes.borrow().map.get_index_of(name))
+                    .map_or(0, |i| i + 1),
+            };
+            (tree, index, attribute)
+        });
+        nodes.dedup();
+    }
+}
+
+/// <https://www.w3.org/TR/xpath-10/#booleans>
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    match (left, right) {
+        (Value::NodeSet(nodes), Value::Boolean(_)) => {
+            compare_atomic(&Value::Boolean(!nodes.is_empty()), comparison, right)
+        }
+        (Value::Boolean(_), Value::NodeSet(nodes)) => {
+            compare_atomic(left, comparison, &Value::Boolean(!nodes.is_empty()))
+        }
+        (Value::NodeSet(nodes), _) => nodes
+            .iter()
+            .any(|node| compare(&Value::String(node.string_value()), comparison, right)),
+        (_, Value::NodeSet(nodes)) => nodes
+            .iter()
+            .any(|node| compare_atomic(left, comparison, &Value::String(node.string_value()))),
+        _ => compare_atomic(left, comparison, right),
+    }
+}
+
+fn compare_atomic(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    match comparison {
+        Comparison::Equal | Comparison::NotEqual => {
+            let equal = match (left, right) {
+                (Value::Boolean(_), _) | (_, Value::Boolean(_)) => {
+                    left.boolean() == right.boolean()
+                }
+                (Value::Number(_), _) | (_, Value::Number(_)) => left.number() == right.number(),
+                _ => left.string() == right.string(),
+            };
+            equal == (comparison == Comparison::Equal)
+        }
+        Comparison::Less => left.number() < right.number(),
+        Comparison::LessEqual => left.number() <= right.number(),
+        Comparison::Greater => left.number() > right.number(),
+        Comparison::GreaterEqual => left.number() >= right.number(),
+    }
+}
+
+/// <https://www.w3.org/TR/xpath-10/#corelib>
+fn call_function(
+    function: Function,
+    arguments: &[Expr],
+    context: &Context,
+) -> Result<Value, XPathError> {
+    let value = |i: usize| evaluate(&arguments[i], context);
+    let string = |i: usize| value(i).map(|v| v.string());
+    let number = |i: usize| value(i).map(|v| v.number());
+    // For functions whose single argument defaults to the context node.
+    let string_or_context = || match arguments.first() {
+        Some(argument) => evaluate(argument, context).map(|v| v.string()),
+        None => Ok(context.node.string_value()),
+    };
+
+    Ok(match function {
+        Function::Last => Value::Number(context.size as f64),
+        Function::Position => Value::Number(context.position as f64),
+        Function::Count => Value::Number(node_set(value(0)?)?.len() as f64),
+        Function::Id => {
+            let mut ids = Vec::new();
+            match value(0)? {
+                Value::NodeSet(nodes) => {
+                    for node in nodes {
+                        ids.extend(node.string_value().split_whitespace().map(String::from))
+                    }
+                }
+                value => ids.extend(value.string().split_whitespace().map(String::from)),
+            }
+            let nodes = root_of(context.node)
+                .descendants()
+                .filter(|node| {
+                    node.as_element().is_some_and(|e| {
+                        let attributes = e.attributes.borrow();
+                        attributes
+                            .get(local_name!("id"))
+                            .is_some_and(|id| ids.iter().any(|i| i == id))
+                    })
+                })
+                .map(XPathNode::Node)
+                .collect();
+            Value::NodeSet(nodes)
+        }
+        Function::LocalName | Function::NamespaceUri | Function::Name => {
+            let node = match arguments.first() {
+                Some(argument) => node_set(evaluate(argument, context)?)?.into_iter().next(),
+                None => Some(context.node.clone()),
+            };
+            Value::String(node.map_or_else(String::new, |node| match function {
+                Function::LocalName => node.local_name(),
+                Function::NamespaceUri => node.namespace_uri(),
+                _ => node.qualified_name(),
+            }))
+        }
+        Function::String => Value::String(string_or_context()?),
+        Function::Concat => {
+            let mut result = String::new();
+            for i in 0..arguments.len() {
+                result.push_str(&string(i)?)
+            }
+            Value::String(result)
+        }
+        Function::StartsWith => Value::Boolean(string(0)?.starts_with(&string(1)?)),
+        Function::Contains => Value::Boolean(string(0)?.contains(&string(1)?)),
+        Function::SubstringBefore => {
+            let (s, pattern) = (string(0)?, string(1)?);
+            Value::String(s.find(&pattern).map_or("", |i| &s[..i]).to_owned())
+        }
+        Function::SubstringAfter => {
+            let (s, pattern) = (string(0)?, string(1)?);
+            Value::String(
+                s.find(&pattern)
+                    .map_or("", |i| &s[i + pattern.len()..])
+                    .to_owned(),
+            )
+        }
+        Function::Substring => {
+            let s = string(0)?;
+            let start = round(number(1)?);
+            let end = if arguments.len() > 2 {
+                start + round(number(2)?)
+            } else {
+                f64::INFINITY
+            };
+            Value::String(
+                s.chars()
+                    .enumerate()
+                    .filter(|&(i, _)| {
+                        let position = (i + 1) as f64;
+                        position >= start && position < end
+                    })
+                    .map(|(_, c)| c)
+                    .collect(),
+            )
+        }
+        Function::StringLength => Value::Number(string_or_context()?.chars().count() as f64),
+        Function::NormalizeSpace => Value::String(
+            string_or_context()?
+                .split(is_xml_whitespace)
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        Function::Translate => {
+            let from: Vec<char> = string(1)?.chars().collect();
+            let to: Vec<char> = string(2)?.chars().collect();
+            Value::String(
+                string(0)?
+                    .chars()
+                    .filter_map(|c| match from.iter().position(|&f| f == c) {
+                        Some(i) => to.get(i).copied(),
+                        None => Some(c),
+                    })
+                    .collect(),
+            )
+        }
+        Function::Boolean => Value::Boolean(value(0)?.boolean()),
+        Function::Not => Value::Boolean(!value(0)?.boolean()),
+        Function::True => Value::Boolean(true),
+        Function::False => Value::Boolean(false),
+        Function::Lang => {
+            let range = string(0)?;
+            Value::Boolean(
+                element_state::language(context.node.tree_node())
+                    .is_some_and(|lang| element_state::language_matches(&lang, &range)),
+            )
+        }
+        Function::Number => Value::Number(match arguments.first() {
+            Some(argument) => evaluate(argument, context)?.number(),
+            None => string_to_number(&context.node.string_value()),
+        }),
+        Function::Sum => Value::Number(
+            node_set(value(0)?)?
+                .iter()
+                .map(|node| string_to_number(&node.string_value()))
+                .sum(),
+        ),
+        Function::Floor => Value::Number(number(0)?.floor()),
+        Function::Ceiling => Value::Number(number(0)?.ceil()),
+        Function::Round => Value::Number(round(number(0)?)),
+    })
+}